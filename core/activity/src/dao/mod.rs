@@ -25,7 +25,8 @@ pub enum DaoError {
     JoinError(#[from] tokio::task::JoinError),
     #[error("R2D2 error: {0}")]
     R2D2Error(#[from] r2d2::Error),
-    #[error("Serde Json error: {0}")]
+    /// serde_json error display already contains line and column.
+    #[error("Serde Json {:?} error: {0}", .0.classify())]
     SerdeJsonError(#[from] serde_json::error::Error),
     #[error("Not found: {0}")]
     NotFound(String),
@@ -1,4 +1,4 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, HttpResponse};
 
 use ya_client_model::ErrorMessage;
 use ya_core_model::activity::RpcMessageError;
@@ -9,19 +9,38 @@ use crate::dao::DaoError;
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("DAO error: {0}")]
-    Dao(#[from] DaoError),
+    Dao(#[source] DaoError),
     #[error("GSB error: {0}")]
-    Gsb(#[from] ya_service_bus::Error),
+    Gsb(#[source] ya_service_bus::Error),
     #[error("Service error: {0}")]
     Service(String),
     #[error("Bad request: {0}")]
     BadRequest(String),
+    /// Client supplied body, that can't be deserialized. Failures of
+    /// our own (de)serialization are reported as `Dao` errors.
+    #[error("Invalid request payload: {0}")]
+    InvalidPayload(#[source] serde_json::Error),
     #[error("Not found: {0}")]
     NotFound(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
-    #[error("Timeout")]
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Too many requests. Retry after {retry_after_secs}s")]
+    TooManyRequests { retry_after_secs: u64 },
+    #[error("Request timeout")]
     Timeout,
+    #[error("GSB timeout: remote service didn't respond in time")]
+    GsbTimeout,
+}
+
+impl From<DaoError> for Error {
+    fn from(e: DaoError) -> Self {
+        match e {
+            DaoError::NotFound(msg) => Error::NotFound(msg),
+            e => Error::Dao(e),
+        }
+    }
 }
 
 impl From<ya_persistence::executor::Error> for Error {
@@ -36,6 +55,18 @@ impl From<Error> for actix_web::HttpResponse {
     }
 }
 
+impl From<ya_service_bus::Error> for Error {
+    fn from(e: ya_service_bus::Error) -> Self {
+        match e {
+            ya_service_bus::Error::Timeout(_) => Error::GsbTimeout,
+            ya_service_bus::Error::NoEndpoint(addr) => {
+                Error::NotFound(format!("GSB endpoint: {}", addr))
+            }
+            e => Error::Gsb(e),
+        }
+    }
+}
+
 impl From<tokio::time::Elapsed> for Error {
     fn from(_: tokio::time::Elapsed) -> Self {
         Error::Timeout
@@ -50,8 +81,13 @@ impl From<RpcMessageError> for Error {
             RpcMessageError::UsageLimitExceeded(msg) => Error::Service(msg),
             RpcMessageError::BadRequest(msg) => Error::BadRequest(msg),
             RpcMessageError::Forbidden(msg) => Error::Forbidden(msg),
+            RpcMessageError::Conflict(msg) => Error::Conflict(msg),
+            RpcMessageError::TooManyRequests(secs) => Error::TooManyRequests {
+                retry_after_secs: secs,
+            },
             RpcMessageError::NotFound(msg) => Error::NotFound(msg),
             RpcMessageError::Timeout => Error::Timeout,
+            RpcMessageError::GsbTimeout => Error::GsbTimeout,
         }
     }
 }
@@ -92,10 +128,38 @@ impl From<Error> for RpcMessageError {
         match e {
             Error::Service(msg) => RpcMessageError::Activity(msg),
             Error::BadRequest(msg) => RpcMessageError::BadRequest(msg),
+            Error::InvalidPayload(_) => RpcMessageError::BadRequest(e.to_string()),
             Error::NotFound(msg) => RpcMessageError::NotFound(msg),
             Error::Forbidden(msg) => RpcMessageError::Forbidden(msg),
+            Error::Conflict(msg) => RpcMessageError::Conflict(msg),
+            Error::TooManyRequests { retry_after_secs } => {
+                RpcMessageError::TooManyRequests(retry_after_secs)
+            }
             Error::Timeout => RpcMessageError::Timeout,
-            _ => RpcMessageError::Service(e.to_string()),
+            Error::GsbTimeout => RpcMessageError::GsbTimeout,
+            Error::Dao(_) | Error::Gsb(_) => {
+                RpcMessageError::Service(format!("{}: {}", e.category(), e))
+            }
+        }
+    }
+}
+
+impl Error {
+    /// Category of internal failure. It's used as message prefix of
+    /// `RpcMessageError::Service`, so remote side can tell failures apart.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::Dao(DaoError::SerdeJsonError(_)) => "serialization",
+            Error::Dao(DaoError::JoinError(_)) => "internal",
+            Error::Dao(_) => "db",
+            Error::Gsb(_) | Error::GsbTimeout => "gsb",
+            Error::Service(_) => "service",
+            Error::BadRequest(_) | Error::InvalidPayload(_) => "bad-request",
+            Error::NotFound(_) => "not-found",
+            Error::Forbidden(_) => "forbidden",
+            Error::Conflict(_) => "conflict",
+            Error::TooManyRequests { .. } => "too-many-requests",
+            Error::Timeout => "timeout",
         }
     }
 }
@@ -103,7 +167,7 @@ impl From<Error> for RpcMessageError {
 impl ResponseError for Error {
     fn error_response(&self) -> HttpResponse {
         match self {
-            Error::BadRequest(_) => {
+            Error::BadRequest(_) | Error::InvalidPayload(_) => {
                 HttpResponse::BadRequest().json(ErrorMessage::new(self.to_string()))
             }
             Error::NotFound(_) => {
@@ -112,14 +176,299 @@ impl ResponseError for Error {
             Error::Forbidden(_) => {
                 HttpResponse::Forbidden().json(ErrorMessage::new(self.to_string()))
             }
-            Error::Timeout => {
+            Error::Conflict(_) => {
+                HttpResponse::Conflict().json(ErrorMessage::new(self.to_string()))
+            }
+            Error::TooManyRequests { retry_after_secs } => HttpResponse::TooManyRequests()
+                .header(header::RETRY_AFTER, retry_after_secs.to_string())
+                .json(ErrorMessage::new(self.to_string())),
+            Error::Timeout | Error::GsbTimeout => {
                 HttpResponse::RequestTimeout().json(ErrorMessage::new(self.to_string()))
             }
             _ => {
                 let e = self.to_string();
                 log::error!("Activity API server error: {}", e);
+                for (depth, cause) in error_chain(self).iter().enumerate().skip(1) {
+                    log::error!("  caused by [{}]: {}", depth, cause);
+                }
                 HttpResponse::InternalServerError().json(ErrorMessage::new(e))
             }
         }
     }
 }
+
+/// Messages of `err` and all errors in its `source()` chain.
+fn error_chain(err: &dyn std::error::Error) -> Vec<String> {
+    let mut chain = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(cause) = source {
+        chain.push(cause.to_string());
+        source = cause.source();
+    }
+    chain
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::body::Body;
+    use actix_web::http::StatusCode;
+    use ya_client_model::activity::ExeScriptCommand;
+
+    #[test]
+    fn test_timeout_conversions() {
+        match RpcMessageError::from(Error::Timeout) {
+            RpcMessageError::Timeout => (),
+            e => panic!("Expected RpcMessageError::Timeout, got: {:?}", e),
+        }
+        match RpcMessageError::from(Error::GsbTimeout) {
+            RpcMessageError::GsbTimeout => (),
+            e => panic!("Expected RpcMessageError::GsbTimeout, got: {:?}", e),
+        }
+        match Error::from(RpcMessageError::Timeout) {
+            Error::Timeout => (),
+            e => panic!("Expected Error::Timeout, got: {:?}", e),
+        }
+        match Error::from(RpcMessageError::GsbTimeout) {
+            Error::GsbTimeout => (),
+            e => panic!("Expected Error::GsbTimeout, got: {:?}", e),
+        }
+        match Error::from(ya_service_bus::Error::Timeout("test".into())) {
+            Error::GsbTimeout => (),
+            e => panic!("Expected Error::GsbTimeout, got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_gsb_error_conversions() {
+        let err = Error::from(ya_service_bus::Error::NoEndpoint("/public/activity".into()));
+        match &err {
+            Error::NotFound(msg) => assert!(msg.contains("/public/activity")),
+            e => panic!("Expected Error::NotFound, got: {:?}", e),
+        }
+        assert_eq!(err.error_response().status(), StatusCode::NOT_FOUND);
+
+        let err = Error::from(ya_service_bus::Error::Timeout("/public/activity".into()));
+        match &err {
+            Error::GsbTimeout => (),
+            e => panic!("Expected Error::GsbTimeout, got: {:?}", e),
+        }
+        assert_eq!(err.error_response().status(), StatusCode::REQUEST_TIMEOUT);
+
+        let err = Error::from(ya_service_bus::Error::Closed("/public/activity".into()));
+        match &err {
+            Error::Gsb(ya_service_bus::Error::Closed(_)) => (),
+            e => panic!("Expected Error::Gsb, got: {:?}", e),
+        }
+        assert_eq!(
+            err.error_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_not_found_response() {
+        let activity_id = "a1b2c3d4e5f6";
+        let err = Error::from(DaoError::NotFound(format!(
+            "activity state: {}",
+            activity_id
+        )));
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                assert!(msg.message.unwrap().contains(activity_id));
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_conflict_conversions() {
+        match RpcMessageError::from(Error::Conflict("activity exists".into())) {
+            RpcMessageError::Conflict(msg) => assert_eq!(msg, "activity exists"),
+            e => panic!("Expected RpcMessageError::Conflict, got: {:?}", e),
+        }
+        match Error::from(RpcMessageError::Conflict("activity exists".into())) {
+            Error::Conflict(msg) => assert_eq!(msg, "activity exists"),
+            e => panic!("Expected Error::Conflict, got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_conflict_response() {
+        let resp = Error::Conflict("activity exists".into()).error_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                assert!(msg.message.unwrap().contains("activity exists"));
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_serialization_error_response() {
+        let err = serde_json::from_str::<serde_json::Value>("{\n  \"state\": [}").unwrap_err();
+        let resp = Error::from(DaoError::from(err)).error_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                let msg = msg.message.unwrap();
+                assert!(msg.contains("Syntax"), "{}", msg);
+                assert!(msg.contains("line 2 column"), "{}", msg);
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_internal_error_categories() {
+        let service_msg = |e: Error| match RpcMessageError::from(e) {
+            RpcMessageError::Service(msg) => msg,
+            e => panic!("Expected RpcMessageError::Service, got: {:?}", e),
+        };
+
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let msg = service_msg(Error::Dao(DaoError::from(err)));
+        assert!(msg.starts_with("serialization: "), "{}", msg);
+
+        let err = diesel::result::Error::RollbackTransaction;
+        let msg = service_msg(Error::Dao(DaoError::from(err)));
+        assert!(msg.starts_with("db: "), "{}", msg);
+
+        let msg = service_msg(Error::Gsb(ya_service_bus::Error::Closed("/local".into())));
+        assert!(msg.starts_with("gsb: "), "{}", msg);
+
+        match RpcMessageError::from(Error::Service("runtime failed".into())) {
+            RpcMessageError::Activity(msg) => assert_eq!(msg, "runtime failed"),
+            e => panic!("Expected RpcMessageError::Activity, got: {:?}", e),
+        }
+        match RpcMessageError::from(Error::BadRequest("bad".into())) {
+            RpcMessageError::BadRequest(msg) => assert_eq!(msg, "bad"),
+            e => panic!("Expected RpcMessageError::BadRequest, got: {:?}", e),
+        }
+        match RpcMessageError::from(Error::NotFound("activity".into())) {
+            RpcMessageError::NotFound(msg) => assert_eq!(msg, "activity"),
+            e => panic!("Expected RpcMessageError::NotFound, got: {:?}", e),
+        }
+        match RpcMessageError::from(Error::Forbidden("activity".into())) {
+            RpcMessageError::Forbidden(msg) => assert_eq!(msg, "activity"),
+            e => panic!("Expected RpcMessageError::Forbidden, got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_diesel_error_chain() {
+        let cause = diesel::result::Error::NotFound;
+        let err = Error::from(DaoError::from(diesel::result::Error::NotFound));
+
+        let chain = error_chain(&err);
+        assert_eq!(chain.len(), 3, "{:?}", chain);
+        assert_eq!(chain[0], err.to_string());
+        assert_eq!(chain[2], cause.to_string());
+
+        // Causes are only logged, body stays the same.
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                assert_eq!(msg.message.unwrap(), err.to_string());
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_invalid_payload_response() {
+        let err = serde_json::from_str::<Vec<ExeScriptCommand>>("[{\"deploy\": 1}").unwrap_err();
+        let resp = Error::InvalidPayload(err).error_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                assert!(msg.message.unwrap().starts_with("Invalid request payload"));
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        match RpcMessageError::from(Error::InvalidPayload(err)) {
+            RpcMessageError::BadRequest(msg) => assert!(msg.contains("EOF")),
+            e => panic!("Expected RpcMessageError::BadRequest, got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_too_many_requests_response() {
+        let resp = Error::TooManyRequests {
+            retry_after_secs: 30,
+        }
+        .error_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            resp.headers().get(header::RETRY_AFTER).unwrap(),
+            &header::HeaderValue::from_static("30")
+        );
+
+        match resp.body().as_ref() {
+            Some(Body::Bytes(bytes)) => {
+                let msg: ErrorMessage = serde_json::from_slice(bytes).unwrap();
+                assert!(msg.message.unwrap().contains("30s"));
+            }
+            body => panic!("Expected json body, got: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_too_many_requests_conversions() {
+        match RpcMessageError::from(Error::TooManyRequests {
+            retry_after_secs: 30,
+        }) {
+            RpcMessageError::TooManyRequests(secs) => assert_eq!(secs, 30),
+            e => panic!("Expected RpcMessageError::TooManyRequests, got: {:?}", e),
+        }
+        match Error::from(RpcMessageError::TooManyRequests(30)) {
+            Error::TooManyRequests { retry_after_secs } => assert_eq!(retry_after_secs, 30),
+            e => panic!("Expected Error::TooManyRequests, got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_timeout_responses() {
+        assert_eq!(
+            Error::Timeout.error_response().status(),
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_eq!(
+            Error::GsbTimeout.error_response().status(),
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_ne!(Error::Timeout.to_string(), Error::GsbTimeout.to_string());
+    }
+
+    #[actix_rt::test]
+    async fn test_elapsed_response() {
+        let elapsed = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            tokio::time::delay_for(std::time::Duration::from_secs(5)),
+        )
+        .await
+        .unwrap_err();
+
+        let err = Error::from(elapsed);
+        match &err {
+            Error::Timeout => (),
+            e => panic!("Expected Error::Timeout, got: {:?}", e),
+        }
+        assert_eq!(err.error_response().status(), StatusCode::REQUEST_TIMEOUT);
+    }
+}
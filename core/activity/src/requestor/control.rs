@@ -175,8 +175,15 @@ async fn exec(
 ) -> impl Responder {
     authorize_activity_initiator(&db, id.identity, &path.activity_id, Role::Requestor).await?;
 
+    if !get_persisted_state(&db, &path.activity_id).await?.alive() {
+        return Err(Error::Conflict(format!(
+            "Activity [{}] already terminated",
+            path.activity_id
+        )));
+    }
+
     let commands: Vec<ExeScriptCommand> =
-        serde_json::from_str(&body.text).map_err(|e| Error::BadRequest(format!("{:?}", e)))?;
+        serde_json::from_str(&body.text).map_err(Error::InvalidPayload)?;
     let agreement = get_activity_agreement(&db, &path.activity_id, Role::Requestor).await?;
     let batch_id = generate_id();
     let msg = activity::Exec {
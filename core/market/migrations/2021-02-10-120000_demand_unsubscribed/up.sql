@@ -0,0 +1,7 @@
+CREATE TABLE market_demand_unsubscribed (
+    id VARCHAR(97) NOT NULL PRIMARY KEY,
+    node_id VARCHAR(20) NOT NULL,
+
+    insertion_ts DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    expiration_ts DATETIME NOT NULL
+);
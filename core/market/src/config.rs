@@ -19,11 +19,45 @@ pub struct DiscoveryConfig {
 
 pub struct SubscriptionConfig {
     pub default_ttl: chrono::Duration,
+    /// How often expired Demands are unsubscribed.
+    pub expired_sweep_interval: Duration,
+    /// How often expired Proposals, that no one reacted to, are removed.
+    pub expired_proposals_sweep_interval: Duration,
+    /// Expired Demand can be resumed this long after expiration.
+    pub resume_grace: chrono::Duration,
+    /// Expired Demand is removed only after `resume_grace` and this margin
+    /// elapsed, so late resume attempts fail with `ResumeWindowElapsed`
+    /// instead of `NotFound`.
+    pub resume_removal_margin: chrono::Duration,
 }
 
 pub struct EventsConfig {
+    /// Number of events returned by query_events, when caller didn't specify it.
     pub max_events_default: i32,
+    /// Requests for more events are rejected with `InvalidMaxEvents`.
     pub max_events_max: i32,
+    pub proposals_order: ProposalsOrder,
+    /// Longer timeouts requested in query_events are clamped to this value.
+    pub max_timeout: Duration,
+}
+
+/// Order in which Proposal events are returned from query_events.
+/// Other events are always returned first, sorted by timestamp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProposalsOrder {
+    Random,
+    /// Cheapest Proposals first, compared by linear pricing model coefficient
+    /// of given usage counter, e.g. `golem.usage.duration_sec`. Coefficients
+    /// of different counters have different units, so they aren't summed.
+    /// Proposals without price for this counter are returned last.
+    PriceAscending {
+        usage_counter: &'static str,
+    },
+    /// Oldest Proposals first. Order is guaranteed per subscription: concurrent
+    /// query_events callers are served one by one, so each of them gets events
+    /// in the order they were generated and no caller gets event newer than
+    /// one left for later calls.
+    InsertionOrder,
 }
 
 impl Default for DiscoveryConfig {
@@ -41,6 +75,10 @@ impl Default for SubscriptionConfig {
     fn default() -> Self {
         SubscriptionConfig {
             default_ttl: chrono::Duration::seconds(50),
+            expired_sweep_interval: Duration::from_secs(60),
+            expired_proposals_sweep_interval: Duration::from_secs(60),
+            resume_grace: chrono::Duration::minutes(5),
+            resume_removal_margin: chrono::Duration::minutes(1),
         }
     }
 }
@@ -50,6 +88,8 @@ impl Default for EventsConfig {
         EventsConfig {
             max_events_default: 20,
             max_events_max: 100,
+            proposals_order: ProposalsOrder::Random,
+            max_timeout: Duration::from_secs(300),
         }
     }
 }
@@ -6,8 +6,10 @@ use ya_client::model::NodeId;
 use ya_persistence::executor::ConnType;
 use ya_persistence::executor::{do_with_transaction, readonly_transaction, AsDao, PoolType};
 
-use crate::db::model::{Demand, SubscriptionId};
+use crate::db::model::{Demand, DemandUnsubscribed, SubscriptionId};
 use crate::db::schema::market_demand::dsl;
+use crate::db::schema::market_demand_unsubscribed::dsl as unsubscribed;
+use crate::db::schema::market_demand_unsubscribed::dsl::market_demand_unsubscribed;
 use crate::db::{DbError, DbResult};
 
 #[allow(unused)]
@@ -25,6 +27,7 @@ impl<'c> AsDao<'c> for DemandDao<'c> {
 pub enum DemandState {
     Active(Demand),
     Expired(Option<Demand>),
+    Unsubscribed,
     NotFound,
 }
 
@@ -82,10 +85,14 @@ impl<'c> DemandDao<'c> {
         .await
     }
 
+    /// Removes Demand and leaves unsubscribe mark instead.
     pub async fn delete(&self, id: &SubscriptionId) -> DbResult<bool> {
         let id = id.clone();
 
         do_with_transaction(self.pool, move |conn| {
+            let demands: Vec<Demand> = dsl::market_demand.filter(dsl::id.eq(&id)).load(conn)?;
+            mark_unsubscribed(conn, demands)?;
+
             let num_deleted =
                 diesel::delete(dsl::market_demand.filter(dsl::id.eq(id))).execute(conn)?;
             Ok(num_deleted > 0)
@@ -93,6 +100,80 @@ impl<'c> DemandDao<'c> {
         .await
     }
 
+    /// Extends expiration of Demand owned by `node_id`. Already expired
+    /// Demands can't be refreshed.
+    pub async fn refresh(
+        &self,
+        id: &SubscriptionId,
+        node_id: &NodeId,
+        expiration_ts: NaiveDateTime,
+    ) -> DbResult<bool> {
+        let id = id.clone();
+        let node_id = node_id.clone();
+        let now = Utc::now().naive_utc();
+
+        do_with_transaction(self.pool, move |conn| {
+            let num_updated = diesel::update(
+                dsl::market_demand
+                    .filter(dsl::id.eq(id))
+                    .filter(dsl::node_id.eq(node_id))
+                    .filter(dsl::expiration_ts.ge(now)),
+            )
+            .set(dsl::expiration_ts.eq(expiration_ts))
+            .execute(conn)?;
+            Ok(num_updated > 0)
+        })
+        .await
+    }
+
+    /// The same as `refresh`, but Demands, that expired after `expired_after`,
+    /// can be extended as well.
+    pub async fn resume(
+        &self,
+        id: &SubscriptionId,
+        node_id: &NodeId,
+        expiration_ts: NaiveDateTime,
+        expired_after: NaiveDateTime,
+    ) -> DbResult<bool> {
+        let id = id.clone();
+        let node_id = node_id.clone();
+
+        do_with_transaction(self.pool, move |conn| {
+            let num_updated = diesel::update(
+                dsl::market_demand
+                    .filter(dsl::id.eq(id))
+                    .filter(dsl::node_id.eq(node_id))
+                    .filter(dsl::expiration_ts.ge(expired_after)),
+            )
+            .set(dsl::expiration_ts.eq(expiration_ts))
+            .execute(conn)?;
+            Ok(num_updated > 0)
+        })
+        .await
+    }
+
+    /// Removes Demands expired before `validation_ts` and returns their ids.
+    pub async fn delete_expired(
+        &self,
+        validation_ts: NaiveDateTime,
+    ) -> DbResult<Vec<SubscriptionId>> {
+        do_with_transaction(self.pool, move |conn| {
+            let expired: Vec<Demand> = dsl::market_demand
+                .filter(dsl::expiration_ts.lt(validation_ts))
+                .load(conn)?;
+            let expired_ids = expired
+                .iter()
+                .map(|demand| demand.id.clone())
+                .collect::<Vec<_>>();
+            mark_unsubscribed(conn, expired)?;
+
+            diesel::delete(dsl::market_demand.filter(dsl::id.eq_any(expired_ids.clone())))
+                .execute(conn)?;
+            Ok(expired_ids)
+        })
+        .await
+    }
+
     pub async fn clean(&self) -> DbResult<()> {
         log::debug!("Clean market demands: start");
         let num_deleted = do_with_transaction(self.pool, move |conn| {
@@ -104,10 +185,28 @@ impl<'c> DemandDao<'c> {
         if num_deleted > 0 {
             log::info!("Clean market demands: {} cleaned", num_deleted);
         }
+        self.clean_unsubscribes().await?;
         log::debug!("Clean market demands: done");
         Ok(())
     }
 
+    pub async fn clean_unsubscribes(&self) -> DbResult<()> {
+        log::debug!("Clean market demands unsubscribes: start");
+        let num_deleted = do_with_transaction(self.pool, move |conn| {
+            let nd = diesel::delete(
+                market_demand_unsubscribed.filter(unsubscribed::expiration_ts.lt(sql_now)),
+            )
+            .execute(conn)?;
+            Result::<usize, DbError>::Ok(nd)
+        })
+        .await?;
+        if num_deleted > 0 {
+            log::info!("Clean market demands unsubscribes: {} cleaned", num_deleted);
+        }
+        log::debug!("Clean market demands unsubscribes: done");
+        Ok(())
+    }
+
     pub async fn demand_state(self, id: &SubscriptionId) -> DbResult<DemandState> {
         let id = id.clone();
         do_with_transaction(self.pool, move |conn| demand_status(conn, &id)).await
@@ -125,6 +224,28 @@ pub(super) fn demand_status(conn: &ConnType, id: &SubscriptionId) -> DbResult<De
             true => Ok(DemandState::Active(demand)),
             false => Ok(DemandState::Expired(Some(demand))),
         },
-        None => Ok(DemandState::NotFound),
+        None => match is_unsubscribed(conn, id)? {
+            true => Ok(DemandState::Unsubscribed),
+            false => Ok(DemandState::NotFound),
+        },
     }
 }
+
+fn is_unsubscribed(conn: &ConnType, id: &SubscriptionId) -> DbResult<bool> {
+    Ok(market_demand_unsubscribed
+        .filter(unsubscribed::id.eq(&id))
+        .first::<DemandUnsubscribed>(conn)
+        .optional()?
+        .is_some())
+}
+
+fn mark_unsubscribed(conn: &ConnType, demands: Vec<Demand>) -> DbResult<()> {
+    let marks = demands
+        .into_iter()
+        .map(Demand::into_unsubscribe)
+        .collect::<Vec<_>>();
+    diesel::insert_into(market_demand_unsubscribed)
+        .values(&marks)
+        .execute(conn)?;
+    Ok(())
+}
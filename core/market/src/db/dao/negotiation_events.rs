@@ -1,17 +1,24 @@
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
 use diesel::dsl::sql;
 use diesel::{sql_types, ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use thiserror::Error;
 
 use ya_client::model::market::Reason;
 use ya_persistence::executor::ConnType;
 use ya_persistence::executor::{do_with_transaction, AsDao, PoolType};
 
+use crate::config::ProposalsOrder;
 use crate::db::dao::demand::{demand_status, DemandState};
 use crate::db::dao::offer::{query_state, OfferState};
 use crate::db::dao::sql_functions::datetime;
-use crate::db::model::{Agreement, EventType, MarketEvent, Owner, Proposal, SubscriptionId};
+use crate::db::model::{
+    Agreement, EventKind, EventType, MarketEvent, Owner, Proposal, ProposalId, SubscriptionId,
+};
 use crate::db::schema::market_negotiation_event::dsl;
+use crate::db::schema::market_proposal::dsl as dsl_proposal;
 use crate::db::{DbError, DbResult};
 use crate::market::EnvConfig;
 
@@ -23,14 +30,18 @@ const EVENT_STORE_DAYS: EnvConfig<'static, u64> = EnvConfig {
 
 #[derive(Error, Debug)]
 pub enum TakeEventsError {
-    #[error("Subscription [{0}] not found. Could be unsubscribed.")]
+    #[error("Subscription [{0}] not found.")]
     NotFound(SubscriptionId),
+    #[error("Subscription [{0}] was unsubscribed.")]
+    Unsubscribed(SubscriptionId),
     #[error("Subscription [{0}] expired.")]
     Expired(SubscriptionId),
     #[error("Failed to get events from DB: {0}.")]
     Db(DbError),
 }
 
+/// Events are kept in database until they are taken by `query_events`, so
+/// undelivered events survive yagna restart.
 pub struct NegotiationEventsDao<'c> {
     pool: &'c PoolType,
 }
@@ -84,11 +95,20 @@ impl<'c> NegotiationEventsDao<'c> {
         subscription_id: &SubscriptionId,
         max_events: i32,
         owner: Owner,
+        order: ProposalsOrder,
+        kinds: Option<Vec<EventKind>>,
     ) -> Result<Vec<MarketEvent>, TakeEventsError> {
         let subscription_id = subscription_id.clone();
+        let allowed = allowed_event_types(kinds);
+        let proposal_types = EventKind::Proposal
+            .event_types()
+            .into_iter()
+            .filter(|event_type| allowed.contains(event_type))
+            .collect::<Vec<_>>();
         do_with_transaction(self.pool, move |conn| {
             // Check subscription wasn't unsubscribed or expired.
             validate_subscription(conn, &subscription_id, owner)?;
+            remove_expired_proposal_events(conn, &subscription_id)?;
 
             // Only ProposalEvents should be in random order.
             //  AgreementEvent and rejections events should be sorted with higher
@@ -101,19 +121,30 @@ impl<'c> NegotiationEventsDao<'c> {
                     EventType::ProviderNewProposal,
                     EventType::RequestorNewProposal,
                 ]))
+                .filter(dsl::event_type.eq_any(allowed))
                 .order_by(dsl::timestamp.asc())
                 .limit(max_events as i64)
                 .load::<MarketEvent>(conn)?;
-            if (events.len() as i32) < max_events {
+            if (events.len() as i32) < max_events && !proposal_types.is_empty() {
                 let limit_left: i32 = max_events - (events.len() as i32);
-                let proposal_events = basic_query
-                    .filter(dsl::event_type.eq_any(vec![
-                        EventType::ProviderNewProposal,
-                        EventType::RequestorNewProposal,
-                    ]))
-                    .order_by(sql::<sql_types::Bool>("RANDOM()"))
-                    .limit(limit_left as i64)
-                    .load::<MarketEvent>(conn)?;
+                let proposal_query = basic_query.filter(dsl::event_type.eq_any(proposal_types));
+                let proposal_events = match order {
+                    ProposalsOrder::Random => proposal_query
+                        .order_by(sql::<sql_types::Bool>("RANDOM()"))
+                        .limit(limit_left as i64)
+                        .load::<MarketEvent>(conn)?,
+                    ProposalsOrder::InsertionOrder => proposal_query
+                        .order_by((dsl::timestamp.asc(), dsl::id.asc()))
+                        .limit(limit_left as i64)
+                        .load::<MarketEvent>(conn)?,
+                    ProposalsOrder::PriceAscending { usage_counter } => {
+                        // We must score all Proposals before applying limit.
+                        let proposal_events = proposal_query
+                            .order_by((dsl::timestamp.asc(), dsl::id.asc()))
+                            .load::<MarketEvent>(conn)?;
+                        cheapest_first(conn, proposal_events, usage_counter, limit_left as usize)?
+                    }
+                };
 
                 events.extend(proposal_events.into_iter());
             }
@@ -130,18 +161,112 @@ impl<'c> NegotiationEventsDao<'c> {
         .await
     }
 
-    pub async fn remove_events(&self, subscription_id: &SubscriptionId) -> DbResult<()> {
+    /// Cursor based alternative to `take_events`. Events not newer than `after`
+    /// are treated as acknowledged and removed from queue. Returned events stay
+    /// in queue until caller passes cursor past them, so no event is lost, if
+    /// caller crashes before processing them.
+    ///
+    /// Events are ordered by timestamp. Events with the same timestamp are never split
+    /// between batches, so the batch can exceed `max_events` in this case.
+    /// Cursor acknowledges only events of requested `kinds`.
+    pub async fn take_events_after(
+        &self,
+        subscription_id: &SubscriptionId,
+        max_events: i32,
+        owner: Owner,
+        after: NaiveDateTime,
+        kinds: Option<Vec<EventKind>>,
+    ) -> Result<Vec<MarketEvent>, TakeEventsError> {
         let subscription_id = subscription_id.clone();
+        let allowed = allowed_event_types(kinds);
         do_with_transaction(self.pool, move |conn| {
+            validate_subscription(conn, &subscription_id, owner)?;
+            remove_expired_proposal_events(conn, &subscription_id)?;
+
             diesel::delete(
-                dsl::market_negotiation_event.filter(dsl::subscription_id.eq(&subscription_id)),
+                dsl::market_negotiation_event
+                    .filter(dsl::subscription_id.eq(&subscription_id))
+                    .filter(dsl::event_type.eq_any(allowed.clone()))
+                    .filter(dsl::timestamp.le(after)),
             )
             .execute(conn)?;
+
+            let basic_query = dsl::market_negotiation_event
+                .filter(dsl::subscription_id.eq(&subscription_id))
+                .filter(dsl::event_type.eq_any(allowed))
+                .filter(dsl::timestamp.gt(after));
+            let mut events = basic_query
+                .clone()
+                .order_by((dsl::timestamp.asc(), dsl::id.asc()))
+                .limit(max_events as i64)
+                .load::<MarketEvent>(conn)?;
+
+            if let Some(last) = events.last().cloned() {
+                let same_timestamp = basic_query
+                    .filter(dsl::timestamp.eq(last.timestamp))
+                    .filter(dsl::id.gt(last.id))
+                    .order_by(dsl::id.asc())
+                    .load::<MarketEvent>(conn)?;
+                events.extend(same_timestamp.into_iter());
+            }
+            Ok(events)
+        })
+        .await
+    }
+
+    /// Puts back taken events, that couldn't be delivered. Original ids and
+    /// timestamps are kept, so events don't change their position in queue.
+    pub async fn restore_events(&self, events: Vec<MarketEvent>) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            for event in events {
+                diesel::insert_or_ignore_into(dsl::market_negotiation_event)
+                    .values((
+                        dsl::id.eq(event.id),
+                        dsl::subscription_id.eq(event.subscription_id),
+                        dsl::timestamp.eq(event.timestamp),
+                        dsl::event_type.eq(event.event_type),
+                        dsl::artifact_id.eq(event.artifact_id),
+                        dsl::reason.eq(event.reason),
+                    ))
+                    .execute(conn)?;
+            }
             Ok(())
         })
         .await
     }
 
+    /// Counts events waiting in queue without removing them.
+    pub async fn count_events(
+        &self,
+        subscription_id: &SubscriptionId,
+        owner: Owner,
+    ) -> Result<usize, TakeEventsError> {
+        let subscription_id = subscription_id.clone();
+        do_with_transaction(self.pool, move |conn| {
+            validate_subscription(conn, &subscription_id, owner)?;
+            remove_expired_proposal_events(conn, &subscription_id)?;
+
+            let count: i64 = dsl::market_negotiation_event
+                .filter(dsl::subscription_id.eq(&subscription_id))
+                .count()
+                .get_result(conn)?;
+            Ok(count as usize)
+        })
+        .await
+    }
+
+    /// Returns number of removed events.
+    pub async fn remove_events(&self, subscription_id: &SubscriptionId) -> DbResult<usize> {
+        let subscription_id = subscription_id.clone();
+        do_with_transaction(self.pool, move |conn| {
+            Ok(diesel::delete(
+                dsl::market_negotiation_event.filter(dsl::subscription_id.eq(&subscription_id)),
+            )
+            .execute(conn)?)
+        })
+        .await
+    }
+
     pub async fn clean(&self) -> DbResult<()> {
         log::debug!("Clean market events: start");
         let interval_days = EVENT_STORE_DAYS.get_value();
@@ -162,6 +287,14 @@ impl<'c> NegotiationEventsDao<'c> {
     }
 }
 
+fn allowed_event_types(kinds: Option<Vec<EventKind>>) -> Vec<EventType> {
+    kinds
+        .unwrap_or_else(EventKind::all)
+        .iter()
+        .flat_map(|kind| kind.event_types())
+        .collect()
+}
+
 fn validate_subscription(
     conn: &ConnType,
     subscription_id: &SubscriptionId,
@@ -171,16 +304,93 @@ fn validate_subscription(
         Owner::Requestor => match demand_status(conn, &subscription_id)? {
             DemandState::NotFound => Err(TakeEventsError::NotFound(subscription_id.clone()))?,
             DemandState::Expired(_) => Err(TakeEventsError::Expired(subscription_id.clone()))?,
+            DemandState::Unsubscribed => {
+                Err(TakeEventsError::Unsubscribed(subscription_id.clone()))?
+            }
             _ => Ok(()),
         },
         Owner::Provider => match query_state(conn, &subscription_id, &Utc::now().naive_utc())? {
             OfferState::NotFound => Err(TakeEventsError::NotFound(subscription_id.clone()))?,
             OfferState::Expired(_) => Err(TakeEventsError::Expired(subscription_id.clone()))?,
+            OfferState::Unsubscribed(_) => {
+                Err(TakeEventsError::Unsubscribed(subscription_id.clone()))?
+            }
             _ => Ok(()),
         },
     }
 }
 
+/// Proposals can't be countered after expiration, so there is no point in
+/// returning them. Background sweep removes them too, but not immediately.
+fn remove_expired_proposal_events(
+    conn: &ConnType,
+    subscription_id: &SubscriptionId,
+) -> DbResult<usize> {
+    let expired = dsl_proposal::market_proposal
+        .filter(dsl_proposal::expiration_ts.le(Utc::now().naive_utc()))
+        .select(dsl_proposal::id);
+    Ok(diesel::delete(
+        dsl::market_negotiation_event
+            .filter(dsl::subscription_id.eq(subscription_id))
+            .filter(dsl::event_type.eq_any(EventKind::Proposal.event_types()))
+            .filter(dsl::artifact_id.eq_any(expired)),
+    )
+    .execute(conn)?)
+}
+
+/// Sorting is stable, so Proposals with equal price stay in timestamp order.
+fn cheapest_first(
+    conn: &ConnType,
+    events: Vec<MarketEvent>,
+    usage_counter: &str,
+    limit: usize,
+) -> DbResult<Vec<MarketEvent>> {
+    let ids = events
+        .iter()
+        .map(|event| event.artifact_id.clone())
+        .collect::<Vec<_>>();
+    let prices = dsl_proposal::market_proposal
+        .filter(dsl_proposal::id.eq_any(ids))
+        .select((dsl_proposal::id, dsl_proposal::properties))
+        .load::<(ProposalId, String)>(conn)?
+        .into_iter()
+        .filter_map(|(id, properties)| {
+            unit_price(&properties, usage_counter).map(|price| (id, price))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut events = events;
+    events.sort_by(|left, right| {
+        match (
+            prices.get(&left.artifact_id),
+            prices.get(&right.artifact_id),
+        ) {
+            (Some(left), Some(right)) => left.partial_cmp(right).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
+    events.truncate(limit);
+    Ok(events)
+}
+
+/// Coefficient of linear pricing model for `usage_counter` from flattened
+/// properties. Coefficients are in the same order as counters in usage vector.
+fn unit_price(properties: &str, usage_counter: &str) -> Option<f64> {
+    let properties: Value = serde_json::from_str(properties).ok()?;
+    let position = properties
+        .get("golem.com.usage.vector")?
+        .as_array()?
+        .iter()
+        .position(|counter| counter.as_str() == Some(usage_counter))?;
+    properties
+        .get("golem.com.pricing.model.linear.coeffs")?
+        .as_array()?
+        .get(position)?
+        .as_f64()
+}
+
 impl<ErrorType: Into<DbError>> From<ErrorType> for TakeEventsError {
     fn from(err: ErrorType) -> Self {
         TakeEventsError::Db(err.into())
@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use diesel::expression::dsl::now as sql_now;
 use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 use serde::{Deserialize, Serialize};
@@ -6,8 +7,13 @@ use ya_persistence::executor::{
     do_with_transaction, readonly_transaction, AsDao, ConnType, PoolType,
 };
 
-use crate::db::model::{DbProposal, Negotiation, Proposal, ProposalId, ProposalState};
+use crate::db::model::{
+    DbProposal, EventKind, Negotiation, Proposal, ProposalId, ProposalState, SubscriptionId,
+};
+use crate::db::schema::market_negotiation;
 use crate::db::schema::market_negotiation::dsl as dsl_negotiation;
+use crate::db::schema::market_negotiation_event::dsl as dsl_event;
+use crate::db::schema::market_offer::dsl as dsl_offer;
 use crate::db::schema::market_proposal::dsl;
 use crate::db::{DbError, DbResult};
 
@@ -119,6 +125,126 @@ impl<'c> ProposalDao<'c> {
         .await
     }
 
+    /// Finds Negotiations for the same subscription and Provider, that still have
+    /// not expired initial Proposal with the same content as `proposal`.
+    /// Negotiations, that ended with Agreement, are skipped.
+    pub async fn find_active_duplicates(&self, proposal: &Proposal) -> DbResult<Vec<Negotiation>> {
+        let subscription_id = proposal.negotiation.subscription_id.clone();
+        let provider_id = proposal.negotiation.provider_id.clone();
+        let properties = proposal.body.properties.clone();
+        let constraints = proposal.body.constraints.clone();
+
+        readonly_transaction(self.pool, move |conn| {
+            // diesel forbids the same table appearing more than once in a query
+            // so we check accepted Proposals in separate step.
+            let candidates: Vec<Negotiation> = dsl_negotiation::market_negotiation
+                .inner_join(dsl::market_proposal)
+                .filter(dsl_negotiation::subscription_id.eq(&subscription_id))
+                .filter(dsl_negotiation::provider_id.eq(&provider_id))
+                .filter(dsl::prev_proposal_id.is_null())
+                .filter(dsl::properties.eq(&properties))
+                .filter(dsl::constraints.eq(&constraints))
+                .filter(dsl::state.eq_any(vec![ProposalState::Initial, ProposalState::Draft]))
+                .filter(dsl::expiration_ts.gt(sql_now))
+                .select(market_negotiation::all_columns)
+                .load(conn)?;
+
+            if candidates.is_empty() {
+                return Ok(candidates);
+            }
+
+            let candidate_ids: Vec<String> = candidates.iter().map(|n| n.id.clone()).collect();
+            let accepted: Vec<String> = dsl::market_proposal
+                .filter(dsl::negotiation_id.eq_any(candidate_ids))
+                .filter(dsl::state.eq(ProposalState::Accepted))
+                .select(dsl::negotiation_id)
+                .load(conn)?;
+
+            Ok(candidates
+                .into_iter()
+                .filter(|negotiation| !accepted.contains(&negotiation.id))
+                .collect())
+        })
+        .await
+    }
+
+    /// Initial Proposals expire together with Demand, so they have to be
+    /// extended, when Demand is resumed. They still can't outlive their Offers.
+    /// Returns number of extended Proposals.
+    pub async fn extend_for_demand(
+        &self,
+        demand_id: &SubscriptionId,
+        demand_expiration_ts: NaiveDateTime,
+    ) -> DbResult<usize> {
+        let demand_id = demand_id.clone();
+        do_with_transaction(self.pool, move |conn| {
+            let proposals: Vec<(ProposalId, SubscriptionId)> = dsl::market_proposal
+                .inner_join(market_negotiation::table)
+                .filter(dsl_negotiation::demand_id.eq(&demand_id))
+                .filter(dsl::state.eq(ProposalState::Initial))
+                .select((dsl::id, dsl_negotiation::offer_id))
+                .load(conn)?;
+
+            let mut num_extended = 0;
+            for (proposal_id, offer_id) in proposals {
+                let offer_expiration_ts: Option<NaiveDateTime> = dsl_offer::market_offer
+                    .filter(dsl_offer::id.eq(offer_id))
+                    .select(dsl_offer::expiration_ts)
+                    .first(conn)
+                    .optional()?;
+                // Offer is already gone, so is its Proposal.
+                let offer_expiration_ts = match offer_expiration_ts {
+                    Some(expiration_ts) => expiration_ts,
+                    None => continue,
+                };
+                num_extended +=
+                    diesel::update(dsl::market_proposal.filter(dsl::id.eq(proposal_id)))
+                        .set(
+                            dsl::expiration_ts
+                                .eq(std::cmp::min(offer_expiration_ts, demand_expiration_ts)),
+                        )
+                        .execute(conn)?;
+            }
+            Ok(num_extended)
+        })
+        .await
+    }
+
+    /// Removes initial Proposals, that expired before `now` without being
+    /// countered, together with their events. Returns number of removed events.
+    pub async fn prune_expired(&self, now: NaiveDateTime) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let expired: Vec<ProposalId> = dsl::market_proposal
+                .filter(dsl::state.eq(ProposalState::Initial))
+                .filter(dsl::expiration_ts.le(now))
+                .select(dsl::id)
+                .load(conn)?;
+            if expired.is_empty() {
+                return Ok(0);
+            }
+
+            // diesel forbids the same table appearing more than once in a query.
+            let countered: Vec<Option<ProposalId>> = dsl::market_proposal
+                .filter(dsl::prev_proposal_id.eq_any(expired.clone()))
+                .select(dsl::prev_proposal_id)
+                .load(conn)?;
+            let expired = expired
+                .into_iter()
+                .filter(|id| !countered.contains(&Some(id.clone())))
+                .collect::<Vec<_>>();
+
+            let num_events = diesel::delete(
+                dsl_event::market_negotiation_event
+                    .filter(dsl_event::event_type.eq_any(EventKind::Proposal.event_types()))
+                    .filter(dsl_event::artifact_id.eq_any(expired.clone())),
+            )
+            .execute(conn)?;
+            diesel::delete(dsl::market_proposal.filter(dsl::id.eq_any(expired))).execute(conn)?;
+            Ok(num_events)
+        })
+        .await
+    }
+
     pub async fn clean(&self) -> DbResult<()> {
         // FIXME clean negotiations also
         log::debug!("Clean market proposals: start");
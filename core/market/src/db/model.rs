@@ -9,8 +9,8 @@ mod subscription_id;
 
 pub use agreement::{check_transition, Agreement, AgreementId, AgreementState, AppSessionId};
 pub use agreement_events::{AgreementEvent, AgreementEventType, NewAgreementEvent};
-pub use demand::Demand;
-pub use negotiation_events::{EventError, EventType, MarketEvent};
+pub use demand::{Demand, DemandUnsubscribed};
+pub use negotiation_events::{EventError, EventKind, EventType, MarketEvent};
 pub use offer::{Offer, OfferUnsubscribed};
 pub use proposal::{DbProposal, Issuer, Negotiation, Proposal, ProposalState};
 
@@ -5,7 +5,7 @@ use ya_client::model::{market::Demand as ClientDemand, ErrorMessage, NodeId};
 use ya_service_api_web::middleware::Identity;
 
 use super::SubscriptionId;
-use crate::db::schema::market_demand;
+use crate::db::schema::{market_demand, market_demand_unsubscribed};
 use ya_client::model::market::NewDemand;
 
 #[derive(Clone, Debug, Identifiable, Insertable, Queryable)]
@@ -24,6 +24,21 @@ pub struct Demand {
     pub expiration_ts: NaiveDateTime,
 }
 
+/// Keeps track of Demands, that were unsubscribed, so we can tell them apart
+/// from subscription ids, that never existed.
+/// Entry is needed only until Demand expiration time.
+#[derive(Clone, Debug, Identifiable, Insertable, Queryable)]
+#[table_name = "market_demand_unsubscribed"]
+pub struct DemandUnsubscribed {
+    pub id: SubscriptionId,
+    pub node_id: NodeId,
+
+    /// Timestamp of adding unsubscribe mark to database.
+    pub insertion_ts: Option<NaiveDateTime>,
+    /// Time when unsubscribed Demand expires; set by Requestor.
+    pub expiration_ts: NaiveDateTime,
+}
+
 impl Demand {
     /// Creates new model demand. If ClientDemand has id already assigned,
     /// it will be ignored and regenerated.
@@ -58,6 +73,15 @@ impl Demand {
         })
     }
 
+    pub fn into_unsubscribe(self) -> DemandUnsubscribed {
+        DemandUnsubscribed {
+            id: self.id,
+            node_id: self.node_id,
+            insertion_ts: None,
+            expiration_ts: self.expiration_ts,
+        }
+    }
+
     pub fn into_client_demand(&self) -> Result<ClientDemand, ErrorMessage> {
         Ok(ClientDemand {
             demand_id: self.id.to_string(),
@@ -55,6 +55,46 @@ pub enum EventType {
     RequestorPropertyQuery,
 }
 
+/// Kind of event independent of the side, that receives it.
+/// Used to filter events returned by query_events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Proposal,
+    ProposalRejected,
+    Agreement,
+    PropertyQuery,
+}
+
+impl EventKind {
+    pub fn all() -> Vec<EventKind> {
+        vec![
+            EventKind::Proposal,
+            EventKind::ProposalRejected,
+            EventKind::Agreement,
+            EventKind::PropertyQuery,
+        ]
+    }
+
+    /// Database event types of this kind for both Provider and Requestor.
+    pub fn event_types(&self) -> Vec<EventType> {
+        match self {
+            EventKind::Proposal => vec![
+                EventType::ProviderNewProposal,
+                EventType::RequestorNewProposal,
+            ],
+            EventKind::ProposalRejected => vec![
+                EventType::ProviderProposalRejected,
+                EventType::RequestorProposalRejected,
+            ],
+            EventKind::Agreement => vec![EventType::ProviderAgreement],
+            EventKind::PropertyQuery => vec![
+                EventType::ProviderPropertyQuery,
+                EventType::RequestorPropertyQuery,
+            ],
+        }
+    }
+}
+
 #[derive(Clone, Debug, Queryable)]
 pub struct MarketEvent {
     pub id: i32,
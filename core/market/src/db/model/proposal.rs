@@ -128,8 +128,8 @@ impl Proposal {
     pub fn new_requestor(demand: ModelDemand, offer: ModelOffer) -> Proposal {
         let negotiation = Negotiation::from_subscriptions(&demand, &offer, Owner::Requestor);
         let creation_ts = Utc::now().naive_utc();
-        // TODO: How to set expiration? Config?
-        let expiration_ts = creation_ts + Duration::minutes(10);
+        // Proposal is valid as long as both subscriptions it was matched from.
+        let expiration_ts = std::cmp::min(offer.expiration_ts, demand.expiration_ts);
         let proposal_id =
             ProposalId::generate_id(&offer.id, &demand.id, &creation_ts, Owner::Requestor);
 
@@ -261,6 +261,9 @@ impl Proposal {
         })
     }
 
+    /// Node, that created this Proposal. For initial Proposals on Requestor side
+    /// this is the Provider, who subscribed the Offer. Exposed to clients
+    /// as `issuer_id`, so they can filter counterparties without parsing properties.
     pub fn issuer(&self) -> NodeId {
         match self.body.issuer {
             Issuer::Us => match self.body.id.owner() {
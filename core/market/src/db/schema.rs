@@ -11,6 +11,16 @@ table! {
     }
 }
 
+table! {
+    market_demand_unsubscribed (id) {
+        id -> Text,
+        node_id -> Text,
+
+        insertion_ts -> Nullable<Timestamp>,
+        expiration_ts -> Timestamp,
+    }
+}
+
 table! {
     market_offer (id) {
         id -> Text,
@@ -121,7 +131,12 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(market_demand, market_offer, market_offer_unsubscribed);
+allow_tables_to_appear_in_same_query!(
+    market_demand,
+    market_demand_unsubscribed,
+    market_offer,
+    market_offer_unsubscribed
+);
 allow_tables_to_appear_in_same_query!(market_proposal, market_negotiation);
 allow_tables_to_appear_in_same_query!(market_agreement, market_agreement_event);
 
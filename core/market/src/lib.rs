@@ -14,4 +14,5 @@ mod utils;
 #[cfg(feature = "testing")]
 pub mod testing;
 
-pub use market::MarketService;
+pub use market::{MarketService, SubscriptionInfo};
+pub use negotiation::{MarketStats, ProposalScorer, ScoredEvent};
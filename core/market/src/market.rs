@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use futures::future;
 use lazy_static::lazy_static;
 use metrics::counter;
 use std::sync::{Arc, Mutex};
@@ -6,7 +7,7 @@ use thiserror::Error;
 
 use crate::config::Config;
 use crate::db::dao::AgreementDao;
-use crate::db::model::{AgreementId, AppSessionId, SubscriptionId};
+use crate::db::model::{AgreementId, AppSessionId, ProposalId, SubscriptionId};
 use crate::identity::{IdentityApi, IdentityGSB};
 use crate::matcher::error::{
     DemandError, MatcherError, MatcherInitError, QueryDemandsError, QueryOfferError,
@@ -14,15 +15,18 @@ use crate::matcher::error::{
 };
 use crate::matcher::{store::SubscriptionStore, Matcher};
 use crate::negotiation::error::{
-    AgreementError, AgreementEventsError, NegotiationError, NegotiationInitError,
+    AgreementError, AgreementEventsError, NegotiationError, NegotiationInitError, QueryEventsError,
+};
+use crate::negotiation::{
+    EventNotifier, MarketStats, ProposalScorer, ProviderBroker, RequestorBroker,
 };
-use crate::negotiation::{EventNotifier, ProviderBroker, RequestorBroker};
 use crate::rest_api;
 
 use ya_client::model::market::{
     Agreement, AgreementOperationEvent as ClientAgreementEvent, Demand, NewDemand, NewOffer, Offer,
     Reason,
 };
+use ya_client::model::NodeId;
 use ya_core_model::market::{local, BUS_ID};
 use ya_persistence::executor::DbExecutor;
 use ya_service_api_interfaces::{Provider, Service};
@@ -73,6 +77,24 @@ pub enum MarketInitError {
     Migration(#[from] anyhow::Error),
 }
 
+/// Metadata of Demand or Offer subscription.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionInfo {
+    pub subscription_id: SubscriptionId,
+    /// Identity, that owns subscription.
+    pub node_id: NodeId,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Events waiting to be taken by `query_events`.
+    pub pending_events: usize,
+}
+
+impl SubscriptionInfo {
+    pub fn ttl(&self) -> chrono::Duration {
+        self.expires_at - self.created_at
+    }
+}
+
 /// Structure connecting all market objects.
 pub struct MarketService {
     pub db: DbExecutor,
@@ -217,6 +239,25 @@ impl MarketService {
         demand: &NewDemand,
         id: &Identity,
     ) -> Result<SubscriptionId, MarketError> {
+        self.subscribe_demand_with(demand, id, None).await
+    }
+
+    /// With `resume` set, Demand with this id is subscribed again instead of
+    /// creating new one, so its pending events and Proposals are kept. Demand
+    /// can be resumed only within `resume_grace` after it expired.
+    pub async fn subscribe_demand_with(
+        &self,
+        demand: &NewDemand,
+        id: &Identity,
+        resume: Option<&SubscriptionId>,
+    ) -> Result<SubscriptionId, MarketError> {
+        if let Some(demand_id) = resume {
+            let demand = self.matcher.resume_demand(demand_id, demand, id).await?;
+
+            counter!("market.demands.resumed", 1);
+            return Ok(demand.id);
+        }
+
         let demand = self.matcher.subscribe_demand(demand, id).await?;
         self.requestor_engine.subscribe_demand(&demand).await?;
 
@@ -239,6 +280,149 @@ impl MarketService {
         Ok(())
     }
 
+    /// Sets scorer used to rank Offers matching Requestor's Demands.
+    /// By default Proposals aren't scored.
+    pub fn set_proposal_scorer(&self, scorer: Option<Arc<dyn ProposalScorer>>) {
+        self.requestor_engine.set_proposal_scorer(scorer);
+    }
+
+    /// Negotiation counters of both Provider and Requestor side.
+    pub fn market_stats(&self) -> MarketStats {
+        let provider = self.provider_engine.stats();
+        let requestor = self.requestor_engine.stats();
+        MarketStats {
+            proposals_generated: provider.proposals_generated + requestor.proposals_generated,
+            events_delivered: provider.events_delivered + requestor.events_delivered,
+            events_expired: provider.events_expired + requestor.events_expired,
+            proposals_pruned: provider.proposals_pruned + requestor.proposals_pruned,
+        }
+    }
+
+    /// Extends Demand expiration by default subscription TTL counted from now.
+    pub async fn refresh_demand(
+        &self,
+        demand_id: &SubscriptionId,
+        id: &Identity,
+    ) -> Result<(), MarketError> {
+        self.matcher.store.refresh_demand(demand_id, id).await?;
+        Ok(())
+    }
+
+    /// Unsubscribes many Demands at once. Failure of one Demand doesn't
+    /// stop others from being unsubscribed, so result is returned per id,
+    /// in the same order as `demand_ids`.
+    pub async fn unsubscribe_demands(
+        &self,
+        demand_ids: &[SubscriptionId],
+        id: &Identity,
+    ) -> Vec<(SubscriptionId, Result<(), MarketError>)> {
+        let results = future::join_all(
+            demand_ids
+                .iter()
+                .map(|demand_id| self.unsubscribe_demand(demand_id, id)),
+        )
+        .await;
+        demand_ids
+            .iter()
+            .cloned()
+            .zip(results.into_iter())
+            .collect()
+    }
+
+    /// Unsubscribes all active Demands owned by given Identity.
+    /// Failure of one Demand doesn't stop others from being unsubscribed.
+    /// Demands removed in the meantime (unsubscribed concurrently or expired)
+    /// are skipped. Returns ids of Demands unsubscribed by this call.
+    pub async fn unsubscribe_all(&self, id: &Identity) -> Result<Vec<SubscriptionId>, MarketError> {
+        let demand_ids = self
+            .matcher
+            .store
+            .get_active_demand_ids(id.identity)
+            .await?;
+
+        let mut unsubscribed = vec![];
+        for (demand_id, result) in self.unsubscribe_demands(&demand_ids, id).await {
+            match result {
+                Ok(()) => unsubscribed.push(demand_id),
+                Err(MarketError::Matcher(MatcherError::Demand(DemandError::NotFound(_)))) => {
+                    log::debug!("Demand [{}] already removed, skipping.", demand_id)
+                }
+                Err(e) => log::warn!("Failed to unsubscribe Demand [{}]. Error: {}", demand_id, e),
+            }
+        }
+        Ok(unsubscribed)
+    }
+
+    /// Subscription id doesn't tell us, whether it is Demand or Offer, so both
+    /// are checked. Fails with `Unsubscribed` or `Expired`, when subscription
+    /// is no longer active.
+    pub async fn get_subscription_info(
+        &self,
+        subscription_id: &SubscriptionId,
+    ) -> Result<SubscriptionInfo, QueryEventsError> {
+        let (node_id, creation_ts, expiration_ts, pending_events) = match self
+            .requestor_engine
+            .count_pending_events_unchecked(subscription_id)
+            .await
+        {
+            Ok(pending_events) => {
+                let demand = self
+                    .matcher
+                    .store
+                    .get_demand(subscription_id)
+                    .await
+                    .map_err(|e| QueryEventsError::Internal(e.to_string()))?;
+                (
+                    demand.node_id,
+                    demand.creation_ts,
+                    demand.expiration_ts,
+                    pending_events,
+                )
+            }
+            Err(QueryEventsError::UnknownSubscription(_)) => {
+                let pending_events = self
+                    .provider_engine
+                    .count_pending_events_unchecked(subscription_id)
+                    .await?;
+                let offer = self
+                    .matcher
+                    .store
+                    .get_offer(subscription_id)
+                    .await
+                    .map_err(|e| QueryEventsError::Internal(e.to_string()))?;
+                (
+                    offer.node_id,
+                    offer.creation_ts,
+                    offer.expiration_ts,
+                    pending_events,
+                )
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(SubscriptionInfo {
+            subscription_id: subscription_id.clone(),
+            node_id,
+            created_at: DateTime::from_utc(creation_ts, Utc),
+            expires_at: DateTime::from_utc(expiration_ts, Utc),
+            pending_events,
+        })
+    }
+
+    /// Creates Agreement in `Proposal` state from Provider's Proposal, that
+    /// was already negotiated. Provider gets Agreement event, when Agreement
+    /// is confirmed.
+    pub async fn create_agreement(
+        &self,
+        id: Identity,
+        proposal_id: &ProposalId,
+        valid_to: DateTime<Utc>,
+    ) -> Result<AgreementId, AgreementError> {
+        self.requestor_engine
+            .create_agreement(id, proposal_id, valid_to)
+            .await
+    }
+
     pub async fn get_agreement(
         &self,
         agreement_id: &AgreementId,
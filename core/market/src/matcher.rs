@@ -240,6 +240,25 @@ impl Matcher {
         Ok(demand)
     }
 
+    /// Resumed Demand isn't passed to resolver again, because Proposals
+    /// matching it were already generated.
+    pub async fn resume_demand(
+        &self,
+        demand_id: &SubscriptionId,
+        demand: &NewDemand,
+        id: &Identity,
+    ) -> Result<Demand, MatcherError> {
+        let demand = self.store.resume_demand(demand_id, demand, id).await?;
+
+        log::info!(
+            "Resumed Demand: [{}] using identity: {} [{}]",
+            &demand.id,
+            id.name,
+            id.identity
+        );
+        Ok(demand)
+    }
+
     pub async fn unsubscribe_demand(
         &self,
         demand_id: &SubscriptionId,
@@ -15,6 +15,10 @@ pub enum DemandError {
     Remove(DbError, SubscriptionId),
     #[error("Demand [{0}] not found.")]
     NotFound(SubscriptionId),
+    #[error("Demand [{0}] expired too long ago to be resumed.")]
+    ResumeWindowElapsed(SubscriptionId),
+    #[error("Demand [{0}] can't be resumed with different properties or constraints.")]
+    ResumeMismatch(SubscriptionId),
     #[error(transparent)]
     JsonObjectExpected(#[from] serde_json::error::Error),
 }
@@ -274,6 +274,21 @@ impl SubscriptionStore {
             .collect())
     }
 
+    pub async fn get_active_demand_ids(
+        &self,
+        node_id: NodeId,
+    ) -> Result<Vec<SubscriptionId>, DemandError> {
+        Ok(self
+            .db
+            .as_dao::<DemandDao>()
+            .get_demands(Some(node_id), None, Utc::now().naive_utc())
+            .await
+            .map_err(|e| DemandError::GetMany(e))?
+            .into_iter()
+            .map(|demand| demand.id)
+            .collect())
+    }
+
     pub async fn get_demands_before(
         &self,
         insertion_ts: NaiveDateTime,
@@ -286,6 +301,88 @@ impl SubscriptionStore {
             .map_err(|e| DemandError::GetMany(e))?)
     }
 
+    pub async fn refresh_demand(
+        &self,
+        demand_id: &SubscriptionId,
+        id: &Identity,
+    ) -> Result<Demand, DemandError> {
+        let expiration_ts = Utc::now().naive_utc() + self.config.subscription.default_ttl;
+        match self
+            .db
+            .as_dao::<DemandDao>()
+            .refresh(demand_id, &id.identity, expiration_ts)
+            .await
+            .map_err(|e| DemandError::Save(e))?
+        {
+            true => self.get_demand(demand_id).await,
+            false => Err(DemandError::NotFound(demand_id.clone())),
+        }
+    }
+
+    /// Extends expiration of Demand, that is still active or expired less than
+    /// `resume_grace` ago. `demand` must be the same, as the one used to
+    /// subscribe, otherwise it would be a different Demand.
+    ///
+    /// Expired Demands aren't removed before `resume_grace` elapses, so they can
+    /// still be resumed shortly after expiration.
+    pub async fn resume_demand(
+        &self,
+        demand_id: &SubscriptionId,
+        demand: &NewDemand,
+        id: &Identity,
+    ) -> Result<Demand, DemandError> {
+        let now = Utc::now().naive_utc();
+        let expiration_ts = now + self.config.subscription.default_ttl;
+        let expired_after = now - self.config.subscription.resume_grace;
+        let requested = Demand::from_new(demand, &id, now, expiration_ts)?;
+
+        let stored = match self
+            .db
+            .as_dao::<DemandDao>()
+            .demand_state(demand_id)
+            .await
+            .map_err(|e| DemandError::GetSingle(e, demand_id.clone()))?
+        {
+            DemandState::Active(demand) | DemandState::Expired(Some(demand)) => demand,
+            _ => return Err(DemandError::NotFound(demand_id.clone())),
+        };
+        if stored.node_id != requested.node_id
+            || stored.properties != requested.properties
+            || stored.constraints != requested.constraints
+        {
+            return Err(DemandError::ResumeMismatch(demand_id.clone()));
+        }
+
+        match self
+            .db
+            .as_dao::<DemandDao>()
+            .resume(demand_id, &id.identity, expiration_ts, expired_after)
+            .await
+            .map_err(|e| DemandError::Save(e))?
+        {
+            true => (),
+            false => return Err(DemandError::ResumeWindowElapsed(demand_id.clone())),
+        }
+
+        self.db
+            .as_dao::<ProposalDao>()
+            .extend_for_demand(demand_id, expiration_ts)
+            .await
+            .map_err(|e| DemandError::Save(e))?;
+        self.get_demand(demand_id).await
+    }
+
+    pub async fn remove_expired_demands(&self) -> Result<Vec<SubscriptionId>, DemandError> {
+        let validation_ts = Utc::now().naive_utc()
+            - self.config.subscription.resume_grace
+            - self.config.subscription.resume_removal_margin;
+        self.db
+            .as_dao::<DemandDao>()
+            .delete_expired(validation_ts)
+            .await
+            .map_err(|e| DemandError::GetMany(e))
+    }
+
     pub async fn remove_demand(
         &self,
         demand_id: &SubscriptionId,
@@ -3,7 +3,10 @@ pub mod error;
 mod notifier;
 mod provider;
 mod requestor;
+mod scorer;
 
+pub use common::MarketStats;
 pub use notifier::EventNotifier;
 pub use provider::{ApprovalResult, ProviderBroker};
-pub use requestor::{ApprovalStatus, RequestorBroker};
+pub use requestor::{ApprovalStatus, RequestorBroker, ScoredEvent};
+pub use scorer::ProposalScorer;
@@ -1,6 +1,8 @@
-use chrono::{DateTime, Utc};
-use metrics::counter;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::channel::oneshot;
+use metrics::{counter, timing, value};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -18,9 +20,10 @@ use crate::db::{
         TakeEventsError,
     },
     model::{
-        Agreement, AgreementEvent, AgreementId, AgreementState, AppSessionId, MarketEvent, Owner,
-        Proposal, ProposalId, ProposalState, SubscriptionId,
+        Agreement, AgreementEvent, AgreementId, AgreementState, AppSessionId, EventKind,
+        MarketEvent, Owner, Proposal, ProposalId, ProposalState, SubscriptionId,
     },
+    DbResult,
 };
 use crate::matcher::{store::SubscriptionStore, RawProposal};
 use crate::negotiation::error::RegenerateProposalError;
@@ -31,6 +34,7 @@ use crate::negotiation::{
         ProposalError, QueryEventsError,
     },
     notifier::NotifierError,
+    scorer::ProposalScores,
     EventNotifier,
 };
 use crate::protocol::negotiation::error::{CallerParseError, RejectProposalError};
@@ -47,6 +51,29 @@ use crate::utils::AgreementLock;
 
 type IsFirst = bool;
 
+/// Negotiation throughput counters since service start.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketStats {
+    /// Proposals generated by matcher.
+    pub proposals_generated: u64,
+    /// Events returned by query_events.
+    pub events_delivered: u64,
+    /// Events removed without being delivered, because subscription
+    /// expired or was unsubscribed.
+    pub events_expired: u64,
+    /// Events of expired Proposals, that no one reacted to, removed
+    /// by background sweep.
+    pub proposals_pruned: u64,
+}
+
+#[derive(Default)]
+struct StatsCounters {
+    proposals_generated: AtomicU64,
+    events_delivered: AtomicU64,
+    events_expired: AtomicU64,
+    proposals_pruned: AtomicU64,
+}
+
 #[derive(Clone)]
 pub struct CommonBroker {
     pub(super) db: DbExecutor,
@@ -56,6 +83,8 @@ pub struct CommonBroker {
     pub(super) agreement_notifier: EventNotifier<AgreementId>,
     pub(super) config: Arc<Config>,
     pub(super) agreement_lock: AgreementLock,
+    pub(super) scores: ProposalScores,
+    stats: Arc<StatsCounters>,
 }
 
 impl CommonBroker {
@@ -73,26 +102,43 @@ impl CommonBroker {
             agreement_notifier: EventNotifier::new(),
             config,
             agreement_lock: AgreementLock::new(),
+            scores: ProposalScores::default(),
+            stats: Arc::new(StatsCounters::default()),
+        }
+    }
+
+    pub fn stats(&self) -> MarketStats {
+        MarketStats {
+            proposals_generated: self.stats.proposals_generated.load(Ordering::Relaxed),
+            events_delivered: self.stats.events_delivered.load(Ordering::Relaxed),
+            events_expired: self.stats.events_expired.load(Ordering::Relaxed),
+            proposals_pruned: self.stats.proposals_pruned.load(Ordering::Relaxed),
         }
     }
 
     pub async fn unsubscribe(&self, id: &SubscriptionId) -> Result<(), NegotiationError> {
         self.negotiation_notifier.stop_notifying(id).await;
+        self.scores.remove_subscription(id);
 
         // We can ignore error, if removing events failed, because they will be never
         // queried again and don't collide with other subscriptions.
-        let _ = self
+        match self
             .db
             .as_dao::<NegotiationEventsDao>()
             .remove_events(id)
             .await
-            .map_err(|e| {
-                log::warn!(
-                    "Failed to remove events related to subscription [{}]. Error: {}.",
-                    id,
-                    e
-                )
-            });
+        {
+            Ok(num_removed) => {
+                self.stats
+                    .events_expired
+                    .fetch_add(num_removed as u64, Ordering::Relaxed);
+            }
+            Err(e) => log::warn!(
+                "Failed to remove events related to subscription [{}]. Error: {}.",
+                id,
+                e
+            ),
+        };
 
         // TODO: remove all resources related to Proposals
         Ok(())
@@ -150,6 +196,13 @@ impl CommonBroker {
         self.validate_proposal(&proposal, caller_id, caller_role)
             .await?;
 
+        match proposal.body.state {
+            ProposalState::Accepted | ProposalState::Rejected => Err(
+                RejectProposalError::InvalidState(proposal_id.clone(), proposal.body.state),
+            )?,
+            _ => (),
+        }
+
         self.db
             .as_dao::<ProposalDao>()
             .change_proposal_state(proposal_id, ProposalState::Rejected)
@@ -172,8 +225,39 @@ impl CommonBroker {
         timeout: f32,
         max_events: Option<i32>,
         owner: Owner,
+        after: Option<DateTime<Utc>>,
+        kinds: Option<Vec<EventKind>>,
     ) -> Result<Vec<MarketEvent>, QueryEventsError> {
-        let mut timeout = Duration::from_secs_f32(timeout.max(0.0));
+        let start = Instant::now();
+        let result = self
+            .query_events_inner(subscription_id, timeout, max_events, owner, after, kinds)
+            .await;
+        record_query_events(owner, start, &result);
+        result
+    }
+
+    async fn query_events_inner(
+        &self,
+        subscription_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        owner: Owner,
+        after: Option<DateTime<Utc>>,
+        kinds: Option<Vec<EventKind>>,
+    ) -> Result<Vec<MarketEvent>, QueryEventsError> {
+        let max_timeout = self.config.events.max_timeout.as_secs_f32();
+        let timeout = if timeout > max_timeout {
+            log::warn!(
+                "Requested query_events timeout {}s for subscription [{}] exceeds limit. Using {}s.",
+                timeout,
+                subscription_id,
+                max_timeout
+            );
+            max_timeout
+        } else {
+            timeout.max(0.0)
+        };
+        let mut timeout = Duration::from_secs_f32(timeout);
         let stop_time = Instant::now() + timeout;
         let max_events = max_events.unwrap_or(self.config.events.max_events_default);
 
@@ -184,15 +268,40 @@ impl CommonBroker {
             ))?
         }
 
+        // Callers waiting for the same subscription are served in FIFO order.
+        // Only the longest waiting one can take events, the rest will be woken
+        // up again, when it returns. Callers filtering events don't take part
+        // in queue, because they would block others waiting for different kinds.
+        // Note, that events themselves are handed out in insertion order only
+        // with `ProposalsOrder::InsertionOrder`. With other orders queue only
+        // guarantees, that callers don't take events concurrently.
+        let ticket = match kinds {
+            None => Some(self.negotiation_notifier.enqueue(subscription_id)),
+            Some(_) => None,
+        };
         let mut notifier = self.negotiation_notifier.listen(subscription_id);
+        let mut expiration = self.subscription_expiration(subscription_id, owner).await;
+        // Subscription is validated on start and after it should have expired.
+        // Unsubscribe wakes up waiting callers by itself.
+        let mut validate = true;
         loop {
-            let events = self
-                .db
-                .as_dao::<NegotiationEventsDao>()
-                .take_events(subscription_id, max_events, owner)
-                .await?;
+            let dao = self.db.as_dao::<NegotiationEventsDao>();
+            let events = if !ticket.as_ref().map(|t| t.is_first()).unwrap_or(true) {
+                // Not our turn to take events, but subscription still must be valid.
+                if validate {
+                    dao.count_events(subscription_id, owner).await?;
+                    validate = false;
+                }
+                vec![]
+            } else {
+                self.take_events_detached(subscription_id, max_events, owner, after, kinds.clone())
+                    .await?
+            };
 
             if events.len() > 0 {
+                self.stats
+                    .events_delivered
+                    .fetch_add(events.len() as u64, Ordering::Relaxed);
                 return Ok(events);
             }
 
@@ -202,13 +311,35 @@ impl CommonBroker {
             }
             timeout = stop_time - Instant::now();
 
+            // We must wake up, when subscription expires, otherwise caller
+            // would wait until the end of timeout without getting any events.
+            let mut expires_first = false;
+            if let Some(expiration) = expiration {
+                let until_expiration = (expiration - Utc::now().naive_utc())
+                    .to_std()
+                    .unwrap_or(Duration::from_millis(0));
+                if until_expiration < timeout {
+                    timeout = until_expiration;
+                    expires_first = true;
+                }
+            }
+
             if let Err(e) = notifier.wait_for_event_with_timeout(timeout).await {
                 return match e {
+                    // Next `take_events` call will return Expired error,
+                    // unless subscription was refreshed in the meantime.
+                    NotifierError::Timeout(_) if expires_first => {
+                        expiration = self.subscription_expiration(subscription_id, owner).await;
+                        validate = true;
+                        continue;
+                    }
                     NotifierError::Timeout(_) => Ok(vec![]),
                     NotifierError::ChannelClosed(_) => {
                         Err(QueryEventsError::Internal(e.to_string()))
                     }
-                    NotifierError::Unsubscribed(id) => Err(TakeEventsError::NotFound(id).into()),
+                    NotifierError::Unsubscribed(id) => {
+                        Err(TakeEventsError::Unsubscribed(id).into())
+                    }
                 };
             }
             // Ok result means, that event with required subscription id was added.
@@ -217,6 +348,149 @@ impl CommonBroker {
         }
     }
 
+    /// Events are taken in separate task, so they aren't lost, when caller's
+    /// future is dropped in the middle of database transaction. Events, that
+    /// no one is waiting for anymore, are put back to queue.
+    /// Task is spawned on runtime (not locally), because `query_events` can be
+    /// called from tasks without `LocalSet`.
+    async fn take_events_detached(
+        &self,
+        subscription_id: &SubscriptionId,
+        max_events: i32,
+        owner: Owner,
+        after: Option<DateTime<Utc>>,
+        kinds: Option<Vec<EventKind>>,
+    ) -> Result<Vec<MarketEvent>, QueryEventsError> {
+        let (sender, receiver) = oneshot::channel();
+        let db = self.db.clone();
+        let notifier = self.negotiation_notifier.clone();
+        let subscription_id = subscription_id.clone();
+        let order = self.config.events.proposals_order;
+
+        tokio::spawn(async move {
+            let dao = db.as_dao::<NegotiationEventsDao>();
+            let result = match after {
+                Some(after) => {
+                    dao.take_events_after(
+                        &subscription_id,
+                        max_events,
+                        owner,
+                        after.naive_utc(),
+                        kinds,
+                    )
+                    .await
+                }
+                None => {
+                    dao.take_events(&subscription_id, max_events, owner, order, kinds)
+                        .await
+                }
+            };
+
+            if let Err(Ok(events)) = sender.send(result) {
+                if events.is_empty() {
+                    return;
+                }
+                log::debug!(
+                    "query_events for subscription [{}] cancelled. Restoring {} events.",
+                    &subscription_id,
+                    events.len()
+                );
+                match dao.restore_events(events).await {
+                    // Wake up callers, that were waiting in queue behind cancelled one.
+                    Ok(_) => notifier.notify(&subscription_id).await,
+                    Err(e) => log::warn!(
+                        "Failed to restore events for subscription [{}]. {}",
+                        &subscription_id,
+                        e
+                    ),
+                }
+            }
+        });
+
+        Ok(receiver.await.map_err(|_| {
+            QueryEventsError::Internal("Task taking events was cancelled.".to_string())
+        })??)
+    }
+
+    /// Removes expired Proposals, that no one reacted to.
+    pub async fn prune_expired_proposals(&self) -> DbResult<usize> {
+        let now = Utc::now().naive_utc();
+        self.scores.remove_expired(now);
+        let num_removed = self.db.as_dao::<ProposalDao>().prune_expired(now).await?;
+        if num_removed > 0 {
+            self.stats
+                .proposals_pruned
+                .fetch_add(num_removed as u64, Ordering::Relaxed);
+            log::info!("Removed {} events of expired Proposals.", num_removed);
+        }
+        Ok(num_removed)
+    }
+
+    /// Only owner of the subscription can access its events. Not existing
+    /// or expired subscriptions are left for querying to report.
+    pub async fn check_owner(
+        &self,
+        subscription_id: &SubscriptionId,
+        owner: Owner,
+        id: &Identity,
+    ) -> Result<(), QueryEventsError> {
+        let node_id = match owner {
+            Owner::Requestor => self
+                .store
+                .get_demand(subscription_id)
+                .await
+                .map(|demand| demand.node_id)
+                .ok(),
+            Owner::Provider => self
+                .store
+                .get_offer(subscription_id)
+                .await
+                .map(|offer| offer.node_id)
+                .ok(),
+        };
+        match node_id {
+            Some(node_id) if node_id != id.identity => {
+                Err(QueryEventsError::Forbidden(subscription_id.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn count_pending_events(
+        &self,
+        subscription_id: &SubscriptionId,
+        owner: Owner,
+    ) -> Result<usize, QueryEventsError> {
+        Ok(self
+            .db
+            .as_dao::<NegotiationEventsDao>()
+            .count_events(subscription_id, owner)
+            .await?)
+    }
+
+    /// Returns `None` for not existing or inactive subscriptions. We don't need
+    /// to handle them here, because `take_events` will return proper error.
+    async fn subscription_expiration(
+        &self,
+        subscription_id: &SubscriptionId,
+        owner: Owner,
+    ) -> Option<NaiveDateTime> {
+        match owner {
+            Owner::Requestor => self
+                .store
+                .get_demand(subscription_id)
+                .await
+                .ok()
+                .map(|demand| demand.expiration_ts),
+            Owner::Provider => self
+                .store
+                .get_offer(subscription_id)
+                .await
+                .ok()
+                .map(|offer| offer.expiration_ts),
+        }
+    }
+
     pub async fn query_agreement_events(
         &self,
         session_id: &AppSessionId,
@@ -714,11 +988,75 @@ impl CommonBroker {
     }
 
     pub async fn generate_proposal(&self, proposal: RawProposal) -> Result<(), SaveProposalError> {
+        let proposal = match self.save_initial_proposal(proposal).await? {
+            Some(proposal) => proposal,
+            None => return Ok(()),
+        };
+
+        // Send channel message to wake all query_events waiting for proposals.
+        counter!("market.proposals.requestor.generated", 1);
+        self.stats
+            .proposals_generated
+            .fetch_add(1, Ordering::Relaxed);
+        self.negotiation_notifier
+            .notify(&proposal.negotiation.subscription_id)
+            .await;
+        Ok(())
+    }
+
+    /// Generates Proposals in the same order as they were passed. Waiting
+    /// query_events calls are notified only once, after all Proposals were saved.
+    pub async fn generate_proposals(
+        &self,
+        proposals: Vec<RawProposal>,
+    ) -> Result<Vec<ProposalId>, SaveProposalError> {
+        let mut ids = vec![];
+        let mut subscriptions: Vec<SubscriptionId> = vec![];
+        for proposal in proposals {
+            let proposal = match self.save_initial_proposal(proposal).await? {
+                Some(proposal) => proposal,
+                None => continue,
+            };
+            let subscription_id = proposal.negotiation.subscription_id;
+            if !subscriptions.contains(&subscription_id) {
+                subscriptions.push(subscription_id);
+            }
+            ids.push(proposal.body.id);
+        }
+
+        counter!("market.proposals.requestor.generated", ids.len() as u64);
+        self.stats
+            .proposals_generated
+            .fetch_add(ids.len() as u64, Ordering::Relaxed);
+        for subscription_id in subscriptions.iter() {
+            self.negotiation_notifier.notify(subscription_id).await;
+        }
+        Ok(ids)
+    }
+
+    /// Returns None, if Provider already has active Proposal with the same content
+    /// for this Demand. This happens, when the same Offer is subscribed many times
+    /// or when the same Offer is matched again after being re-broadcasted.
+    async fn save_initial_proposal(
+        &self,
+        proposal: RawProposal,
+    ) -> Result<Option<Proposal>, SaveProposalError> {
         let db = self.db.clone();
-        let notifier = self.negotiation_notifier.clone();
 
-        // Add proposal to database together with Negotiation record.
+        let score = self.scores.score(&proposal.offer, &proposal.demand);
         let proposal = Proposal::new_requestor(proposal.demand, proposal.offer);
+        if self.is_duplicate(&proposal).await? {
+            log::debug!(
+                "Skipping Offer [{}] for Demand [{}]. Provider [{}] already has active Proposal with the same content.",
+                proposal.negotiation.offer_id,
+                proposal.negotiation.demand_id,
+                proposal.negotiation.provider_id
+            );
+            counter!("market.proposals.requestor.duplicated", 1);
+            return Ok(None);
+        }
+
+        // Add proposal to database together with Negotiation record.
         let proposal = db
             .as_dao::<ProposalDao>()
             .save_initial_proposal(proposal)
@@ -731,16 +1069,37 @@ impl CommonBroker {
             proposal.negotiation.demand_id
         );
 
+        if let Some(score) = score {
+            self.scores.insert(
+                proposal.body.id.clone(),
+                proposal.negotiation.subscription_id.clone(),
+                proposal.body.expiration_ts,
+                score,
+            );
+        }
+
         // Create Proposal Event and add it to queue (database).
-        let subscription_id = proposal.negotiation.subscription_id.clone();
         db.as_dao::<NegotiationEventsDao>()
             .add_proposal_event(&proposal, Owner::Requestor)
             .await?;
+        Ok(Some(proposal))
+    }
 
-        // Send channel message to wake all query_events waiting for proposals.
-        counter!("market.proposals.requestor.generated", 1);
-        notifier.notify(&subscription_id).await;
-        Ok(())
+    async fn is_duplicate(&self, proposal: &Proposal) -> Result<bool, SaveProposalError> {
+        let duplicates = self
+            .db
+            .as_dao::<ProposalDao>()
+            .find_active_duplicates(proposal)
+            .await?;
+
+        // Proposals for unsubscribed Offers can't be negotiated anymore,
+        // so they shouldn't block new ones.
+        for negotiation in duplicates {
+            if self.store.get_offer(&negotiation.offer_id).await.is_ok() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     pub async fn regenerate_proposal(
@@ -835,3 +1194,30 @@ pub fn inc_terminate_metrics(reason: &Option<Reason>, owner: Owner) {
         }
     };
 }
+
+/// Records how long `query_events` call blocked, how many events it returned
+/// and how it ended. Metrics are no-op, unless some recorder is installed.
+fn record_query_events(
+    owner: Owner,
+    start: Instant,
+    result: &Result<Vec<MarketEvent>, QueryEventsError>,
+) {
+    let owner = match owner {
+        Owner::Provider => "provider",
+        Owner::Requestor => "requestor",
+    };
+    let outcome = match result {
+        Ok(events) if events.is_empty() => "empty",
+        Ok(_) => "success",
+        Err(QueryEventsError::TakeEvents(TakeEventsError::Unsubscribed(_))) => "unsubscribed",
+        Err(QueryEventsError::TakeEvents(TakeEventsError::Expired(_))) => "expired",
+        Err(QueryEventsError::UnknownSubscription(_)) => "not-found",
+        Err(QueryEventsError::InvalidMaxEvents(..)) => "invalid-max-events",
+        Err(_) => "error",
+    };
+    let returned = result.as_ref().map(|events| events.len()).unwrap_or(0);
+
+    timing!("market.events.query.time", start, Instant::now(), "owner" => owner, "outcome" => outcome);
+    value!("market.events.query.returned", returned as u64, "owner" => owner);
+    counter!("market.events.query", 1, "owner" => owner, "outcome" => outcome);
+}
@@ -60,6 +60,8 @@ pub enum AgreementError {
     NoNegotiations(ProposalId),
     #[error("Can't create Agreement for out own Proposal {0}. You can promote only provider's Proposals to Agreement.")]
     OwnProposal(ProposalId),
+    #[error("Can't create Agreement for rejected Proposal [{0}].")]
+    ProposalRejected(ProposalId),
     #[error("Failed to save Agreement for Proposal [{0}]. Error: {1}")]
     Save(ProposalId, DbError),
     #[error("Failed to get Agreement [{0}]. Error: {1}")]
@@ -104,12 +106,18 @@ pub enum WaitForApprovalError {
 
 #[derive(Error, Debug)]
 pub enum QueryEventsError {
+    /// Subscription id is malformed.
     #[error("Invalid subscription id. {0}")]
-    InvalidSubscriptionId(#[from] SubscriptionParseError),
+    InvalidSubscriptionId(String),
+    /// Subscription id is well-formed, but was never registered.
+    #[error("Subscription [{0}] not found.")]
+    UnknownSubscription(SubscriptionId),
     #[error(transparent)]
-    TakeEvents(#[from] TakeEventsError),
+    TakeEvents(TakeEventsError),
     #[error("Invalid maxEvents '{0}', should be between 1 and {1}.")]
     InvalidMaxEvents(i32, i32),
+    #[error("Subscription [{0}] belongs to other identity.")]
+    Forbidden(SubscriptionId),
     #[error("Can't query events. Error: {0}.")]
     Internal(String),
 }
@@ -204,6 +212,21 @@ impl From<QueryOfferError> for ProposalValidationError {
     }
 }
 
+impl From<SubscriptionParseError> for QueryEventsError {
+    fn from(e: SubscriptionParseError) -> Self {
+        QueryEventsError::InvalidSubscriptionId(e.to_string())
+    }
+}
+
+impl From<TakeEventsError> for QueryEventsError {
+    fn from(e: TakeEventsError) -> Self {
+        match e {
+            TakeEventsError::NotFound(id) => QueryEventsError::UnknownSubscription(id),
+            e => QueryEventsError::TakeEvents(e),
+        }
+    }
+}
+
 impl From<DemandError> for ProposalValidationError {
     fn from(e: DemandError) -> Self {
         match e {
@@ -1,4 +1,6 @@
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::broadcast::{channel, Receiver, Sender};
@@ -27,6 +29,8 @@ where
     for<'a> DisplayEnabler<'a, Type>: std::fmt::Display,
 {
     sender: Sender<Notification<Type>>,
+    waiters: Arc<Mutex<Vec<(Type, u64)>>>,
+    next_ticket: Arc<AtomicU64>,
 }
 
 /// Thanks to EventNotifierListener we can create separate object, that already collects
@@ -41,6 +45,20 @@ where
     subscription_id: Type,
 }
 
+/// Place in the queue of callers waiting for events for the same id.
+/// Callers are served in order in which they took their tickets.
+/// Dropping ticket wakes up remaining waiters, so the next one can
+/// check, if something is left for it.
+pub struct WaitTicket<Type>
+where
+    Type: Debug + PartialEq + Clone + EnableDisplay<Type> + 'static,
+    for<'a> DisplayEnabler<'a, Type>: std::fmt::Display,
+{
+    notifier: EventNotifier<Type>,
+    subscription_id: Type,
+    ticket: u64,
+}
+
 #[derive(Clone)]
 enum Notification<Type>
 where
@@ -59,7 +77,11 @@ where
     pub fn new() -> EventNotifier<Type> {
         // We will create receivers later, when someone needs it.
         let (sender, _receiver) = channel(100);
-        EventNotifier { sender }
+        EventNotifier {
+            sender,
+            waiters: Arc::new(Mutex::new(vec![])),
+            next_ticket: Arc::new(AtomicU64::new(0)),
+        }
     }
 
     pub async fn notify(&self, subscription_id: &Type) {
@@ -82,6 +104,58 @@ where
             subscription_id: subscription_id.clone(),
         }
     }
+
+    /// Enqueues caller at the end of waiters queue for given id.
+    pub fn enqueue(&self, subscription_id: &Type) -> WaitTicket<Type> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::SeqCst);
+        self.waiters
+            .lock()
+            .unwrap()
+            .push((subscription_id.clone(), ticket));
+
+        WaitTicket {
+            notifier: self.clone(),
+            subscription_id: subscription_id.clone(),
+            ticket,
+        }
+    }
+}
+
+impl<Type> WaitTicket<Type>
+where
+    Type: Debug + PartialEq + Clone + EnableDisplay<Type> + 'static,
+    for<'a> DisplayEnabler<'a, Type>: std::fmt::Display,
+{
+    /// Checks if there are no callers, that started waiting earlier.
+    pub fn is_first(&self) -> bool {
+        self.notifier
+            .waiters
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, _)| id == &self.subscription_id)
+            .map(|(_, ticket)| *ticket == self.ticket)
+            .unwrap_or(false)
+    }
+}
+
+impl<Type> Drop for WaitTicket<Type>
+where
+    Type: Debug + PartialEq + Clone + EnableDisplay<Type> + 'static,
+    for<'a> DisplayEnabler<'a, Type>: std::fmt::Display,
+{
+    fn drop(&mut self) {
+        let others_waiting = {
+            let mut waiters = self.notifier.waiters.lock().unwrap();
+            waiters.retain(|(_, ticket)| *ticket != self.ticket);
+            waiters.iter().any(|(id, _)| id == &self.subscription_id)
+        };
+
+        if others_waiting {
+            let to_send = Notification::<Type>::NewEvent(self.subscription_id.clone());
+            let _ = self.notifier.sender.send(to_send);
+        }
+    }
 }
 
 impl<Type> EventNotifierListener<Type>
@@ -17,7 +17,7 @@ use crate::db::{
 use crate::matcher::store::SubscriptionStore;
 use crate::protocol::negotiation::{error::*, messages::*, provider::NegotiationApi};
 
-use super::common::CommonBroker;
+use super::common::{CommonBroker, MarketStats};
 use super::error::*;
 use super::notifier::EventNotifier;
 use crate::config::Config;
@@ -114,6 +114,10 @@ impl ProviderBroker {
         })
     }
 
+    pub fn stats(&self) -> MarketStats {
+        self.common.stats()
+    }
+
     pub async fn bind_gsb(
         &self,
         public_prefix: &str,
@@ -191,15 +195,52 @@ impl ProviderBroker {
         Ok(())
     }
 
+    /// Returns number of events, that would be returned by `query_events`,
+    /// without marking them as consumed.
+    pub async fn count_pending_events(
+        &self,
+        offer_id: &SubscriptionId,
+        id: &Identity,
+    ) -> Result<usize, QueryEventsError> {
+        self.common
+            .check_owner(offer_id, Owner::Provider, id)
+            .await?;
+        self.count_pending_events_unchecked(offer_id).await
+    }
+
+    pub(crate) async fn count_pending_events_unchecked(
+        &self,
+        offer_id: &SubscriptionId,
+    ) -> Result<usize, QueryEventsError> {
+        self.common
+            .count_pending_events(offer_id, Owner::Provider)
+            .await
+    }
+
+    /// Only owner of the Offer can query its events.
     pub async fn query_events(
         &self,
         offer_id: &SubscriptionId,
         timeout: f32,
         max_events: Option<i32>,
+        id: &Identity,
+    ) -> Result<Vec<ProviderEvent>, QueryEventsError> {
+        self.common
+            .check_owner(offer_id, Owner::Provider, id)
+            .await?;
+        self.query_events_unchecked(offer_id, timeout, max_events)
+            .await
+    }
+
+    pub(crate) async fn query_events_unchecked(
+        &self,
+        offer_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
     ) -> Result<Vec<ProviderEvent>, QueryEventsError> {
         let events = self
             .common
-            .query_events(offer_id, timeout, max_events, Owner::Provider)
+            .query_events(offer_id, timeout, max_events, Owner::Provider, None, None)
             .await?;
 
         // Map model events to client RequestorEvent.
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
 use metrics::counter;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedReceiver;
@@ -12,14 +13,14 @@ use ya_service_api_web::middleware::Identity;
 use ya_std_utils::LogErr;
 
 use crate::db::{
-    dao::{AgreementDao, AgreementDaoError, SaveAgreementError},
+    dao::{AgreementDao, AgreementDaoError, SaveAgreementError, TakeEventsError},
     model::{Agreement, AgreementId, AgreementState, AppSessionId},
-    model::{Demand, Issuer, Owner, ProposalId, SubscriptionId},
+    model::{Demand, EventKind, Issuer, Owner, ProposalId, ProposalState, SubscriptionId},
 };
-use crate::matcher::{store::SubscriptionStore, RawProposal};
+use crate::matcher::{error::DemandError, store::SubscriptionStore, RawProposal};
 use crate::protocol::negotiation::{error::*, messages::*, requestor::NegotiationApi};
 
-use super::{common::*, error::*, notifier::NotifierError, EventNotifier};
+use super::{common::*, error::*, notifier::NotifierError, EventNotifier, ProposalScorer};
 use crate::config::Config;
 use crate::db::dao::AgreementEventsDao;
 use crate::utils::display::EnableDisplay;
@@ -34,6 +35,14 @@ pub enum ApprovalStatus {
     Rejected { reason: Option<Reason> },
 }
 
+/// Requestor event annotated with score computed by `ProposalScorer`.
+/// Score is available only for initial Proposals.
+#[derive(Clone, Debug)]
+pub struct ScoredEvent {
+    pub event: RequestorEvent,
+    pub score: Option<f64>,
+}
+
 /// Requestor part of negotiation logic.
 pub struct RequestorBroker {
     pub(crate) common: CommonBroker,
@@ -106,10 +115,16 @@ impl RequestorBroker {
         counter!("market.proposals.requestor.rejected.by-us", 0);
         counter!("market.proposals.self-reaction-attempt", 0);
 
+        tokio::spawn(expired_demands_sweeper_thread(broker.clone()));
+        tokio::spawn(expired_proposals_sweeper_thread(broker.clone()));
         tokio::spawn(proposal_receiver_thread(broker, proposal_receiver));
         Ok(engine)
     }
 
+    pub fn stats(&self) -> MarketStats {
+        self.common.stats()
+    }
+
     pub async fn bind_gsb(
         &self,
         public_prefix: &str,
@@ -128,6 +143,12 @@ impl RequestorBroker {
         self.common.unsubscribe(id).await
     }
 
+    /// Removes expired Demands. Waiting query_events calls are woken up
+    /// the same way, as when Demand is unsubscribed explicitly.
+    pub async fn unsubscribe_expired_demands(&self) -> Result<Vec<SubscriptionId>, DemandError> {
+        unsubscribe_expired_demands(&self.common).await
+    }
+
     pub async fn counter_proposal(
         &self,
         demand_id: &SubscriptionId,
@@ -193,15 +214,166 @@ impl RequestorBroker {
         Ok(())
     }
 
+    /// Only owner of the Demand can query its events.
     pub async fn query_events(
         &self,
         demand_id: &SubscriptionId,
         timeout: f32,
         max_events: Option<i32>,
+        id: &Identity,
+    ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
+        self.query_events_with(demand_id, timeout, max_events, None, None, id)
+            .await
+    }
+
+    pub(crate) async fn query_events_unchecked(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+    ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
+        self.collect_events(demand_id, timeout, max_events, None, None)
+            .await
+    }
+
+    /// Queries events using cursor. Events are returned in timestamp order.
+    /// Passing `event_date` of the last processed event as `after` acknowledges
+    /// all previous events, so they won't be returned again. Events newer than
+    /// `after` are kept, until they are acknowledged by the next call.
+    ///
+    /// With `after` set to `None` events are consumed immediately like before.
+    pub async fn query_events_after(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        after: Option<DateTime<Utc>>,
+        id: &Identity,
+    ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
+        self.query_events_with(demand_id, timeout, max_events, after, None, id)
+            .await
+    }
+
+    /// Scorer will be applied to Proposals generated from now on.
+    /// Passing `None` restores default behavior.
+    pub fn set_proposal_scorer(&self, scorer: Option<Arc<dyn ProposalScorer>>) {
+        self.common.scores.set_scorer(scorer);
+    }
+
+    /// The same as `query_events_after`, but initial Proposals are annotated
+    /// with score assigned by `ProposalScorer`.
+    pub async fn query_scored_events(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        id: &Identity,
+    ) -> Result<Vec<ScoredEvent>, QueryEventsError> {
+        let events = self
+            .query_events_after(demand_id, timeout, max_events, None, id)
+            .await?;
+
+        Ok(events
+            .into_iter()
+            .map(|event| {
+                let score = match &event {
+                    RequestorEvent::ProposalEvent { proposal, .. } => {
+                        ProposalId::from_str(&proposal.proposal_id)
+                            .ok()
+                            .and_then(|id| self.common.scores.take(&id))
+                    }
+                    _ => None,
+                };
+                ScoredEvent { event, score }
+            })
+            .collect())
+    }
+
+    /// Alternative to polling `query_events`. Events are consumed the same way,
+    /// as by `query_events`. Stream ends, when Demand is unsubscribed or
+    /// after first error. Events are taken one at a time, so dropping the
+    /// stream never loses events, which weren't yielded yet.
+    pub fn event_stream<'a>(
+        &'a self,
+        demand_id: &SubscriptionId,
+        id: &Identity,
+    ) -> impl Stream<Item = Result<RequestorEvent, QueryEventsError>> + 'a {
+        let timeout = self.common.config.events.max_timeout.as_secs_f32();
+        let state = (demand_id.clone(), id.clone(), false);
+
+        futures::stream::unfold(state, move |(demand_id, id, finished)| async move {
+            if finished {
+                return None;
+            }
+            loop {
+                match self
+                    .query_events_after(&demand_id, timeout, Some(1), None, &id)
+                    .await
+                {
+                    Ok(events) => {
+                        if let Some(event) = events.into_iter().next() {
+                            return Some((Ok(event), (demand_id, id, false)));
+                        }
+                    }
+                    Err(QueryEventsError::TakeEvents(TakeEventsError::Unsubscribed(_))) => {
+                        return None
+                    }
+                    Err(e) => return Some((Err(e), (demand_id, id, true))),
+                }
+            }
+        })
+    }
+
+    /// Returns only events of given kinds. Other events stay in queue and
+    /// don't count against `max_events`. With `event_kinds` set to `None`
+    /// it behaves the same as `query_events`.
+    pub async fn query_events_filtered(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        event_kinds: Option<Vec<EventKind>>,
+        id: &Identity,
+    ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
+        self.query_events_with(demand_id, timeout, max_events, None, event_kinds, id)
+            .await
+    }
+
+    /// Common path of all public queries, so none of them skips ownership check.
+    async fn query_events_with(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        after: Option<DateTime<Utc>>,
+        event_kinds: Option<Vec<EventKind>>,
+        id: &Identity,
+    ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
+        self.common
+            .check_owner(demand_id, Owner::Requestor, id)
+            .await?;
+        self.collect_events(demand_id, timeout, max_events, after, event_kinds)
+            .await
+    }
+
+    async fn collect_events(
+        &self,
+        demand_id: &SubscriptionId,
+        timeout: f32,
+        max_events: Option<i32>,
+        after: Option<DateTime<Utc>>,
+        event_kinds: Option<Vec<EventKind>>,
     ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
         let events = self
             .common
-            .query_events(demand_id, timeout, max_events, Owner::Requestor)
+            .query_events(
+                demand_id,
+                timeout,
+                max_events,
+                Owner::Requestor,
+                after,
+                event_kinds,
+            )
             .await?;
 
         // Map model events to client RequestorEvent.
@@ -220,6 +392,28 @@ impl RequestorBroker {
         Ok(events)
     }
 
+    /// Returns number of events, that would be returned by `query_events`,
+    /// without marking them as consumed.
+    pub async fn count_pending_events(
+        &self,
+        demand_id: &SubscriptionId,
+        id: &Identity,
+    ) -> Result<usize, QueryEventsError> {
+        self.common
+            .check_owner(demand_id, Owner::Requestor, id)
+            .await?;
+        self.count_pending_events_unchecked(demand_id).await
+    }
+
+    pub(crate) async fn count_pending_events_unchecked(
+        &self,
+        demand_id: &SubscriptionId,
+    ) -> Result<usize, QueryEventsError> {
+        self.common
+            .count_pending_events(demand_id, Owner::Requestor)
+            .await
+    }
+
     /// Initiates the Agreement handshake phase.
     ///
     /// Formulates an Agreement artifact from the Proposal indicated by the
@@ -249,6 +443,15 @@ impl RequestorBroker {
         if offer_proposal.body.issuer != Issuer::Them {
             return Err(AgreementError::OwnProposal(proposal_id.clone()));
         }
+        match offer_proposal.body.state {
+            ProposalState::Initial => {
+                return Err(AgreementError::NoNegotiations(proposal_id.clone()))
+            }
+            ProposalState::Rejected => {
+                return Err(AgreementError::ProposalRejected(proposal_id.clone()))
+            }
+            _ => (),
+        }
 
         let demand_proposal_id = offer_proposal
             .body
@@ -679,6 +882,38 @@ async fn agreement_rejected(
     Ok(())
 }
 
+async fn unsubscribe_expired_demands(
+    broker: &CommonBroker,
+) -> Result<Vec<SubscriptionId>, DemandError> {
+    let expired = broker.store.remove_expired_demands().await?;
+    for demand_id in expired.iter() {
+        broker.unsubscribe(demand_id).await.ok();
+        log::info!("Unsubscribed expired Demand [{}].", demand_id);
+    }
+    Ok(expired)
+}
+
+async fn expired_demands_sweeper_thread(broker: CommonBroker) {
+    let mut interval = tokio::time::interval(broker.config.subscription.expired_sweep_interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = unsubscribe_expired_demands(&broker).await {
+            log::warn!("Failed to unsubscribe expired Demands. Error: {}", e);
+        }
+    }
+}
+
+async fn expired_proposals_sweeper_thread(broker: CommonBroker) {
+    let mut interval =
+        tokio::time::interval(broker.config.subscription.expired_proposals_sweep_interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker.prune_expired_proposals().await {
+            log::warn!("Failed to remove expired Proposals. Error: {}", e);
+        }
+    }
+}
+
 pub async fn proposal_receiver_thread(
     broker: CommonBroker,
     mut proposal_receiver: UnboundedReceiver<RawProposal>,
@@ -0,0 +1,81 @@
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::db::model::{Demand, Offer, ProposalId, SubscriptionId};
+
+/// Lets Requestor rank Offers matching his Demand. Score is computed,
+/// when initial Proposal is generated.
+pub trait ProposalScorer: Send + Sync {
+    fn score(&self, offer: &Offer, demand: &Demand) -> f64;
+}
+
+/// Scores of initial Proposals, which weren't returned by query_scored_events yet.
+#[derive(Clone, Default)]
+pub(crate) struct ProposalScores {
+    scorer: Arc<RwLock<Option<Arc<dyn ProposalScorer>>>>,
+    scores: Arc<Mutex<HashMap<ProposalId, ScoreEntry>>>,
+}
+
+struct ScoreEntry {
+    subscription_id: SubscriptionId,
+    expiration_ts: NaiveDateTime,
+    score: f64,
+}
+
+impl ProposalScores {
+    pub fn set_scorer(&self, scorer: Option<Arc<dyn ProposalScorer>>) {
+        *self.scorer.write().unwrap() = scorer;
+    }
+
+    /// Returns `None`, if no scorer was set.
+    pub fn score(&self, offer: &Offer, demand: &Demand) -> Option<f64> {
+        self.scorer
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|scorer| scorer.score(offer, demand))
+    }
+
+    pub fn insert(
+        &self,
+        proposal_id: ProposalId,
+        subscription_id: SubscriptionId,
+        expiration_ts: NaiveDateTime,
+        score: f64,
+    ) {
+        self.scores.lock().unwrap().insert(
+            proposal_id,
+            ScoreEntry {
+                subscription_id,
+                expiration_ts,
+                score,
+            },
+        );
+    }
+
+    /// Score is returned only once, together with Proposal event.
+    pub fn take(&self, proposal_id: &ProposalId) -> Option<f64> {
+        self.scores
+            .lock()
+            .unwrap()
+            .remove(proposal_id)
+            .map(|entry| entry.score)
+    }
+
+    pub fn remove_subscription(&self, subscription_id: &SubscriptionId) {
+        self.scores
+            .lock()
+            .unwrap()
+            .retain(|_, entry| &entry.subscription_id != subscription_id);
+    }
+
+    /// Proposal events taken by plain query_events never claim their score,
+    /// so scores are dropped together with expired Proposals.
+    pub fn remove_expired(&self, now: NaiveDateTime) {
+        self.scores
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.expiration_ts > now);
+    }
+}
@@ -2,7 +2,9 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::db::dao::ChangeProposalStateError;
-use crate::db::model::{AgreementId, AgreementState, ProposalId, ProposalIdValidationError};
+use crate::db::model::{
+    AgreementId, AgreementState, ProposalId, ProposalIdValidationError, ProposalState,
+};
 use crate::matcher::error::QueryOfferError;
 use crate::negotiation::error::{GetProposalError, MatchValidationError, ProposalValidationError};
 
@@ -46,6 +48,8 @@ pub enum RejectProposalError {
     Validation(#[from] ProposalValidationError),
     #[error(transparent)]
     CallerParse(#[from] CallerParseError),
+    #[error("Can't reject Proposal [{0}] in state {1}.")]
+    InvalidState(ProposalId, ProposalState),
 }
 
 #[derive(Error, Debug, Serialize, Deserialize)]
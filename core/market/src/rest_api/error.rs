@@ -66,6 +66,12 @@ impl ResponseError for DemandError {
             DemandError::NotFound(_) => {
                 HttpResponse::NotFound().json(ErrorMessage::new(self.to_string()))
             }
+            DemandError::ResumeWindowElapsed(_) => {
+                HttpResponse::Gone().json(ErrorMessage::new(self.to_string()))
+            }
+            DemandError::ResumeMismatch(_) => {
+                HttpResponse::BadRequest().json(ErrorMessage::new(self.to_string()))
+            }
             _ => HttpResponse::InternalServerError().json(ErrorMessage::new(self.to_string())),
         }
     }
@@ -122,13 +128,15 @@ impl ResponseError for QueryEventsError {
     fn error_response(&self) -> HttpResponse {
         let msg = ErrorMessage::new(self.to_string());
         match self {
-            QueryEventsError::TakeEvents(TakeEventsError::NotFound(_))
+            QueryEventsError::UnknownSubscription(_)
+            | QueryEventsError::TakeEvents(TakeEventsError::Unsubscribed(_))
             | QueryEventsError::TakeEvents(TakeEventsError::Expired(_)) => {
                 HttpResponse::NotFound().json(msg)
             }
             QueryEventsError::InvalidSubscriptionId(_) | QueryEventsError::InvalidMaxEvents(..) => {
                 HttpResponse::BadRequest().json(msg)
             }
+            QueryEventsError::Forbidden(_) => HttpResponse::Forbidden().json(msg),
             _ => HttpResponse::InternalServerError().json(msg),
         }
     }
@@ -155,6 +163,7 @@ impl ResponseError for RejectProposalError {
         let msg = ErrorMessage::new(self.to_string());
         match self {
             RejectProposalError::Validation(_) => HttpResponse::BadRequest().json(msg),
+            RejectProposalError::InvalidState(..) => HttpResponse::Conflict().json(msg),
             RejectProposalError::Gsb(_)
             | RejectProposalError::Get(_)
             | RejectProposalError::ChangeState(_)
@@ -198,6 +207,7 @@ impl ResponseError for AgreementError {
             AgreementError::UpdateState(_, e) => e.error_response(),
             AgreementError::NoNegotiations(_)
             | AgreementError::OwnProposal(..)
+            | AgreementError::ProposalRejected(..)
             | AgreementError::ProposalNotFound(..)
             | AgreementError::ProposalCountered(..)
             | AgreementError::InvalidId(..) => HttpResponse::BadRequest().json(msg),
@@ -67,14 +67,14 @@ async fn collect(
     market: Data<Arc<MarketService>>,
     path: Path<PathSubscription>,
     query: Query<QueryTimeoutMaxEvents>,
-    _id: Identity,
+    id: Identity,
 ) -> impl Responder {
     let subscription_id = path.into_inner().subscription_id;
     let timeout = query.timeout;
     let max_events = query.max_events;
     market
         .provider_engine
-        .query_events(&subscription_id, timeout, max_events)
+        .query_events(&subscription_id, timeout, max_events, &id)
         .await
         .log_err()
         .map(|events| HttpResponse::Ok().json(events))
@@ -70,7 +70,7 @@ pub async fn negotiate_agreement_with_ids(
     // but we should eat Event from queue.
     let events = prov_mkt
         .provider_engine
-        .query_events(&negotiation.offer_id, 2.0, Some(5))
+        .query_events(&negotiation.offer_id, 2.0, Some(5), prov_id)
         .await?;
     let _ = expect_agreement(events, "To approve #P").unwrap();
 
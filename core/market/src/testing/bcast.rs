@@ -33,10 +33,17 @@ struct BCastServiceInner {
 impl BCast for BCastService {
     fn add(&self, subscribe: net::local::Subscribe) {
         let mut me = self.inner.borrow_mut();
-        me.topics
+        let receivers = me
+            .topics
             .entry(subscribe.topic().to_owned())
-            .or_insert_with(Default::default)
-            .push(subscribe.endpoint().into())
+            .or_insert_with(Default::default);
+        // Restarted nodes subscribe the same endpoints again.
+        if !receivers
+            .iter()
+            .any(|endpoint| endpoint.as_ref() == subscribe.endpoint())
+        {
+            receivers.push(subscribe.endpoint().into())
+        }
     }
 
     fn resolve(&self, _node_id: &str, topic: &str) -> Vec<Arc<str>> {
@@ -3,7 +3,7 @@ use actix_service::Service as ActixService;
 use actix_web::{dev::ServiceResponse, test, App};
 use anyhow::{anyhow, bail, Context, Result};
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
 use ya_client::model::market::RequestorEvent;
@@ -25,7 +25,7 @@ use crate::db::dao::ProposalDao;
 use crate::db::model::{Demand, Offer, Proposal, ProposalId, SubscriptionId};
 use crate::identity::IdentityApi;
 use crate::matcher::error::{DemandError, QueryOfferError};
-use crate::matcher::EventsListeners;
+use crate::matcher::{EventsListeners, RawProposal};
 use crate::negotiation::error::*;
 use crate::protocol::callback::*;
 use crate::protocol::discovery::{builder::DiscoveryBuilder, error::*, message::*, Discovery};
@@ -201,6 +201,31 @@ impl MarketsNetwork {
             .await
     }
 
+    /// Replaces Market instance with new one working on the same database
+    /// and using the same identities. Emulates yagna restart.
+    pub async fn restart_market_instance(mut self, name: &str) -> Self {
+        self.break_networking_for(name).unwrap();
+
+        let idx = self
+            .nodes
+            .iter()
+            .position(|node| node.name == name)
+            .unwrap();
+        let node = self.nodes.remove(idx);
+
+        let db = self.create_database(name);
+        let market = Arc::new(
+            MarketService::new(
+                &db,
+                node.mock_identity.clone() as Arc<dyn IdentityApi>,
+                self.config.clone(),
+            )
+            .unwrap(),
+        );
+        self.add_node(name, node.mock_identity, MockNodeKind::Market(market))
+            .await
+    }
+
     pub async fn add_matcher_instance(self, name: &str) -> Self {
         let db = self.init_database(name);
 
@@ -543,12 +568,20 @@ pub trait MarketServiceExt {
         &self,
         proposal_id: &ProposalId,
     ) -> Result<Proposal, anyhow::Error>;
+    /// Queries Requestor events without checking Demand ownership.
     async fn query_events(
         &self,
         subscription_id: &SubscriptionId,
         timeout: f32,
         max_events: Option<i32>,
     ) -> Result<Vec<RequestorEvent>, QueryEventsError>;
+    /// Injects Proposals for given Demand bypassing the matcher.
+    /// Duplicated Offers will produce only one Proposal.
+    async fn inject_proposals(
+        &self,
+        offers: &[Offer],
+        demand_id: &SubscriptionId,
+    ) -> Result<Vec<ProposalId>, anyhow::Error>;
 }
 
 #[async_trait::async_trait]
@@ -585,9 +618,33 @@ impl MarketServiceExt for MarketService {
         max_events: Option<i32>,
     ) -> Result<Vec<RequestorEvent>, QueryEventsError> {
         self.requestor_engine
-            .query_events(subscription_id, timeout, max_events)
+            .query_events_unchecked(subscription_id, timeout, max_events)
             .await
     }
+
+    async fn inject_proposals(
+        &self,
+        offers: &[Offer],
+        demand_id: &SubscriptionId,
+    ) -> Result<Vec<ProposalId>, anyhow::Error> {
+        let demand = self.matcher.store.get_demand(demand_id).await?;
+
+        let mut injected = HashSet::new();
+        let proposals = offers
+            .iter()
+            .filter(|offer| injected.insert(offer.id.clone()))
+            .map(|offer| RawProposal {
+                offer: offer.clone(),
+                demand: demand.clone(),
+            })
+            .collect();
+
+        Ok(self
+            .requestor_engine
+            .common
+            .generate_proposals(proposals)
+            .await?)
+    }
 }
 
 pub mod default {
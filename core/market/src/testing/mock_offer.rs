@@ -103,6 +103,15 @@ pub mod client {
         )
     }
 
+    /// The same as `sample_offer`, but with different node name, so Offers
+    /// subscribed by the same Provider won't be treated as duplicates.
+    #[allow(unused)]
+    pub fn sample_offer_named(name: &str) -> NewOffer {
+        let mut offer = sample_offer();
+        offer.properties["golem"]["node.id.name"] = serde_json::json!(name);
+        offer
+    }
+
     /// Use this Offer, if you want to negotiate with only subset of nodes.
     /// Set match_on parameter to unique string for this subset.
     #[allow(unused)]
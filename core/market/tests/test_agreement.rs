@@ -1,6 +1,7 @@
 use actix_web::{http::StatusCode, test, web::Bytes};
 use chrono::{Duration, Utc};
 
+use ya_client::model::market::agreement::State as ClientAgreementState;
 use ya_core_model::{market, Role};
 use ya_market::assert_err_eq;
 use ya_market::testing::{
@@ -887,6 +888,81 @@ async fn cant_promote_initial_proposal() {
     }
 }
 
+/// Agreement created through MarketService from negotiated Proposal
+/// should wait in `Proposal` state for confirmation.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_market_create_agreement() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_market = network.get_market(REQ_NAME);
+    let req_id = network.get_default_id(REQ_NAME);
+
+    let agreement_id = req_market
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+
+    let agreement = req_market
+        .get_agreement(&agreement_id, &req_id)
+        .await
+        .unwrap();
+    assert_eq!(agreement.state, ClientAgreementState::Proposal);
+}
+
+/// Requestor can't create Agreement from Proposal, he rejected.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn cant_promote_rejected_proposal() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let NegotiationHelper {
+        proposal_id,
+        demand_id,
+        ..
+    } = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap();
+
+    let req_market = network.get_market(REQ_NAME);
+    let req_id = network.get_default_id(REQ_NAME);
+    req_market
+        .requestor_engine
+        .reject_proposal(&demand_id, &proposal_id, &req_id, None)
+        .await
+        .unwrap();
+
+    match req_market
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::hours(1),
+        )
+        .await
+    {
+        Err(AgreementError::ProposalRejected(id)) => assert_eq!(id, proposal_id),
+        e => panic!("Expected AgreementError::ProposalRejected, got: {:?}", e),
+    }
+}
+
 /// Requestor can promote only last proposal in negotiation chain.
 /// If negotiations were more advanced, `create_agreement` will end with error.
 #[cfg_attr(not(feature = "test-suite"), ignore)]
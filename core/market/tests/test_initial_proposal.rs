@@ -3,14 +3,21 @@ use ya_client::model::market::proposal::State;
 use ya_market::assert_err_eq;
 use ya_market::testing::agreement_utils::{gen_reason, negotiate_agreement};
 use ya_market::testing::events_helper::{requestor, ClientProposalHelper};
-use ya_market::testing::mock_offer::client::{sample_demand, sample_offer};
+use ya_market::testing::mock_identity::generate_identity;
+use ya_market::testing::mock_offer::client::{sample_demand, sample_offer, sample_offer_named};
+use ya_market::testing::{
+    mock_offer, Config, Demand, Offer, ProposalId, ProposalScorer, ProposalsOrder,
+};
 use ya_market::testing::{MarketServiceExt, MarketsNetwork, Owner};
 use ya_market::testing::{QueryEventsError, TakeEventsError};
 use ya_market::MarketService;
 
 use chrono::Utc;
+use futures::StreamExt;
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::time::Duration;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
 const REQ_NAME: &str = "Node-1";
 const PROV_NAME: &str = "Node-2";
@@ -29,8 +36,10 @@ async fn test_query_events_non_existent_subscription() {
 
     // We expect that no events are available for non existent subscription.
     let result = market1.query_events(&non_existent_id, 1.2, Some(5)).await;
-
-    assert_err_eq!(TakeEventsError::NotFound(non_existent_id), result);
+    assert_err_eq!(
+        QueryEventsError::UnknownSubscription(non_existent_id.clone()),
+        result
+    );
 }
 
 /// Initial proposal generated by market should be available at
@@ -71,6 +80,8 @@ async fn test_query_initial_proposal() {
 
     assert_eq!(proposal.prev_proposal_id, None);
     assert_eq!(proposal.state, State::Initial);
+    // Initial Proposal is issued by Provider, who subscribed the Offer.
+    assert_eq!(proposal.issuer_id, identity2.identity);
 
     // We expect that, the same event won't be available again.
     let events = market1
@@ -98,10 +109,10 @@ async fn test_query_multiple_events() {
         .subscribe_demand(&sample_demand(), &identity2)
         .await
         .unwrap();
-    let offer = sample_offer();
-    market1.subscribe_offer(&offer, &identity1).await.unwrap();
-    market1.subscribe_offer(&offer, &identity1).await.unwrap();
-    market1.subscribe_offer(&offer, &identity1).await.unwrap();
+    for idx in 0..3 {
+        let offer = sample_offer_named(&format!("provider-{}", idx));
+        market1.subscribe_offer(&offer, &identity1).await.unwrap();
+    }
 
     // We expect that 3 proposal will be available as requestor event.
     let mut events = vec![];
@@ -130,8 +141,762 @@ async fn test_query_multiple_events() {
         .query_events(&demand_id, 0.2, Some(5))
         .await
         .unwrap();
-
-    assert_eq!(events.len(), 0);
+
+    assert_eq!(events.len(), 0);
+}
+
+/// The same Offer subscribed many times by one Provider should result
+/// in single Proposal for Demand. Offers with different content still match.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_duplicated_offers_generate_single_proposal() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity2)
+        .await
+        .unwrap();
+    let offer = sample_offer();
+    market1.subscribe_offer(&offer, &identity1).await.unwrap();
+    market1.subscribe_offer(&offer, &identity1).await.unwrap();
+
+    let events = market1
+        .query_events(&demand_id, 1.0, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+
+    // Offer with different content should generate new Proposal.
+    market1
+        .subscribe_offer(&sample_offer_named("other-provider"), &identity1)
+        .await
+        .unwrap();
+
+    let events = market1
+        .query_events(&demand_id, 1.0, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+/// Stats should count generated Proposals and delivered events.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_market_stats() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = (0..5)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let stats = market1.market_stats();
+    assert_eq!(stats.proposals_generated, 5);
+    assert_eq!(stats.events_delivered, 0);
+
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(3))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(market1.market_stats().events_delivered, 3);
+
+    // Events left in queue are dropped with subscription.
+    market1
+        .unsubscribe_demand(&demand_id, &identity1)
+        .await
+        .unwrap();
+    let stats = market1.market_stats();
+    assert_eq!(stats.proposals_generated, 5);
+    assert_eq!(stats.events_delivered, 3);
+    assert_eq!(stats.events_expired, 2);
+}
+
+/// Without maxEvents specified, query_events should return at most
+/// configured default number of events.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_default_max_events() {
+    let mut config = Config::default();
+    config.events.max_events_default = 3;
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = (0..5)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let events = market1.query_events(&demand_id, 0.0, None).await.unwrap();
+    assert_eq!(events.len(), 3);
+
+    let events = market1.query_events(&demand_id, 0.0, None).await.unwrap();
+    assert_eq!(events.len(), 2);
+}
+
+/// Requestor can't query events of Demand subscribed by other identity.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_other_identity() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    market1
+        .inject_proposals(&[mock_offer::sample_offer()], &demand_id)
+        .await
+        .unwrap();
+
+    let result = market1
+        .requestor_engine
+        .query_events(&demand_id, 0.0, Some(5), &identity2)
+        .await;
+    assert_err_eq!(QueryEventsError::Forbidden(demand_id.clone()), result);
+
+    // Every other way of accessing events is guarded the same way.
+    let engine = &market1.requestor_engine;
+    let forbidden = QueryEventsError::Forbidden(demand_id.clone());
+    let result = engine
+        .query_events_after(&demand_id, 0.0, Some(5), None, &identity2)
+        .await;
+    assert_err_eq!(forbidden, result);
+    let result = engine
+        .query_scored_events(&demand_id, 0.0, Some(5), &identity2)
+        .await;
+    assert_err_eq!(forbidden, result);
+    let result = engine
+        .query_events_filtered(&demand_id, 0.0, Some(5), None, &identity2)
+        .await;
+    assert_err_eq!(forbidden, result);
+    let result = engine.count_pending_events(&demand_id, &identity2).await;
+    assert_err_eq!(forbidden, result);
+    let result = Box::pin(engine.event_stream(&demand_id, &identity2))
+        .next()
+        .await
+        .unwrap();
+    assert_err_eq!(forbidden, result);
+
+    // Events weren't consumed by forbidden calls.
+    let events = market1
+        .requestor_engine
+        .query_events(&demand_id, 0.0, Some(5), &identity1)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+/// Provider can't query events of Offer subscribed by other identity.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_offer_events_other_identity() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let offer_id = market1
+        .subscribe_offer(&sample_offer(), &identity1)
+        .await
+        .unwrap();
+
+    let result = market1
+        .provider_engine
+        .query_events(&offer_id, 0.0, Some(5), &identity2)
+        .await;
+    assert_err_eq!(QueryEventsError::Forbidden(offer_id.clone()), result);
+    let result = market1
+        .provider_engine
+        .count_pending_events(&offer_id, &identity2)
+        .await;
+    assert_err_eq!(QueryEventsError::Forbidden(offer_id.clone()), result);
+
+    let events = market1
+        .provider_engine
+        .query_events(&offer_id, 0.0, Some(5), &identity1)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+}
+
+/// Matching the same Offer again (for example after it was re-broadcasted)
+/// shouldn't generate second initial Proposal.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rematched_offer_generates_single_proposal() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity2)
+        .await
+        .unwrap();
+    let offer_id = market1
+        .subscribe_offer(&sample_offer(), &identity1)
+        .await
+        .unwrap();
+
+    let events = market1
+        .query_events(&demand_id, 1.0, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+
+    // The same Offer matched second time.
+    let offer = market1.get_offer(&offer_id).await.unwrap();
+    let generated = market1
+        .inject_proposals(&[offer], &demand_id)
+        .await
+        .unwrap();
+    assert_eq!(generated.len(), 0);
+
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+}
+
+/// Event stream should yield Proposals in order they were generated
+/// and end, when Demand is unsubscribed.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_event_stream() {
+    let mut config = Config::default();
+    config.events.proposals_order = ProposalsOrder::InsertionOrder;
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let market1c = market1.clone();
+    let demand_idc = demand_id.clone();
+    let identity1c = identity1.clone();
+    let stream_handle = tokio::spawn(async move {
+        market1c
+            .requestor_engine
+            .event_stream(&demand_idc, &identity1c)
+            .map(|event| match event.unwrap() {
+                RequestorEvent::ProposalEvent { proposal, .. } => proposal.proposal_id,
+                e => panic!("Expected ProposalEvent, got: {:?}", e),
+            })
+            .collect::<Vec<_>>()
+            .await
+    });
+
+    let mut injected = vec![];
+    for _ in 0..3 {
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+        let ids = market1
+            .inject_proposals(&[mock_offer::sample_offer()], &demand_id)
+            .await
+            .unwrap();
+        injected.extend(ids.into_iter().map(|id| id.to_string()));
+    }
+
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    market1
+        .unsubscribe_demand(&demand_id, &identity1)
+        .await
+        .unwrap();
+
+    // Protect from eternal waiting.
+    let received = tokio::time::timeout(Duration::from_millis(500), stream_handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received, injected);
+}
+
+/// Events not yielded by event stream should stay in queue, when the stream
+/// is dropped.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_event_stream_dropped_mid_batch() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    market1
+        .inject_proposals(
+            &[
+                mock_offer::sample_offer(),
+                mock_offer::sample_offer(),
+                mock_offer::sample_offer(),
+            ],
+            &demand_id,
+        )
+        .await
+        .unwrap();
+
+    {
+        let stream = market1
+            .requestor_engine
+            .event_stream(&demand_id, &identity1);
+        futures::pin_mut!(stream);
+        stream.next().await.unwrap().unwrap();
+    }
+
+    let events = market1
+        .query_events(&demand_id, 0.0, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+}
+
+/// Query_events shouldn't wait for timeout, if events are already available.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_full_batch_returns_immediately() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = (0..5)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let start = Instant::now();
+    let events = market1
+        .query_events(&demand_id, 60.0, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 5);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+/// Bulk injected Proposals should be returned by query_events exactly once.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_injected_proposals() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = (0..50)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    let proposal_ids = market1.inject_proposals(&offers, &demand_id).await.unwrap();
+    assert_eq!(proposal_ids.len(), 50);
+
+    let mut events = vec![];
+    for _ in 0..5 {
+        let mut batch = market1
+            .query_events(&demand_id, 0.2, Some(10))
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 10);
+        events.append(&mut batch);
+    }
+
+    let received = events
+        .into_iter()
+        .map(|event| match event {
+            RequestorEvent::ProposalEvent { proposal, .. } => {
+                proposal.proposal_id.parse::<ProposalId>().unwrap()
+            }
+            _ => panic!("ProposalEvent expected, but got {:?}", event),
+        })
+        .collect::<HashSet<_>>();
+    assert_eq!(received.len(), 50);
+    assert_eq!(received, proposal_ids.into_iter().collect::<HashSet<_>>());
+
+    // All events were drained.
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(10))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+}
+
+/// Query events using cursor shouldn't return duplicates and shouldn't skip events.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_after_cursor() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let mut cursor = Utc::now() - chrono::Duration::seconds(1);
+    let offers = (0..3)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    let proposal_ids = market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let engine = &market1.requestor_engine;
+    let mut received = vec![];
+    loop {
+        let events = engine
+            .query_events_after(&demand_id, 0.2, Some(1), Some(cursor), &identity1)
+            .await
+            .unwrap();
+        if events.is_empty() {
+            break;
+        }
+
+        // Cursor based query doesn't consume events. We get the same
+        // events again, until we move cursor forward.
+        let repeated = engine
+            .query_events_after(&demand_id, 0.2, Some(1), Some(cursor), &identity1)
+            .await
+            .unwrap();
+        assert_eq!(repeated.len(), events.len());
+
+        for event in events {
+            match event {
+                RequestorEvent::ProposalEvent {
+                    proposal,
+                    event_date,
+                } => {
+                    assert!(event_date > cursor);
+                    cursor = event_date;
+                    received.push(proposal.proposal_id.parse::<ProposalId>().unwrap());
+                }
+                _ => panic!("ProposalEvent expected, but got {:?}", event),
+            }
+        }
+    }
+
+    assert_eq!(received.len(), 3);
+    assert_eq!(
+        received.into_iter().collect::<HashSet<_>>(),
+        proposal_ids.into_iter().collect::<HashSet<_>>()
+    );
+
+    // All events were acknowledged by moving cursor.
+    assert_eq!(
+        engine
+            .count_pending_events(&demand_id, &identity1)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+/// Counting pending events shouldn't consume them.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_count_pending_events() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = vec![mock_offer::sample_offer(), mock_offer::sample_offer()];
+    market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let engine = &market1.requestor_engine;
+    assert_eq!(
+        engine
+            .count_pending_events(&demand_id, &identity1)
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        engine
+            .count_pending_events(&demand_id, &identity1)
+            .await
+            .unwrap(),
+        2
+    );
+
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        engine
+            .count_pending_events(&demand_id, &identity1)
+            .await
+            .unwrap(),
+        1
+    );
+
+    let events = market1
+        .query_events(&demand_id, 0.2, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        engine
+            .count_pending_events(&demand_id, &identity1)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // The same error as returned by query_events for unsubscribed Demand.
+    market1
+        .unsubscribe_demand(&demand_id, &identity1)
+        .await
+        .unwrap();
+    let result = engine.count_pending_events(&demand_id, &identity1).await;
+    assert_err_eq!(TakeEventsError::Unsubscribed(demand_id), result);
+}
+
+/// With price ordering enabled, query_events should return cheapest Proposals first.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_price_ascending() {
+    let mut config = Config::default();
+    config.events.proposals_order = ProposalsOrder::PriceAscending {
+        usage_counter: "golem.usage.duration_sec",
+    };
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let proposal_ids = market1
+        .inject_proposals(&priced_offers(&[0.5, 0.1, 0.3]), &demand_id)
+        .await
+        .unwrap();
+
+    let mut received = vec![];
+    for _ in 0..3 {
+        let events = market1
+            .query_events(&demand_id, 0.2, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            RequestorEvent::ProposalEvent { proposal, .. } => {
+                received.push(proposal.proposal_id.parse::<ProposalId>().unwrap())
+            }
+            event => panic!("ProposalEvent expected, but got {:?}", event),
+        }
+    }
+
+    assert_eq!(
+        received,
+        vec![
+            proposal_ids[1].clone(),
+            proposal_ids[2].clone(),
+            proposal_ids[0].clone()
+        ]
+    );
+}
+
+/// With insertion ordering enabled, concurrent callers draining the same
+/// subscription should each get Proposals in the order they were generated.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_insertion_order() {
+    let mut config = Config::default();
+    config.events.proposals_order = ProposalsOrder::InsertionOrder;
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let offers = (0..30)
+        .map(|_| mock_offer::sample_offer())
+        .collect::<Vec<_>>();
+    let proposal_ids = market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let mut callers = vec![];
+    for _ in 0..2 {
+        let market = market1.clone();
+        let demand_id = demand_id.clone();
+        callers.push(tokio::spawn(async move {
+            let mut received = vec![];
+            loop {
+                let events = market.query_events(&demand_id, 0.2, Some(3)).await.unwrap();
+                if events.is_empty() {
+                    return received;
+                }
+                for event in events {
+                    match event {
+                        RequestorEvent::ProposalEvent { proposal, .. } => {
+                            received.push(proposal.proposal_id.parse::<ProposalId>().unwrap())
+                        }
+                        event => panic!("ProposalEvent expected, but got {:?}", event),
+                    }
+                }
+            }
+        }));
+    }
+
+    let mut merged = vec![];
+    for caller in callers {
+        let received = caller.await.unwrap();
+        let positions = received
+            .iter()
+            .map(|id| proposal_ids.iter().position(|p| p == id).unwrap())
+            .collect::<Vec<_>>();
+        let mut sorted = positions.clone();
+        sorted.sort();
+        assert_eq!(positions, sorted);
+        merged.extend(positions);
+    }
+
+    merged.sort();
+    assert_eq!(merged, (0..proposal_ids.len()).collect::<Vec<_>>());
+}
+
+/// Timeouts longer than configured limit should be clamped.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_timeout_cap() {
+    let mut config = Config::default();
+    config.events.max_timeout = Duration::from_millis(500);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    for timeout in vec![3600.0, f32::MAX, f32::INFINITY] {
+        let start = Instant::now();
+        let events = tokio::time::timeout(
+            Duration::from_millis(1500),
+            market1.query_events(&demand_id, timeout, Some(5)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(events.len(), 0);
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
 }
 
 /// Query_events should hang on endpoint until event will come
@@ -180,6 +945,100 @@ async fn test_query_events_timeout() {
         .unwrap();
 }
 
+/// Dropped query_events call shouldn't block later callers or take
+/// events, that would never be delivered.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_dropped_caller() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    // Caller disconnects, before any event comes.
+    let dropped = tokio::time::timeout(
+        Duration::from_millis(50),
+        market1.query_events(&demand_id, 1.2, Some(5)),
+    )
+    .await;
+    assert!(dropped.is_err());
+
+    market1
+        .subscribe_offer(&sample_offer(), &identity2)
+        .await
+        .unwrap();
+
+    let events = tokio::time::timeout(
+        Duration::from_millis(1500),
+        market1.query_events(&demand_id, 1.2, Some(5)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+/// Caller can be dropped at any point, also when events were already taken
+/// from database. Taken events must be put back to queue then.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_dropped_during_take() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    // Each round lets query_events go one step further, before it is dropped,
+    // until it manages to return events on its own.
+    let mut polls = 0;
+    loop {
+        let offers = (0..3)
+            .map(|_| mock_offer::sample_offer())
+            .collect::<Vec<_>>();
+        market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+        let mut query = Box::pin(market1.query_events(&demand_id, 1.2, Some(5)));
+        let mut completed = None;
+        for _ in 0..polls {
+            if let Poll::Ready(result) = futures::poll!(&mut query) {
+                completed = Some(result.unwrap());
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(1)).await;
+        }
+        drop(query);
+
+        if let Some(events) = completed {
+            assert_eq!(events.len(), 3);
+            break;
+        }
+
+        let events = market1
+            .query_events(&demand_id, 1.2, Some(5))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 3, "Events lost after {} polls", polls);
+        polls += 1;
+    }
+}
+
 /// Query events will return before timeout will elapse, if Demand will be unsubscribed.
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
@@ -202,7 +1061,7 @@ async fn test_query_events_unsubscribe_notification() {
     // We set timeout and we expect that function will wait until events will come.
     let query_handle = tokio::spawn(async move {
         match market1.query_events(&subscription_id, 1.2, Some(5)).await {
-            Err(QueryEventsError::TakeEvents(TakeEventsError::NotFound(id))) => {
+            Err(QueryEventsError::TakeEvents(TakeEventsError::Unsubscribed(id))) => {
                 assert_eq!(id, subscription_id);
             }
             x => panic!("Expected Unsubscribed error, but got {:?}", x),
@@ -225,6 +1084,157 @@ async fn test_query_events_unsubscribe_notification() {
         .unwrap();
 }
 
+/// Proposals expire together with Offer, they were generated from.
+/// Expired Proposals shouldn't be returned, but fresh ones should.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_expired_proposals_not_returned() {
+    let mut config = Config::default();
+    config.subscription.expired_proposals_sweep_interval = Duration::from_millis(100);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let expiration = Utc::now().naive_utc() + chrono::Duration::milliseconds(300);
+    let expiring = mock_offer::sample_offer_with_expiration(expiration);
+    let expired_ids = market1
+        .inject_proposals(&[expiring], &demand_id)
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    // Pruned events aren't counted as expired with subscription.
+    let stats = market1.market_stats();
+    assert_eq!(stats.proposals_pruned, 1);
+    assert_eq!(stats.events_expired, 0);
+
+    let events = market1.query_events(&demand_id, 0.0, None).await.unwrap();
+    assert_eq!(events.len(), 0);
+    // Background sweep removed Proposal, that no one reacted to.
+    assert!(market1.get_proposal_from_db(&expired_ids[0]).await.is_err());
+
+    market1
+        .inject_proposals(&[mock_offer::sample_offer()], &demand_id)
+        .await
+        .unwrap();
+    let events = market1.query_events(&demand_id, 0.0, None).await.unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+/// Proposal can't outlive the Demand it was matched with.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_proposal_expires_with_demand() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    let demand = market1.get_demand(&demand_id).await.unwrap();
+
+    let offer_expiration = demand.expiration_ts + chrono::Duration::hours(1);
+    let offer = mock_offer::sample_offer_with_expiration(offer_expiration);
+    let proposal_ids = market1
+        .inject_proposals(&[offer], &demand_id)
+        .await
+        .unwrap();
+
+    let proposal = market1
+        .get_proposal_from_db(&proposal_ids[0])
+        .await
+        .unwrap();
+    assert_eq!(proposal.body.expiration_ts, demand.expiration_ts);
+}
+
+/// Subscription expiration should wake up waiting query_events call
+/// the same way as unsubscribe does.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_expiration_notification() {
+    let mut config = Config::default();
+    config.subscription.default_ttl = chrono::Duration::milliseconds(500);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let subscription_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    // Query timeout is much longer than subscription TTL.
+    let start = Instant::now();
+    let result = tokio::time::timeout(
+        Duration::from_millis(1500),
+        market1.query_events(&subscription_id, 5.0, Some(5)),
+    )
+    .await
+    .unwrap();
+
+    assert_err_eq!(TakeEventsError::Expired(subscription_id), result);
+    assert!(start.elapsed() >= Duration::from_millis(400));
+}
+
+/// Provider's query_events can't outlive Offer either. Wait time should be
+/// clamped to Offer expiration instead of requested timeout.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_offer_expiration_notification() {
+    let mut config = Config::default();
+    config.subscription.default_ttl = chrono::Duration::milliseconds(500);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let offer_id = market1
+        .subscribe_offer(&sample_offer(), &identity1)
+        .await
+        .unwrap();
+
+    let start = Instant::now();
+    let result = tokio::time::timeout(
+        Duration::from_millis(1500),
+        market1
+            .provider_engine
+            .query_events(&offer_id, 5.0, Some(5), &identity1),
+    )
+    .await
+    .unwrap();
+
+    assert_err_eq!(TakeEventsError::Expired(offer_id), result);
+    assert!(start.elapsed() >= Duration::from_millis(400));
+}
+
 /// Tests if query events returns proper error on invalid input
 /// or unsubscribed demand.
 #[cfg_attr(not(feature = "test-suite"), ignore)]
@@ -258,7 +1268,7 @@ async fn test_query_events_edge_cases() {
         Duration::from_millis(20),
         market1
             .requestor_engine
-            .query_events(&demand_id, -5.0, None),
+            .query_events(&demand_id, -5.0, None, &identity2),
     )
     .await
     .unwrap()
@@ -279,6 +1289,14 @@ async fn test_query_events_edge_cases() {
     let result = market1.query_events(&demand_id, 0.2, Some(0)).await;
     assert_err_eq!(QueryEventsError::InvalidMaxEvents(0, 100), result);
 
+    // maxEvents above configured upper bound should be rejected, so nobody
+    // can force market to collect unbounded batch of events.
+    let result = market1.query_events(&demand_id, 0.2, Some(101)).await;
+    assert_err_eq!(QueryEventsError::InvalidMaxEvents(101, 100), result);
+
+    let result = market1.query_events(&demand_id, 0.2, Some(i32::MAX)).await;
+    assert_err_eq!(QueryEventsError::InvalidMaxEvents(i32::MAX, 100), result);
+
     // Query events returns error, if Demand was unsubscribed.
     market1
         .unsubscribe_demand(&demand_id, &identity2)
@@ -286,7 +1304,7 @@ async fn test_query_events_edge_cases() {
         .unwrap();
 
     let result = market1.query_events(&demand_id, 0.0, None).await;
-    assert_err_eq!(TakeEventsError::NotFound(demand_id), result);
+    assert_err_eq!(TakeEventsError::Unsubscribed(demand_id), result);
 }
 
 /// Generate proposals for multiple subscriptions. Query events should return
@@ -380,11 +1398,11 @@ async fn test_simultaneous_query_events() {
     // Generate 2 proposals. Each waiting query events call will take an event.
     tokio::time::delay_for(Duration::from_millis(50)).await;
     market1
-        .subscribe_offer(&sample_offer(), &identity1)
+        .subscribe_offer(&sample_offer_named("provider-1"), &identity1)
         .await
         .unwrap();
     market1
-        .subscribe_offer(&sample_offer(), &identity1)
+        .subscribe_offer(&sample_offer_named("provider-2"), &identity1)
         .await
         .unwrap();
 
@@ -420,6 +1438,53 @@ async fn test_simultaneous_query_events() {
     assert_eq!(events.len(), 0);
 }
 
+/// Callers waiting for events should be served in order in which
+/// they started waiting.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_fifo_order() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let mut waiters = vec![];
+    for _ in 0..3 {
+        let market = market1.clone();
+        let demand_id = demand_id.clone();
+        waiters.push(tokio::spawn(async move {
+            market.query_events(&demand_id, 1.0, Some(5)).await.unwrap()
+        }));
+        // Make sure waiters start waiting in known order.
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+    }
+
+    market1
+        .inject_proposals(&[mock_offer::sample_offer()], &demand_id)
+        .await
+        .unwrap();
+
+    let mut results = vec![];
+    for waiter in waiters {
+        results.push(
+            tokio::time::timeout(Duration::from_millis(1500), waiter)
+                .await
+                .unwrap()
+                .unwrap()
+                .len(),
+        );
+    }
+    assert_eq!(results, vec![1, 0, 0]);
+}
+
 /// Run two query events in the same time.
 /// The same event shouldn't be returned twice.
 #[cfg_attr(not(feature = "test-suite"), ignore)]
@@ -508,7 +1573,7 @@ async fn test_counter_initial_proposal() {
     // We expect that proposal will be available as event.
     let events = market1
         .requestor_engine
-        .query_events(&subscription_id, 1.0, Some(5))
+        .query_events(&subscription_id, 1.0, Some(5), &identity1)
         .await
         .unwrap();
 
@@ -536,7 +1601,7 @@ async fn test_counter_initial_proposal() {
     let new_proposal_id = new_proposal_id.translate(Owner::Provider);
     let events = market1
         .provider_engine
-        .query_events(&offer_id, 1.5, Some(5))
+        .query_events(&offer_id, 1.5, Some(5), &identity2)
         .await
         .unwrap();
     assert_eq!(events.len(), 1);
@@ -664,3 +1729,138 @@ async fn test_respawn_proposal_after_terminate_by_provider() -> anyhow::Result<(
     assert_eq!(proposal.state, State::Initial);
     Ok(())
 }
+
+/// Scores Offers by negated linear price, so cheaper Offers rank higher.
+struct PriceScorer;
+
+impl ProposalScorer for PriceScorer {
+    fn score(&self, offer: &Offer, _demand: &Demand) -> f64 {
+        let properties: serde_json::Value = serde_json::from_str(&offer.properties).unwrap();
+        let price: f64 = properties["golem.com.pricing.model.linear.coeffs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|coeff| coeff.as_f64())
+            .sum();
+        -price
+    }
+}
+
+/// Offers priced per second of duration. Usage vector is set, so price
+/// coefficients can be matched with usage counters.
+fn priced_offers(prices: &[f64]) -> Vec<Offer> {
+    prices
+        .iter()
+        .map(|price| {
+            let mut offer = sample_offer();
+            offer.properties["golem"]["com.usage.vector"] =
+                serde_json::json!(["golem.usage.duration_sec", "golem.usage.cpu_sec"]);
+            offer.properties["golem"]["com.pricing.model.linear.coeffs"] =
+                serde_json::json!([price, 0.0, 0.0]);
+            let creation_ts = Utc::now().naive_utc();
+            let expiration_ts = creation_ts + chrono::Duration::hours(1);
+            Offer::from_new(&offer, &generate_identity(""), creation_ts, expiration_ts).unwrap()
+        })
+        .collect()
+}
+
+/// Initial Proposals should be annotated with score computed by
+/// ProposalScorer. Without scorer, Proposals have no score.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_scored_events() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    // Default behavior: no scores.
+    market1
+        .inject_proposals(&priced_offers(&[0.2]), &demand_id)
+        .await
+        .unwrap();
+    let events = market1
+        .requestor_engine
+        .query_scored_events(&demand_id, 0.2, Some(5), &identity1)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].score, None);
+
+    market1.set_proposal_scorer(Some(Arc::new(PriceScorer)));
+
+    let offers = priced_offers(&[0.5, 0.1, 0.3]);
+    let proposal_ids = market1.inject_proposals(&offers, &demand_id).await.unwrap();
+
+    let events = market1
+        .requestor_engine
+        .query_scored_events(&demand_id, 0.2, Some(5), &identity1)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 3);
+
+    let mut scores = vec![];
+    for scored in events.iter() {
+        match &scored.event {
+            RequestorEvent::ProposalEvent { proposal, .. } => {
+                let id = proposal.proposal_id.parse::<ProposalId>().unwrap();
+                let idx = proposal_ids.iter().position(|p| p == &id).unwrap();
+                scores.push((idx, scored.score));
+            }
+            event => panic!("ProposalEvent expected, but got {:?}", event),
+        }
+    }
+    scores.sort_by_key(|(idx, _)| *idx);
+    assert_eq!(
+        scores,
+        vec![(0, Some(-0.5)), (1, Some(-0.1)), (2, Some(-0.3))]
+    );
+}
+
+/// Events are stored in database, so Proposals generated before restart
+/// should be still available after restart.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_after_restart() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    let offers = priced_offers(&[0.1]);
+    let proposal_ids = market1.inject_proposals(&offers, &demand_id).await.unwrap();
+    assert_eq!(proposal_ids.len(), 1);
+    drop(market1);
+
+    let network = network.restart_market_instance("Node-1").await;
+    let market1 = network.get_market("Node-1");
+
+    let events = market1
+        .requestor_engine
+        .query_events(&demand_id, 0.2, Some(5), &identity1)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        RequestorEvent::ProposalEvent { proposal, .. } => assert_eq!(
+            proposal.proposal_id.parse::<ProposalId>().unwrap(),
+            proposal_ids[0]
+        ),
+        event => panic!("ProposalEvent expected, but got {:?}", event),
+    }
+}
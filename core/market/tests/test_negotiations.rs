@@ -1,14 +1,17 @@
-use ya_client::model::market::{proposal::State, RequestorEvent};
+use ya_client::model::market::{proposal::State, ProviderEvent, RequestorEvent};
 use ya_market::testing::{
     events_helper::{provider, requestor, ClientProposalHelper},
+    mock_offer,
     mock_offer::client::{not_matching_demand, not_matching_offer, sample_demand, sample_offer},
     mock_offer::flatten_json,
-    negotiation::error::{CounterProposalError, RemoteProposalError},
+    negotiation::error::{CounterProposalError, RejectProposalError, RemoteProposalError},
     proposal_util::{exchange_draft_proposals, NegotiationHelper},
-    wait_for_bcast, MarketServiceExt, MarketsNetwork, Owner, ProposalError, ProposalState,
-    ProposalValidationError, SaveProposalError,
+    wait_for_bcast, EventKind, MarketServiceExt, MarketsNetwork, Owner, ProposalError,
+    ProposalState, ProposalValidationError, SaveProposalError,
 };
 
+use std::time::{Duration, Instant};
+
 /// Test countering initial and draft proposals on both Provider and Requestor side.
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
@@ -728,7 +731,7 @@ async fn test_reject_negotiations_same_identity() {
     // We expect, that there will be no Proposals.
     let events = market1
         .requestor_engine
-        .query_events(&demand_id, 3.0, Some(5))
+        .query_events(&demand_id, 3.0, Some(5), &identity1)
         .await
         .unwrap();
     assert_eq!(events.len(), 0);
@@ -776,7 +779,7 @@ async fn test_reject_initial_offer() {
 
     req_mkt
         .requestor_engine
-        .query_events(&demand_id, 1.2, Some(5))
+        .query_events(&demand_id, 1.2, Some(5), &req_id)
         .await
         .map_err(|e| panic!("Expected Ok([]), got: {}\nDEBUG: {:?}", e.to_string(), e))
         .map(|events| assert_eq!(events.len(), 0))
@@ -844,7 +847,7 @@ async fn test_reject_demand() {
     // Requestor receives Rejection with reason
     req_mkt
         .requestor_engine
-        .query_events(&demand_id, 1.2, Some(5))
+        .query_events(&demand_id, 1.2, Some(5), &req_id)
         .await
         .map_err(|e| panic!("Expected Ok([ev]), got: {}\nDEBUG: {:?}", e.to_string(), e))
         .map(|events| {
@@ -930,7 +933,7 @@ async fn test_proposal_events_last() {
 
     let events = market1
         .requestor_engine
-        .query_events(&demand_id, 3.0, Some(5))
+        .query_events(&demand_id, 3.0, Some(5), &identity1)
         .await
         .unwrap();
     assert_eq!(events.len(), 2);
@@ -943,3 +946,276 @@ async fn test_proposal_events_last() {
         _ => assert!(false, format!("Invalid last event_type: {:#?}", events[0])),
     }
 }
+
+/// Rejecting Proposal for the second time should fail, and Provider
+/// should see only the first rejection with its reason.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_reject_already_rejected_proposal() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Req-1")
+        .await
+        .add_market_instance("Prov-1")
+        .await;
+
+    let req_mkt = network.get_market("Req-1");
+    let prov_mkt = network.get_market("Prov-1");
+
+    let req_id = network.get_default_id("Req-1");
+    let prov_id = network.get_default_id("Prov-1");
+
+    let demand = sample_demand();
+    let demand_id = req_mkt.subscribe_demand(&demand, &req_id).await.unwrap();
+    let offer_id = prov_mkt
+        .subscribe_offer(&sample_offer(), &prov_id)
+        .await
+        .unwrap();
+
+    let proposal0 = requestor::query_proposal(&req_mkt, &demand_id, "Initial #R")
+        .await
+        .unwrap();
+    let proposal0id = &proposal0.get_proposal_id().unwrap();
+
+    req_mkt
+        .requestor_engine
+        .counter_proposal(&demand_id, &proposal0id, &demand, &req_id)
+        .await
+        .unwrap();
+    let prov_proposal1 = provider::query_proposal(&prov_mkt, &offer_id, "Initial #P")
+        .await
+        .unwrap();
+    let prov_proposal1_id = prov_proposal1.get_proposal_id().unwrap();
+
+    // Provider counters, so Requestor has Draft Proposal to reject.
+    let prov_proposal2_id = prov_mkt
+        .provider_engine
+        .counter_proposal(&offer_id, &prov_proposal1_id, &sample_offer(), &prov_id)
+        .await
+        .unwrap();
+    let req_proposal2_id = prov_proposal2_id.clone().translate(Owner::Requestor);
+    requestor::query_proposal(&req_mkt, &demand_id, "Draft #R")
+        .await
+        .unwrap();
+
+    req_mkt
+        .requestor_engine
+        .reject_proposal(
+            &demand_id,
+            &req_proposal2_id,
+            &req_id,
+            Some("too expensive".into()),
+        )
+        .await
+        .unwrap();
+
+    let result = req_mkt
+        .requestor_engine
+        .reject_proposal(&demand_id, &req_proposal2_id, &req_id, Some("again".into()))
+        .await;
+    match result {
+        Err(RejectProposalError::InvalidState(id, ProposalState::Rejected)) => {
+            assert_eq!(id, req_proposal2_id)
+        }
+        e => panic!("Expected InvalidState error, got: {:?}", e),
+    }
+
+    let events = prov_mkt
+        .provider_engine
+        .query_events(&offer_id, 1.2, Some(5), &prov_id)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        ProviderEvent::ProposalRejectedEvent {
+            proposal_id,
+            reason,
+            ..
+        } => {
+            assert_eq!(proposal_id, &prov_proposal2_id.to_string());
+            assert_eq!(reason, &Some("too expensive".into()));
+        }
+        event => panic!("Expected ProposalRejectedEvent, got: {:?}", event),
+    }
+}
+
+/// Requestor asking only for rejections should get them, even if
+/// Proposals are waiting in queue. Proposals should stay for later calls.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_filtered_by_kind() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Req-1")
+        .await
+        .add_market_instance("Prov-1")
+        .await;
+
+    let req_mkt = network.get_market("Req-1");
+    let prov_mkt = network.get_market("Prov-1");
+
+    let req_id = network.get_default_id("Req-1");
+    let prov_id = network.get_default_id("Prov-1");
+
+    let demand = sample_demand();
+    let demand_id = req_mkt.subscribe_demand(&demand, &req_id).await.unwrap();
+    let offer_id = prov_mkt
+        .subscribe_offer(&sample_offer(), &prov_id)
+        .await
+        .unwrap();
+
+    let proposal0 = requestor::query_proposal(&req_mkt, &demand_id, "Initial #R")
+        .await
+        .unwrap();
+    let proposal0id = &proposal0.get_proposal_id().unwrap();
+    req_mkt
+        .requestor_engine
+        .counter_proposal(&demand_id, &proposal0id, &demand, &req_id)
+        .await
+        .unwrap();
+
+    let prov_proposal1 = provider::query_proposal(&prov_mkt, &offer_id, "Initial #P")
+        .await
+        .unwrap();
+    prov_mkt
+        .provider_engine
+        .reject_proposal(
+            &offer_id,
+            &prov_proposal1.get_proposal_id().unwrap(),
+            &prov_id,
+            Some("zima".into()),
+        )
+        .await
+        .unwrap();
+
+    req_mkt
+        .inject_proposals(
+            &[mock_offer::sample_offer(), mock_offer::sample_offer()],
+            &demand_id,
+        )
+        .await
+        .unwrap();
+
+    // Without filter rejection would be returned first and use part of max_events.
+    let events = req_mkt
+        .requestor_engine
+        .query_events_filtered(
+            &demand_id,
+            1.2,
+            Some(2),
+            Some(vec![EventKind::Proposal]),
+            &req_id,
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+    for event in events {
+        match event {
+            RequestorEvent::ProposalEvent { .. } => (),
+            event => panic!("Expected ProposalEvent, got: {:?}", event),
+        }
+    }
+
+    let events = req_mkt
+        .requestor_engine
+        .query_events_filtered(
+            &demand_id,
+            0.2,
+            Some(5),
+            Some(vec![EventKind::Agreement]),
+            &req_id,
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+
+    // Rejection wasn't consumed by filtered calls.
+    let events = req_mkt
+        .requestor_engine
+        .query_events_filtered(&demand_id, 1.2, Some(5), None, &req_id)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        RequestorEvent::ProposalRejectedEvent { reason, .. } => {
+            assert_eq!(reason, &Some("zima".into()))
+        }
+        event => panic!("Expected ProposalRejectedEvent, got: {:?}", event),
+    }
+}
+
+/// Provider query_events should wait for Proposal from Requestor, the same
+/// way as on Requestor side, and return empty list, if timeout elapses.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_provider_query_events_timeout() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await
+        .add_market_instance("Node-2")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let market2 = network.get_market("Node-2");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.get_default_id("Node-2");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    let offer_id = market2
+        .subscribe_offer(&sample_offer(), &identity2)
+        .await
+        .unwrap();
+
+    let proposal0 = requestor::query_proposal(&market1, &demand_id, "Initial #R")
+        .await
+        .unwrap();
+
+    // No Proposal was sent to Provider yet.
+    let start = Instant::now();
+    let events = market2
+        .provider_engine
+        .query_events(&offer_id, 0.3, Some(5), &identity2)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    let market2c = market2.clone();
+    let offer_idc = offer_id.clone();
+    let identity2c = identity2.clone();
+    let query_handle = tokio::spawn(async move {
+        let events = market2c
+            .provider_engine
+            .query_events(&offer_idc, 1.2, Some(5), &identity2c)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ProviderEvent::ProposalEvent { .. } => (),
+            e => panic!("Expected ProviderEvent::ProposalEvent, got: {:?}", e),
+        }
+    });
+
+    // Countering Proposal before timeout will elapse should wake up Provider.
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+    market1
+        .requestor_engine
+        .counter_proposal(
+            &demand_id,
+            &proposal0.get_proposal_id().unwrap(),
+            &sample_demand(),
+            &identity1,
+        )
+        .await
+        .unwrap();
+
+    // Protect from eternal waiting.
+    tokio::time::timeout(Duration::from_millis(1500), query_handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
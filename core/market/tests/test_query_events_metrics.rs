@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+use metrics::{Key, Recorder};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ya_market::assert_err_eq;
+use ya_market::testing::client::sample_demand;
+use ya_market::testing::{mock_offer, TakeEventsError};
+use ya_market::testing::{MarketServiceExt, MarketsNetwork};
+
+/// Keeps counters in memory, so tests can check what was recorded.
+#[derive(Default)]
+struct MemoryRecorder {
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryRecorder {
+    fn counter(&self, name: &str) -> u64 {
+        *self.counters.lock().unwrap().get(name).unwrap_or(&0)
+    }
+}
+
+fn key_to_string(key: &Key) -> String {
+    let labels = key
+        .labels()
+        .map(|label| format!("{}={}", label.key(), label.value()))
+        .collect::<Vec<_>>();
+    format!("{}{{{}}}", key.name(), labels.join(","))
+}
+
+impl Recorder for MemoryRecorder {
+    fn increment_counter(&self, key: Key, value: u64) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(key_to_string(&key))
+            .or_insert(0) += value;
+    }
+
+    fn update_gauge(&self, _key: Key, _value: i64) {}
+
+    fn record_histogram(&self, _key: Key, _value: u64) {}
+}
+
+lazy_static! {
+    /// Recorder can be set only once per process.
+    static ref RECORDER: &'static MemoryRecorder = {
+        let recorder = Box::leak(Box::new(MemoryRecorder::default()));
+        metrics::set_recorder(recorder).unwrap();
+        recorder
+    };
+}
+
+const SUCCESS: &str = "market.events.query{owner=requestor,outcome=success}";
+const EMPTY: &str = "market.events.query{owner=requestor,outcome=empty}";
+const UNSUBSCRIBED: &str = "market.events.query{owner=requestor,outcome=unsubscribed}";
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_query_events_metrics() {
+    let recorder = *RECORDER;
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    let success = recorder.counter(SUCCESS);
+    let empty = recorder.counter(EMPTY);
+    let unsubscribed = recorder.counter(UNSUBSCRIBED);
+
+    let events = market1
+        .query_events(&demand_id, 0.1, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 0);
+    assert_eq!(recorder.counter(SUCCESS), success);
+    assert_eq!(recorder.counter(EMPTY), empty + 1);
+    assert_eq!(recorder.counter(UNSUBSCRIBED), unsubscribed);
+
+    market1
+        .inject_proposals(&[mock_offer::sample_offer()], &demand_id)
+        .await
+        .unwrap();
+    let events = market1
+        .query_events(&demand_id, 0.1, Some(5))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(recorder.counter(SUCCESS), success + 1);
+    assert_eq!(recorder.counter(EMPTY), empty + 1);
+
+    market1
+        .unsubscribe_demand(&demand_id, &identity1)
+        .await
+        .unwrap();
+    let result = market1.query_events(&demand_id, 0.1, Some(5)).await;
+    assert_err_eq!(TakeEventsError::Unsubscribed(demand_id.clone()), result);
+    assert_eq!(recorder.counter(SUCCESS), success + 1);
+    assert_eq!(recorder.counter(EMPTY), empty + 1);
+    assert_eq!(recorder.counter(UNSUBSCRIBED), unsubscribed + 1);
+}
@@ -143,6 +143,32 @@ async fn test_rest_invalid_subscription_id_should_return_400() {
     );
 }
 
+/// Malformed subscription id is rejected, before query_events is called.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_query_events_invalid_subscription_id() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+    let mut app = network.get_rest_app("Node-1").await;
+
+    let req = test::TestRequest::get()
+        .uri("/market-api/v1/demands/garbage/events?timeout=0")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let result: ErrorMessage = read_response_json(resp).await;
+    assert_eq!(
+        PathError::Deserialize(serde::de::Error::custom(
+            SubscriptionParseError::InvalidFormat("garbage".to_string())
+        ))
+        .to_string(),
+        result.message.unwrap()
+    );
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn test_rest_subscribe_unsubscribe_offer() {
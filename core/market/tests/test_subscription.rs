@@ -1,8 +1,13 @@
 use ya_market::assert_err_eq;
 use ya_market::testing::client::{sample_demand, sample_offer};
-use ya_market::testing::mock_offer::flatten_json;
-use ya_market::testing::{DemandError, QueryOfferError};
-use ya_market::testing::{MarketServiceExt, MarketsNetwork};
+use ya_market::testing::mock_offer::{self, flatten_json};
+use ya_market::testing::{Config, MarketServiceExt, MarketsNetwork};
+use ya_market::testing::{
+    DemandError, QueryEventsError, QueryOfferError, SubscriptionId, TakeEventsError,
+};
+
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Test subscribes offers, checks if offer is available
 /// and than unsubscribes. Checking broadcasting behavior is out of scope.
@@ -88,3 +93,303 @@ async fn test_subscribe_demand() {
         market1.get_demand(&subscription_id).await
     );
 }
+
+/// Test unsubscribes all Demands of single Identity at once.
+/// Waiting query_events calls should be woken up.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_unsubscribe_all_demands() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+    let identity2 = network.create_identity("Node-1", "Identity2");
+
+    let mut demand_ids = vec![];
+    for _ in 0..3 {
+        demand_ids.push(
+            market1
+                .subscribe_demand(&sample_demand(), &identity1)
+                .await
+                .unwrap(),
+        );
+    }
+    let other_demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity2)
+        .await
+        .unwrap();
+
+    let waiting_id = demand_ids[0].clone();
+    let waiting_market = market1.clone();
+    let query_handle = tokio::spawn(async move {
+        let result = waiting_market.query_events(&waiting_id, 1.2, Some(5)).await;
+        assert_err_eq!(TakeEventsError::Unsubscribed(waiting_id), result);
+    });
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+
+    let mut unsubscribed = market1.unsubscribe_all(&identity1).await.unwrap();
+    unsubscribed.sort_by_key(|id| id.to_string());
+    demand_ids.sort_by_key(|id| id.to_string());
+    assert_eq!(unsubscribed, demand_ids);
+
+    // Protect from eternal waiting.
+    tokio::time::timeout(Duration::from_millis(500), query_handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    for demand_id in demand_ids {
+        let result = market1.query_events(&demand_id, 0.0, None).await;
+        assert_err_eq!(TakeEventsError::Unsubscribed(demand_id), result);
+    }
+
+    // Demands of other Identities shouldn't be affected.
+    assert!(market1.get_demand(&other_demand_id).await.is_ok());
+}
+
+/// Expired Demands should be unsubscribed by background sweep,
+/// unless they were refreshed before expiration.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_expired_demands_sweep() {
+    let mut config = Config::default();
+    config.subscription.default_ttl = chrono::Duration::milliseconds(500);
+    config.subscription.expired_sweep_interval = Duration::from_millis(100);
+    config.subscription.resume_grace = chrono::Duration::zero();
+    config.subscription.resume_removal_margin = chrono::Duration::zero();
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let stale_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    let refreshed_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+
+    tokio::time::delay_for(Duration::from_millis(300)).await;
+    market1
+        .refresh_demand(&refreshed_id, &identity1)
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(400)).await;
+
+    // Sweep removed Demand, so it looks like unsubscribed.
+    assert_err_eq!(
+        DemandError::NotFound(stale_id.clone()),
+        market1.get_demand(&stale_id).await
+    );
+    let result = market1.query_events(&stale_id, 0.0, None).await;
+    assert_err_eq!(TakeEventsError::Unsubscribed(stale_id.clone()), result);
+
+    assert!(market1.get_demand(&refreshed_id).await.is_ok());
+    assert!(market1.query_events(&refreshed_id, 0.0, None).await.is_ok());
+
+    // Expired Demand can't be refreshed.
+    assert!(market1.refresh_demand(&stale_id, &identity1).await.is_err());
+}
+
+/// Bulk unsubscribe should report result for each Demand separately
+/// and wake up query_events waiters, like single unsubscribe does.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_unsubscribe_demands_bulk() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let mut demand_ids = vec![];
+    for _ in 0..2 {
+        demand_ids.push(
+            market1
+                .subscribe_demand(&sample_demand(), &identity1)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let waiting_id = demand_ids[1].clone();
+    let waiting_market = market1.clone();
+    let query_handle = tokio::spawn(async move {
+        let result = waiting_market.query_events(&waiting_id, 1.2, Some(5)).await;
+        assert_err_eq!(TakeEventsError::Unsubscribed(waiting_id), result);
+    });
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+
+    let non_existent_id: SubscriptionId = "80da375cb604426fb6cddd64f4ccc715-85fdde1924371f4a3a412748f61e5b941c500ea69a55a5135b886a2bffcb8e55".parse().unwrap();
+    let mut to_unsubscribe = demand_ids.clone();
+    to_unsubscribe.insert(1, non_existent_id.clone());
+
+    let results = market1
+        .unsubscribe_demands(&to_unsubscribe, &identity1)
+        .await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, demand_ids[0]);
+    assert!(results[0].1.is_ok());
+    assert_eq!(results[1].0, non_existent_id);
+    assert!(results[1].1.is_err());
+    assert_eq!(results[2].0, demand_ids[1]);
+    assert!(results[2].1.is_ok());
+
+    // Protect from eternal waiting.
+    tokio::time::timeout(Duration::from_millis(500), query_handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    for demand_id in demand_ids {
+        let result = market1.query_events(&demand_id, 0.0, None).await;
+        assert_err_eq!(TakeEventsError::Unsubscribed(demand_id), result);
+    }
+}
+
+/// Subscription metadata should reflect what was set on subscribe.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_get_subscription_info() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand_id = market1
+        .subscribe_demand(&sample_demand(), &identity1)
+        .await
+        .unwrap();
+    let demand = market1.get_demand(&demand_id).await.unwrap();
+
+    let info = market1.get_subscription_info(&demand_id).await.unwrap();
+    assert_eq!(info.subscription_id, demand_id);
+    assert_eq!(info.node_id, identity1.identity);
+    assert_eq!(info.created_at.naive_utc(), demand.creation_ts);
+    assert_eq!(info.expires_at.naive_utc(), demand.expiration_ts);
+    assert_eq!(info.ttl(), Config::default().subscription.default_ttl);
+    assert_eq!(info.pending_events, 0);
+
+    market1
+        .unsubscribe_demand(&demand_id, &identity1)
+        .await
+        .unwrap();
+    assert_err_eq!(
+        QueryEventsError::TakeEvents(TakeEventsError::Unsubscribed(demand_id.clone())),
+        market1.get_subscription_info(&demand_id).await
+    );
+
+    let offer_id = market1
+        .subscribe_offer(&sample_offer(), &identity1)
+        .await
+        .unwrap();
+    let offer = market1.get_offer(&offer_id).await.unwrap();
+
+    let info = market1.get_subscription_info(&offer_id).await.unwrap();
+    assert_eq!(info.subscription_id, offer_id);
+    assert_eq!(info.node_id, identity1.identity);
+    assert_eq!(info.created_at.naive_utc(), offer.creation_ts);
+    assert_eq!(info.expires_at.naive_utc(), offer.expiration_ts);
+    assert_eq!(info.pending_events, 0);
+}
+
+/// Expired Demand resumed within grace period should keep its events.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_resume_expired_demand() {
+    let mut config = Config::default();
+    config.subscription.default_ttl = chrono::Duration::milliseconds(500);
+    config.subscription.expired_sweep_interval = Duration::from_millis(100);
+    config.subscription.resume_grace = chrono::Duration::seconds(30);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand = sample_demand();
+    let demand_id = market1.subscribe_demand(&demand, &identity1).await.unwrap();
+    // Offer outlives Demand, so its Proposal is valid again after resume.
+    let offer = mock_offer::sample_offer();
+    market1
+        .matcher
+        .store
+        .save_offer(offer.clone())
+        .await
+        .unwrap();
+    market1
+        .inject_proposals(&[offer], &demand_id)
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(700)).await;
+
+    let result = market1.query_events(&demand_id, 0.0, None).await;
+    assert_err_eq!(TakeEventsError::Expired(demand_id.clone()), result);
+
+    // Demand with different properties is a different subscription.
+    let mut other = sample_demand();
+    other.constraints = "(golem.node.id.name=other)".to_string();
+    assert_err_eq!(
+        DemandError::ResumeMismatch(demand_id.clone()),
+        market1
+            .subscribe_demand_with(&other, &identity1, Some(&demand_id))
+            .await
+    );
+
+    let resumed_id = market1
+        .subscribe_demand_with(&demand, &identity1, Some(&demand_id))
+        .await
+        .unwrap();
+    assert_eq!(resumed_id, demand_id);
+
+    let events = market1.query_events(&demand_id, 0.0, None).await.unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+/// Demand can't be resumed, after grace period elapsed.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_resume_after_grace_period() {
+    let mut config = Config::default();
+    config.subscription.default_ttl = chrono::Duration::milliseconds(300);
+    config.subscription.resume_grace = chrono::Duration::milliseconds(200);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance("Node-1")
+        .await;
+
+    let market1 = network.get_market("Node-1");
+    let identity1 = network.get_default_id("Node-1");
+
+    let demand = sample_demand();
+    let demand_id = market1.subscribe_demand(&demand, &identity1).await.unwrap();
+    tokio::time::delay_for(Duration::from_millis(700)).await;
+
+    assert_err_eq!(
+        DemandError::ResumeWindowElapsed(demand_id.clone()),
+        market1
+            .subscribe_demand_with(&demand, &identity1, Some(&demand_id))
+            .await
+    );
+}
@@ -345,6 +345,13 @@ pub enum RpcMessageError {
     NotFound(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Too many requests. Retry after {0}s")]
+    TooManyRequests(u64),
     #[error("Timeout")]
     Timeout,
+    /// Remote peer didn't respond over GSB in time.
+    #[error("GSB timeout")]
+    GsbTimeout,
 }
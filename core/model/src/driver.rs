@@ -126,6 +126,39 @@ impl RpcMessage for VerifyPayment {
     type Error = GenericError;
 }
 
+// ************************** HEALTH **************************
+
+/// Checks, if driver is able to reach the network without sending
+/// any transaction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DriverHealth {
+    network: Option<String>,
+}
+
+impl DriverHealth {
+    pub fn new(network: Option<String>) -> Self {
+        Self { network }
+    }
+    pub fn network(&self) -> Option<String> {
+        self.network.clone()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthStatus {
+    pub network: String,
+    pub reachable: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+impl RpcMessage for DriverHealth {
+    const ID: &'static str = "DriverHealth";
+    type Item = HealthStatus;
+    type Error = GenericError;
+}
+
 // ************************** FUND **************************
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -168,6 +201,8 @@ pub struct Init {
     network: Option<String>,
     token: Option<String>,
     mode: AccountMode,
+    #[serde(default)]
+    fee_tokens: Vec<String>,
 }
 
 impl Init {
@@ -182,8 +217,17 @@ impl Init {
             network,
             token,
             mode,
+            fee_tokens: vec![],
         }
     }
+
+    /// Pay the unlock fee in the first of given tokens with sufficient balance,
+    /// instead of letting the driver choose.
+    pub fn with_fee_tokens(mut self, fee_tokens: Vec<String>) -> Self {
+        self.fee_tokens = fee_tokens;
+        self
+    }
+
     pub fn address(&self) -> String {
         self.address.clone()
     }
@@ -196,6 +240,9 @@ impl Init {
     pub fn mode(&self) -> AccountMode {
         self.mode.clone()
     }
+    pub fn fee_tokens(&self) -> Vec<String> {
+        self.fee_tokens.clone()
+    }
 }
 
 impl RpcMessage for Init {
@@ -213,6 +260,10 @@ pub struct SchedulePayment {
     recipient: String,
     platform: String,
     due_date: DateTime<Utc>,
+    /// Scheduling again with the same key returns order ID of the first
+    /// payment, instead of scheduling another one.
+    #[serde(default)]
+    idempotency_key: Option<String>,
 }
 
 impl SchedulePayment {
@@ -229,9 +280,15 @@ impl SchedulePayment {
             recipient,
             platform,
             due_date,
+            idempotency_key: None,
         }
     }
 
+    pub fn with_idempotency_key(mut self, key: String) -> Self {
+        self.idempotency_key = Some(key);
+        self
+    }
+
     pub fn amount(&self) -> BigDecimal {
         self.amount.clone()
     }
@@ -251,6 +308,10 @@ impl SchedulePayment {
     pub fn due_date(&self) -> DateTime<Utc> {
         self.due_date.clone()
     }
+
+    pub fn idempotency_key(&self) -> Option<String> {
+        self.idempotency_key.clone()
+    }
 }
 
 impl RpcMessage for SchedulePayment {
@@ -325,6 +386,22 @@ impl RpcMessage for Enter {
 
 // ************************** EXIT **************************
 
+/// How long exit waits before reporting success.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExitConfirmation {
+    /// Withdrawal is included in a committed block, it can be still reverted.
+    Commit,
+    /// Withdrawal is finalized on L1.
+    Verify,
+}
+
+impl Default for ExitConfirmation {
+    fn default() -> Self {
+        ExitConfirmation::Commit
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Exit {
     sender: String,
@@ -332,6 +409,10 @@ pub struct Exit {
     amount: Option<BigDecimal>,
     network: Option<String>,
     token: Option<String>,
+    #[serde(default)]
+    percent: Option<u8>,
+    #[serde(default)]
+    confirmation: ExitConfirmation,
 }
 
 impl Exit {
@@ -348,12 +429,31 @@ impl Exit {
             amount,
             network,
             token,
+            percent: None,
+            confirmation: ExitConfirmation::default(),
         }
     }
 
+    /// Withdraw given percentage of available funds, when no explicit amount is set.
+    pub fn with_percent(mut self, percent: impl Into<Option<u8>>) -> Self {
+        self.percent = percent.into();
+        self
+    }
+
+    pub fn with_confirmation(mut self, confirmation: ExitConfirmation) -> Self {
+        self.confirmation = confirmation;
+        self
+    }
+
     pub fn amount(&self) -> Option<BigDecimal> {
         self.amount.clone()
     }
+    pub fn percent(&self) -> Option<u8> {
+        self.percent
+    }
+    pub fn confirmation(&self) -> ExitConfirmation {
+        self.confirmation
+    }
     pub fn sender(&self) -> String {
         self.sender.clone()
     }
@@ -0,0 +1,10 @@
+-- Highest nonce used by transactions sent from given account. Committed nonce
+-- reported by the network lags behind sent transactions, so after restart
+-- driver could reuse nonces without it.
+CREATE TABLE nonce_cache(
+	sender VARCHAR(40) NOT NULL,
+	network INTEGER NOT NULL,
+	nonce INTEGER NOT NULL,
+	updated_ts DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY(sender, network)
+);
@@ -0,0 +1,12 @@
+-- Transfer signed for given payment, stored before it is sent. Allows to
+-- detect retries of payments, which might have been already sent, and to
+-- send the very same transaction again.
+CREATE TABLE transfer_attempt(
+	order_id VARCHAR(50) NOT NULL PRIMARY KEY,
+	network INTEGER NOT NULL,
+	sender VARCHAR(40) NOT NULL,
+	nonce INTEGER NOT NULL,
+	tx_hash VARCHAR(64) NOT NULL,
+	signed_tx TEXT NOT NULL,
+	created_ts DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
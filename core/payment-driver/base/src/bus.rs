@@ -74,6 +74,9 @@ pub async fn bind_service<Driver: PaymentDriver + 'static>(
         )
         .bind_with_processor(
             move |db, dr, c, m| async move { dr.verify_signature(db, c, m).await }
+        )
+        .bind_with_processor(
+            move |db, dr, c, m| async move { dr.health(db, c, m).await }
         );
 
     log::debug!("Successfully bound payment driver service to service bus.");
@@ -8,8 +8,10 @@
 mod error;
 
 pub use error::DbError;
+pub mod nonce;
 pub mod payment;
 pub mod transaction;
+pub mod transfer_attempt;
 
 pub use ya_persistence::executor::DbExecutor;
 
@@ -0,0 +1,85 @@
+/*
+    Data access object for nonce cache, linking `NonceEntity` with `nonce_cache`
+*/
+
+// External crates
+use chrono::{NaiveDateTime, Utc};
+use diesel::{self, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+// Workspace uses
+use ya_persistence::executor::{do_with_transaction, readonly_transaction, AsDao, PoolType};
+
+// Local uses
+use crate::{
+    dao::DbResult,
+    db::{
+        models::{Network, NonceEntity},
+        schema::nonce_cache::dsl,
+    },
+};
+
+pub struct NonceDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for NonceDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> NonceDao<'c> {
+    /// Highest nonce used by `address`, if it was updated after `since`.
+    pub async fn get_last_nonce(
+        &self,
+        address: &str,
+        network: Network,
+        since: NaiveDateTime,
+    ) -> DbResult<Option<u32>> {
+        let address = address.to_lowercase();
+        readonly_transaction(self.pool, move |conn| {
+            let nonce: Option<i32> = dsl::nonce_cache
+                .filter(dsl::sender.eq(address))
+                .filter(dsl::network.eq(network))
+                .filter(dsl::updated_ts.ge(since))
+                .select(dsl::nonce)
+                .first(conn)
+                .optional()?;
+            Ok(nonce.map(|nonce| nonce as u32))
+        })
+        .await
+    }
+
+    /// Stores `nonce` unless higher one was already stored.
+    pub async fn record_nonce(&self, address: &str, network: Network, nonce: u32) -> DbResult<()> {
+        let address = address.to_lowercase();
+        do_with_transaction(self.pool, move |conn| {
+            let cached: Option<i32> = dsl::nonce_cache
+                .find((address.clone(), network))
+                .select(dsl::nonce)
+                .first(conn)
+                .optional()?;
+            let entity = NonceEntity {
+                sender: address,
+                network,
+                nonce: std::cmp::max(cached.unwrap_or(0), nonce as i32),
+                updated_ts: Utc::now().naive_utc(),
+            };
+            diesel::replace_into(dsl::nonce_cache)
+                .values(entity)
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes entries not updated since `before`.
+    pub async fn clean_stale(&self, before: NaiveDateTime) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let removed = diesel::delete(dsl::nonce_cache.filter(dsl::updated_ts.lt(before)))
+                .execute(conn)?;
+            Ok(removed)
+        })
+        .await
+    }
+}
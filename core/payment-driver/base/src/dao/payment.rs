@@ -3,7 +3,7 @@
 */
 
 // External crates
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{self, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 
 // Workspace uses
 use ya_persistence::executor::{do_with_transaction, readonly_transaction, AsDao, PoolType};
@@ -46,6 +46,14 @@ impl<'c> PaymentDao<'c> {
         .await
     }
 
+    pub async fn get(&self, order_id: String) -> DbResult<Option<PaymentEntity>> {
+        readonly_transaction(self.pool, move |conn| {
+            let payment = dsl::payment.find(order_id).first(conn).optional()?;
+            Ok(payment)
+        })
+        .await
+    }
+
     pub async fn insert(&self, payment: PaymentEntity) -> DbResult<()> {
         do_with_transaction(self.pool, move |conn| {
             diesel::insert_into(dsl::payment)
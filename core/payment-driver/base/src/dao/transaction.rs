@@ -100,6 +100,27 @@ impl<'c> TransactionDao<'c> {
             .await
     }
 
+    /// Transactions of `address`, which were sent, but not confirmed yet.
+    pub async fn get_unconfirmed_txs_for(
+        &self,
+        address: &str,
+        network: Network,
+    ) -> DbResult<Vec<TransactionEntity>> {
+        let address = address.to_string();
+        readonly_transaction(self.pool, move |conn| {
+            let txs: Vec<TransactionEntity> = dsl::transaction
+                .filter(
+                    dsl::status
+                        .eq(TransactionStatus::Sent as i32)
+                        .and(dsl::sender.eq(address))
+                        .and(dsl::network.eq(network)),
+                )
+                .load(conn)?;
+            Ok(txs)
+        })
+        .await
+    }
+
     pub async fn get_by_status(
         &self,
         status: i32,
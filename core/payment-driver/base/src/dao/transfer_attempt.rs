@@ -0,0 +1,58 @@
+/*
+    Data access object for transfer attempts, linking `TransferAttemptEntity` with `transfer_attempt`
+*/
+
+// External crates
+use diesel::{self, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+// Workspace uses
+use ya_persistence::executor::{do_with_transaction, readonly_transaction, AsDao, PoolType};
+
+// Local uses
+use crate::{
+    dao::DbResult,
+    db::{models::TransferAttemptEntity, schema::transfer_attempt::dsl},
+};
+
+pub struct TransferAttemptDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for TransferAttemptDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> TransferAttemptDao<'c> {
+    pub async fn get(&self, order_id: String) -> DbResult<Option<TransferAttemptEntity>> {
+        readonly_transaction(self.pool, move |conn| {
+            let attempt = dsl::transfer_attempt
+                .find(order_id)
+                .first(conn)
+                .optional()?;
+            Ok(attempt)
+        })
+        .await
+    }
+
+    /// Replaces previous attempt for the same order.
+    pub async fn record(&self, attempt: TransferAttemptEntity) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            diesel::replace_into(dsl::transfer_attempt)
+                .values(attempt)
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn remove(&self, order_id: String) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            diesel::delete(dsl::transfer_attempt.filter(dsl::order_id.eq(order_id)))
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -100,6 +100,29 @@ pub struct PaymentEntity {
     pub network: Network,
 }
 
+#[derive(Queryable, Clone, Debug, Identifiable, Insertable, PartialEq)]
+#[primary_key(sender, network)]
+#[table_name = "nonce_cache"]
+pub struct NonceEntity {
+    pub sender: String,
+    pub network: Network,
+    pub nonce: i32,
+    pub updated_ts: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone, Debug, Identifiable, Insertable, PartialEq)]
+#[primary_key(order_id)]
+#[table_name = "transfer_attempt"]
+pub struct TransferAttemptEntity {
+    pub order_id: String,
+    pub network: Network,
+    pub sender: String,
+    pub nonce: i32,
+    pub tx_hash: String,
+    pub signed_tx: String,
+    pub created_ts: NaiveDateTime,
+}
+
 #[derive(AsExpression, FromSqlRow, PartialEq, Debug, Clone, Copy, FromPrimitive)]
 #[sql_type = "Integer"]
 pub enum Network {
@@ -1,3 +1,12 @@
+table! {
+    nonce_cache (sender, network) {
+        sender -> Text,
+        network -> Integer,
+        nonce -> Integer,
+        updated_ts -> Timestamp,
+    }
+}
+
 table! {
     payment (order_id) {
         order_id -> Text,
@@ -34,6 +43,18 @@ table! {
     }
 }
 
+table! {
+    transfer_attempt (order_id) {
+        order_id -> Text,
+        network -> Integer,
+        sender -> Text,
+        nonce -> Integer,
+        tx_hash -> Text,
+        signed_tx -> Text,
+        created_ts -> Timestamp,
+    }
+}
+
 table! {
     transaction_status (status_id) {
         status_id -> Integer,
@@ -54,9 +75,11 @@ joinable!(transaction -> transaction_status (status));
 joinable!(transaction -> transaction_type (tx_type));
 
 allow_tables_to_appear_in_same_query!(
+    nonce_cache,
     payment,
     payment_status,
     transaction,
     transaction_status,
     transaction_type,
+    transfer_attempt,
 );
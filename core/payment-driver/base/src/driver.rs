@@ -86,6 +86,18 @@ pub trait PaymentDriver {
         msg: ValidateAllocation,
     ) -> Result<bool, GenericError>;
 
+    async fn health(
+        &self,
+        _db: DbExecutor,
+        _caller: String,
+        _msg: DriverHealth,
+    ) -> Result<HealthStatus, GenericError> {
+        Err(GenericError::new(format!(
+            "Health check not supported by driver: {}",
+            self.get_name()
+        )))
+    }
+
     async fn sign_payment(
         &self,
         _db: DbExecutor,
@@ -69,7 +69,7 @@ async fn main() -> anyhow::Result<()> {
         .expect("Cannot parse 'amount' parameter to BigDecimal");
 
     let withdraw_handle =
-        driver_wallet::withdraw(wallet, DbNetwork::Rinkeby, Some(amount), None).await?;
+        driver_wallet::withdraw(wallet, DbNetwork::Rinkeby, Some(amount), None, None).await?;
 
     let tx_info = withdraw_handle.wait_for_commit().await?;
     if tx_info.success.unwrap_or(false) {
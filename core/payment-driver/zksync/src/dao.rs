@@ -3,15 +3,18 @@
 */
 
 // Extrernal crates
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use uuid::Uuid;
 
 // Workspace uses
 use ya_payment_driver::{
-    dao::{payment::PaymentDao, transaction::TransactionDao, DbExecutor},
+    dao::{
+        nonce::NonceDao, payment::PaymentDao, transaction::TransactionDao,
+        transfer_attempt::TransferAttemptDao, DbExecutor,
+    },
     db::models::{
-        Network, PaymentEntity, TransactionEntity, TransactionStatus, TxType,
-        PAYMENT_STATUS_FAILED, PAYMENT_STATUS_NOT_YET, TX_CREATED,
+        Network, PaymentEntity, TransactionEntity, TransactionStatus, TransferAttemptEntity,
+        TxType, PAYMENT_STATUS_FAILED, PAYMENT_STATUS_NOT_YET, TX_CREATED,
     },
     model::{GenericError, PaymentDetails, SchedulePayment},
     utils,
@@ -36,6 +39,14 @@ impl ZksyncDao {
         self.db.as_dao::<TransactionDao>()
     }
 
+    fn nonce(&self) -> NonceDao {
+        self.db.as_dao::<NonceDao>()
+    }
+
+    fn transfer_attempt(&self) -> TransferAttemptDao {
+        self.db.as_dao::<TransferAttemptDao>()
+    }
+
     pub async fn get_pending_payments(
         &self,
         node_id: &str,
@@ -58,6 +69,40 @@ impl ZksyncDao {
         }
     }
 
+    /// Inserts payment under new order ID, unless payment with the same
+    /// idempotency key was already scheduled. Returns order ID of the payment.
+    pub async fn schedule_payment(&self, msg: &SchedulePayment) -> Result<String, GenericError> {
+        let order_id = match msg.idempotency_key() {
+            Some(key) => key,
+            None => {
+                let order_id = Uuid::new_v4().to_string();
+                self.insert_payment(&order_id, msg).await?;
+                return Ok(order_id);
+            }
+        };
+        let existing = self
+            .payment()
+            .get(order_id.clone())
+            .await
+            .map_err(GenericError::new)?;
+        match existing {
+            Some(payment) if !payment.sender.eq_ignore_ascii_case(&msg.sender()) => {
+                Err(GenericError::new(format!(
+                    "Idempotency key {} already used by another sender",
+                    order_id
+                )))
+            }
+            Some(_) => {
+                log::info!("Payment already scheduled. order_id={}", order_id);
+                Ok(order_id)
+            }
+            None => {
+                self.insert_payment(&order_id, msg).await?;
+                Ok(order_id)
+            }
+        }
+    }
+
     pub async fn insert_payment(
         &self,
         order_id: &str,
@@ -205,4 +250,273 @@ impl ZksyncDao {
             }
         }
     }
+
+    pub async fn get_unconfirmed_txs_for(
+        &self,
+        address: &str,
+        network: Network,
+    ) -> Vec<TransactionEntity> {
+        match self
+            .transaction()
+            .get_unconfirmed_txs_for(address, network)
+            .await
+        {
+            Ok(txs) => txs,
+            Err(e) => {
+                log::error!(
+                    "Failed to fetch unconfirmed transactions for {:?} : {:?}",
+                    address,
+                    e
+                );
+                vec![]
+            }
+        }
+    }
+
+    pub async fn get_last_nonce(
+        &self,
+        address: &str,
+        network: Network,
+        since: NaiveDateTime,
+    ) -> Option<u32> {
+        match self.nonce().get_last_nonce(address, network, since).await {
+            Ok(nonce) => nonce,
+            Err(e) => {
+                log::error!("Failed to fetch cached nonce for {:?} : {:?}", address, e);
+                None
+            }
+        }
+    }
+
+    pub async fn record_nonce(&self, address: &str, network: Network, nonce: u32) {
+        if let Err(e) = self.nonce().record_nonce(address, network, nonce).await {
+            log::error!("Failed to cache nonce for {:?} : {:?}", address, e)
+        }
+    }
+
+    pub async fn clean_stale_nonces(&self, before: NaiveDateTime) {
+        if let Err(e) = self.nonce().clean_stale(before).await {
+            log::error!("Failed to clean stale nonces : {:?}", e)
+        }
+    }
+
+    pub async fn get_transfer_attempt(&self, order_id: &str) -> Option<TransferAttemptEntity> {
+        match self.transfer_attempt().get(order_id.to_string()).await {
+            Ok(attempt) => attempt,
+            Err(e) => {
+                log::error!(
+                    "Failed to fetch transfer attempt for {:?} : {:?}",
+                    order_id,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Must succeed before the transfer is sent, otherwise retry of the payment
+    /// won't be able to tell whether it was sent.
+    pub async fn record_transfer_attempt(
+        &self,
+        order_id: &str,
+        details: &PaymentDetails,
+        network: Network,
+        nonce: u32,
+        tx_hash: &str,
+        signed_tx: &str,
+    ) -> Result<(), GenericError> {
+        let attempt = TransferAttemptEntity {
+            order_id: order_id.to_string(),
+            network,
+            sender: details.sender.to_lowercase(),
+            nonce: nonce as i32,
+            tx_hash: tx_hash.to_string(),
+            signed_tx: signed_tx.to_string(),
+            created_ts: Utc::now().naive_utc(),
+        };
+        self.transfer_attempt()
+            .record(attempt)
+            .await
+            .map_err(GenericError::new)
+    }
+
+    pub async fn remove_transfer_attempt(&self, order_id: &str) {
+        if let Err(e) = self.transfer_attempt().remove(order_id.to_string()).await {
+            log::error!(
+                "Failed to remove transfer attempt for {:?} : {:?}",
+                order_id,
+                e
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zksync::wallet::AccountNonce;
+    use chrono::Duration;
+
+    const ADDRESS: &str = "0xAbCd000000000000000000000000000000000001";
+
+    async fn init_dao(dir: &tempdir::TempDir) -> ZksyncDao {
+        let db = DbExecutor::from_data_dir(dir.path(), "zksync-driver").unwrap();
+        ya_payment_driver::dao::init(&db).await.unwrap();
+        ZksyncDao::new(db)
+    }
+
+    #[actix_rt::test]
+    async fn test_rapid_transfers_use_consecutive_nonces() {
+        let dir = tempdir::TempDir::new("zksync_nonce").unwrap();
+        let dao = init_dao(&dir).await;
+        let since = (Utc::now() - Duration::hours(1)).naive_utc();
+        // Network doesn't reflect any of sent transactions yet.
+        let committed = 3;
+
+        let mut used = vec![];
+        for _ in 0..2 {
+            let nonce = AccountNonce {
+                committed,
+                pending: dao.get_last_nonce(ADDRESS, Network::Rinkeby, since).await,
+            }
+            .next();
+            dao.record_nonce(ADDRESS, Network::Rinkeby, nonce).await;
+            used.push(nonce);
+        }
+        assert_eq!(used, vec![3, 4]);
+
+        // Cache is kept per network and address is case insensitive.
+        assert_eq!(
+            dao.get_last_nonce(ADDRESS, Network::Mainnet, since).await,
+            None
+        );
+        assert_eq!(
+            dao.get_last_nonce(&ADDRESS.to_lowercase(), Network::Rinkeby, since)
+                .await,
+            Some(4)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_stale_nonces() {
+        let dir = tempdir::TempDir::new("zksync_nonce").unwrap();
+        let dao = init_dao(&dir).await;
+        dao.record_nonce(ADDRESS, Network::Rinkeby, 7).await;
+
+        let future = (Utc::now() + Duration::hours(1)).naive_utc();
+        assert_eq!(
+            dao.get_last_nonce(ADDRESS, Network::Rinkeby, future).await,
+            None
+        );
+
+        let past = (Utc::now() - Duration::hours(1)).naive_utc();
+        dao.clean_stale_nonces(past).await;
+        assert_eq!(
+            dao.get_last_nonce(ADDRESS, Network::Rinkeby, past).await,
+            Some(7)
+        );
+
+        dao.clean_stale_nonces(future).await;
+        assert_eq!(
+            dao.get_last_nonce(ADDRESS, Network::Rinkeby, past).await,
+            None
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_attempt() {
+        let dir = tempdir::TempDir::new("zksync_transfer_attempt").unwrap();
+        let dao = init_dao(&dir).await;
+        let details = PaymentDetails {
+            recipient: "0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5".to_string(),
+            sender: ADDRESS.to_string(),
+            amount: 1.into(),
+            date: None,
+        };
+        assert!(dao.get_transfer_attempt("order-1").await.is_none());
+
+        dao.record_transfer_attempt("order-1", &details, Network::Rinkeby, 3, "aa", "{}")
+            .await
+            .unwrap();
+        // Payment sent anew, after previous transfer was dropped.
+        dao.record_transfer_attempt("order-1", &details, Network::Rinkeby, 4, "bb", "{}")
+            .await
+            .unwrap();
+        let attempt = dao.get_transfer_attempt("order-1").await.unwrap();
+        assert_eq!(attempt.tx_hash, "bb");
+        assert_eq!(attempt.nonce, 4);
+        assert_eq!(attempt.signed_tx, "{}");
+        assert_eq!(attempt.sender, ADDRESS.to_lowercase());
+
+        dao.remove_transfer_attempt("order-1").await;
+        assert!(dao.get_transfer_attempt("order-1").await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_schedule_payment_idempotency_key() {
+        let dir = tempdir::TempDir::new("zksync_schedule_payment").unwrap();
+        let dao = init_dao(&dir).await;
+        let payment = |sender: &str| {
+            SchedulePayment::new(
+                1.into(),
+                sender.to_string(),
+                "0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5".to_string(),
+                crate::DEFAULT_PLATFORM.to_string(),
+                Utc::now(),
+            )
+        };
+
+        // Retried after timeout, not knowing if the first request landed.
+        let msg = payment(ADDRESS).with_idempotency_key("key-1".to_string());
+        let order_id = dao.schedule_payment(&msg).await.unwrap();
+        assert_eq!(order_id, "key-1");
+        assert_eq!(dao.schedule_payment(&msg).await.unwrap(), order_id);
+        let pending = dao.get_pending_payments(ADDRESS, Network::Rinkeby).await;
+        assert_eq!(pending.len(), 1);
+
+        let other = payment("0x0000000000000000000000000000000000000002")
+            .with_idempotency_key("key-1".to_string());
+        assert!(dao.schedule_payment(&other).await.is_err());
+
+        // Without key every request is a new payment.
+        let first = dao.schedule_payment(&payment(ADDRESS)).await.unwrap();
+        let second = dao.schedule_payment(&payment(ADDRESS)).await.unwrap();
+        assert_ne!(first, second);
+        let pending = dao.get_pending_payments(ADDRESS, Network::Rinkeby).await;
+        assert_eq!(pending.len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_unconfirmed_txs_for_sender() {
+        let dir = tempdir::TempDir::new("zksync_transactions").unwrap();
+        let dao = init_dao(&dir).await;
+        let details = |sender: &str| PaymentDetails {
+            recipient: "0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5".to_string(),
+            sender: sender.to_string(),
+            amount: 1.into(),
+            date: None,
+        };
+
+        let sent = dao
+            .insert_transaction(&details(ADDRESS), Utc::now(), Network::Rinkeby)
+            .await;
+        dao.transaction_sent(&sent, "aa", "order-1").await;
+        // Created, but not sent yet.
+        dao.insert_transaction(&details(ADDRESS), Utc::now(), Network::Rinkeby)
+            .await;
+        let other = dao
+            .insert_transaction(&details("0x01"), Utc::now(), Network::Rinkeby)
+            .await;
+        dao.transaction_sent(&other, "bb", "order-2").await;
+
+        let txs = dao.get_unconfirmed_txs_for(ADDRESS, Network::Rinkeby).await;
+        assert_eq!(
+            txs.into_iter().map(|tx| tx.tx_hash).collect::<Vec<_>>(),
+            vec![Some("aa".to_string())]
+        );
+        assert!(dao
+            .get_unconfirmed_txs_for(ADDRESS, Network::Mainnet)
+            .await
+            .is_empty());
+    }
 }
@@ -7,10 +7,11 @@
 use chrono::{Duration, TimeZone, Utc};
 use lazy_static::lazy_static;
 use num_bigint::BigInt;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::str::FromStr;
-use uuid::Uuid;
+use zksync::types::BlockStatus;
 
 // Workspace uses
 use ya_payment_driver::{
@@ -18,7 +19,7 @@ use ya_payment_driver::{
     bus,
     cron::PaymentDriverCron,
     dao::DbExecutor,
-    db::models::{Network as DbNetwork, PaymentEntity, TxType},
+    db::models::{Network as DbNetwork, PaymentEntity, TransferAttemptEntity, TxType},
     driver::{async_trait, BigDecimal, IdentityError, IdentityEvent, Network, PaymentDriver},
     model::*,
     utils,
@@ -29,14 +30,25 @@ use ya_utils_futures::timeout::IntoTimeoutFuture;
 use crate::{
     dao::ZksyncDao,
     network::{
-        get_network_token, network_token_to_platform, platform_to_network_token, SUPPORTED_NETWORKS,
+        get_network_token, network_token_to_platform, platform_to_network_token, resolve_network,
+        SUPPORTED_NETWORKS,
+    },
+    zksync::{
+        error::ZksyncError,
+        utils::PackPolicy,
+        wallet::{self, AccountNonce, TransferProbe},
     },
-    zksync::wallet,
     DEFAULT_NETWORK, DRIVER_NAME,
 };
 
 lazy_static! {
     static ref TX_SUMBIT_TIMEOUT: Duration = Duration::minutes(15);
+    // Nonces cached longer than that must have been already committed.
+    static ref NONCE_CACHE_WINDOW: Duration =
+        match env::var("NONCE_CACHE_WINDOW_SECS").map(|s| s.parse()) {
+            Ok(Ok(x)) => Duration::seconds(x),
+            _ => Duration::hours(1),
+        };
     static ref MAX_ALLOCATION_SURCHARGE: BigDecimal =
         match env::var("MAX_ALLOCATION_SURCHARGE").map(|s| s.parse()) {
             Ok(Ok(x)) => x,
@@ -49,10 +61,41 @@ lazy_static! {
             Ok(Ok(x)) => x,
             _ => BigInt::from(10),
         };
+    // Rounding of transfer amounts to packable format, `up` or `down`.
+    static ref PACK_POLICY: PackPolicy =
+        match env::var("ZKSYNC_PACK_POLICY").map(|s| s.parse()) {
+            Ok(Ok(x)) => x,
+            _ => PackPolicy::default(),
+        };
+}
+
+/// Pairs of address and network, for which wallet was initialized
+/// with `AccountMode::SEND`.
+#[derive(Default)]
+struct SendAccounts(HashSet<(String, String)>);
+
+impl SendAccounts {
+    fn add(&mut self, address: &str, network: DbNetwork) {
+        self.0.insert((address.to_lowercase(), network.to_string()));
+    }
+
+    fn ensure_initialized(&self, address: &str, network: DbNetwork) -> Result<(), GenericError> {
+        match self
+            .0
+            .contains(&(address.to_lowercase(), network.to_string()))
+        {
+            true => Ok(()),
+            false => Err(GenericError::new(format!(
+                "Account not initialized for sending. address={}, network={}",
+                address, network
+            ))),
+        }
+    }
 }
 
 pub struct ZksyncDriver {
     active_accounts: AccountsRc,
+    send_accounts: RefCell<SendAccounts>,
     dao: ZksyncDao,
 }
 
@@ -60,17 +103,48 @@ impl ZksyncDriver {
     pub fn new(db: DbExecutor) -> Self {
         Self {
             active_accounts: Accounts::new_rc(),
+            send_accounts: RefCell::new(SendAccounts::default()),
             dao: ZksyncDao::new(db),
         }
     }
 
     pub async fn load_active_accounts(&self) {
         log::debug!("load_active_accounts");
-        let mut accounts = self.active_accounts.borrow_mut();
         let unlocked_accounts = bus::list_unlocked_identities().await.unwrap();
         for account in unlocked_accounts {
             log::debug!("account={}", account);
-            accounts.add_account(account)
+            self.log_pending_transactions(&account.to_string()).await;
+            self.active_accounts.borrow_mut().add_account(account)
+        }
+    }
+
+    /// Hashes of transactions sent from `address`, which are neither
+    /// committed nor failed yet.
+    pub async fn pending_transactions(&self, address: &str, network: DbNetwork) -> Vec<String> {
+        let tx_hashes = self
+            .dao
+            .get_unconfirmed_txs_for(address, network)
+            .await
+            .into_iter()
+            .filter_map(|tx| tx.tx_hash)
+            .collect();
+        wallet::pending_transactions(tx_hashes, network).await
+    }
+
+    /// Transactions sent before restart are still confirmed by the cron job,
+    /// this only makes them visible.
+    async fn log_pending_transactions(&self, address: &str) {
+        for network_key in self.get_networks().keys() {
+            let network = DbNetwork::from_str(&network_key).unwrap();
+            let pending = self.pending_transactions(address, network).await;
+            if !pending.is_empty() {
+                log::info!(
+                    "Pending transactions found. address={}, network={}, tx_hashes={:?}",
+                    address,
+                    network,
+                    pending
+                );
+            }
         }
     }
 
@@ -88,18 +162,34 @@ impl ZksyncDriver {
             let network = DbNetwork::from_str(&network_key).unwrap();
             let payments: Vec<PaymentEntity> =
                 self.dao.get_pending_payments(node_id, network).await;
-            let mut nonce = 0;
-            if !payments.is_empty() {
-                log::info!(
-                    "Processing payments. count={}, network={} node_id={}",
-                    payments.len(),
-                    network_key,
-                    node_id
-                );
-
-                nonce = wallet::get_nonce(node_id, network).await;
-                log::debug!("Payments: nonce={}, details={:?}", &nonce, payments);
+            if payments.is_empty() {
+                continue;
             }
+            log::info!(
+                "Processing payments. count={}, network={} node_id={}",
+                payments.len(),
+                network_key,
+                node_id
+            );
+
+            let committed = match wallet::get_committed_nonce(node_id, network).await {
+                Ok(committed) => committed,
+                Err(e) => {
+                    log::error!(
+                        "Unable to get nonce, payments will be retried. node_id={}, error={}",
+                        node_id,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let since = (Utc::now() - *NONCE_CACHE_WINDOW).naive_utc();
+            let mut nonce = AccountNonce {
+                committed,
+                pending: self.dao.get_last_nonce(node_id, network, since).await,
+            }
+            .next();
+            log::debug!("Payments: nonce={}, details={:?}", &nonce, payments);
             for payment in payments {
                 self.handle_payment(payment, &mut nonce).await;
             }
@@ -108,10 +198,9 @@ impl ZksyncDriver {
 
     async fn handle_payment(&self, payment: PaymentEntity, nonce: &mut u32) {
         let details = utils::db_to_payment_details(&payment);
-        let tx_nonce = nonce.to_owned();
 
-        match wallet::make_transfer(&details, tx_nonce, payment.network).await {
-            Ok(tx_hash) => {
+        match self.transfer(&payment, &details, *nonce).await {
+            Ok((tx_hash, tx_nonce)) => {
                 let tx_id = self
                     .dao
                     .insert_transaction(&details, Utc::now(), payment.network)
@@ -119,12 +208,19 @@ impl ZksyncDriver {
                 self.dao
                     .transaction_sent(&tx_id, &tx_hash, &payment.order_id)
                     .await;
-                *nonce += 1;
+                self.dao
+                    .record_nonce(&details.sender, payment.network, tx_nonce)
+                    .await;
+                self.dao.remove_transfer_attempt(&payment.order_id).await;
+                *nonce = std::cmp::max(*nonce, tx_nonce + 1);
             }
             Err(e) => {
                 let deadline =
                     Utc.from_utc_datetime(&payment.payment_due_date) + *TX_SUMBIT_TIMEOUT;
-                if Utc::now() > deadline {
+                if !e.is_retryable() {
+                    log::error!("Failed to submit zkSync transaction. Payment won't be retried. details={:?} error={}", payment, e);
+                    self.dao.payment_failed(&payment.order_id).await;
+                } else if Utc::now() > deadline {
                     log::error!("Failed to submit zkSync transaction. Retry deadline reached. details={:?} error={}", payment, e);
                     self.dao.payment_failed(&payment.order_id).await;
                 } else {
@@ -136,6 +232,122 @@ impl ZksyncDriver {
             }
         };
     }
+
+    /// Sends transfer for the payment, unless its previous attempt already
+    /// did. Returns hash and nonce of the transfer.
+    async fn transfer(
+        &self,
+        payment: &PaymentEntity,
+        details: &PaymentDetails,
+        next_nonce: u32,
+    ) -> Result<(String, u32), ZksyncError> {
+        let attempt = self.dao.get_transfer_attempt(&payment.order_id).await;
+        match plan_retry(attempt, &details.sender, payment.network, next_nonce).await? {
+            TransferPlan::Reuse(tx_hash, tx_nonce) => {
+                log::info!(
+                    "Payment already sent. order_id={}, tx_hash={}",
+                    payment.order_id,
+                    tx_hash
+                );
+                Ok((tx_hash, tx_nonce))
+            }
+            TransferPlan::Resubmit(attempt) => {
+                log::info!(
+                    "Outcome of previous transfer unknown, sending it again. order_id={}, tx_hash={}",
+                    payment.order_id,
+                    attempt.tx_hash
+                );
+                let tx_nonce = attempt.nonce as u32;
+                let transfer = wallet::PreparedTransfer::from_signed_tx(
+                    &attempt.signed_tx,
+                    details,
+                    tx_nonce,
+                    payment.network,
+                )?;
+                let tx_hash = self.send_transfer(&payment.order_id, transfer).await?;
+                Ok((tx_hash, tx_nonce))
+            }
+            TransferPlan::Send(tx_nonce) => {
+                let transfer =
+                    wallet::prepare_transfer(details, tx_nonce, payment.network, *PACK_POLICY)
+                        .await?;
+                self.dao
+                    .record_transfer_attempt(
+                        &payment.order_id,
+                        details,
+                        payment.network,
+                        tx_nonce,
+                        &transfer.tx_hash(),
+                        &transfer.signed_tx()?,
+                    )
+                    .await?;
+                let tx_hash = self.send_transfer(&payment.order_id, transfer).await?;
+                Ok((tx_hash, tx_nonce))
+            }
+        }
+    }
+
+    /// Attempt is kept only, when it isn't known whether transfer was sent.
+    /// Rejected transfer has to be signed again with fresh fee and nonce.
+    async fn send_transfer(
+        &self,
+        order_id: &str,
+        transfer: wallet::PreparedTransfer,
+    ) -> Result<String, ZksyncError> {
+        match wallet::send_transfer(transfer).await {
+            Err(e @ ZksyncError::Rejected(_)) => {
+                self.dao.remove_transfer_attempt(order_id).await;
+                Err(e)
+            }
+            result => result,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum TransferPlan {
+    /// Transfer of previous attempt was executed, hash and nonce are reused.
+    Reuse(String, u32),
+    /// Outcome of previous attempt is unknown. Its signed transaction is sent
+    /// again unchanged, so its hash stays the same.
+    Resubmit(TransferAttemptEntity),
+    /// Transfer has to be sent with given nonce.
+    Send(u32),
+}
+
+/// Probes the network for outcome of previous transfer attempt of the same
+/// payment, so retried payment is never sent twice.
+async fn plan_retry(
+    attempt: Option<TransferAttemptEntity>,
+    sender: &str,
+    network: DbNetwork,
+    next_nonce: u32,
+) -> Result<TransferPlan, ZksyncError> {
+    let attempt = match attempt {
+        Some(attempt) => {
+            let probe =
+                wallet::probe_transfer(&attempt.tx_hash, sender, attempt.nonce as u32, network)
+                    .await?;
+            Some((attempt, probe))
+        }
+        None => None,
+    };
+    Ok(plan_transfer(attempt, next_nonce))
+}
+
+/// `attempt` holds previous transfer attempt of the same payment along with
+/// its probed outcome.
+fn plan_transfer(
+    attempt: Option<(TransferAttemptEntity, TransferProbe)>,
+    next_nonce: u32,
+) -> TransferPlan {
+    match attempt {
+        Some((attempt, TransferProbe::Executed)) => {
+            TransferPlan::Reuse(attempt.tx_hash, attempt.nonce as u32)
+        }
+        Some((attempt, TransferProbe::Unknown)) => TransferPlan::Resubmit(attempt),
+        Some((_, TransferProbe::Dropped)) | None => TransferPlan::Send(next_nonce),
+    }
 }
 
 #[async_trait(?Send)]
@@ -173,11 +385,18 @@ impl PaymentDriver for ZksyncDriver {
         }
 
         let tx_hash = wallet::exit(&msg).await?;
+        let status = match msg.confirmation() {
+            ExitConfirmation::Commit => {
+                "Withdrawal has been committed by the zkSync operator. \
+                It may take some time until the funds are available on Ethereum blockchain."
+            }
+            ExitConfirmation::Verify => {
+                "Withdrawal has been verified by the zkSync operator and finalized on Ethereum blockchain."
+            }
+        };
         Ok(format!(
-            "Withdrawal has been accepted by the zkSync operator. \
-        It may take some time until the funds are available on Ethereum blockchain. \
-        Tracking link: https://rinkeby.zkscan.io/explorer/transactions/{}",
-            tx_hash
+            "{} Tracking link: https://rinkeby.zkscan.io/explorer/transactions/{}",
+            status, tx_hash
         ))
     }
 
@@ -190,7 +409,8 @@ impl PaymentDriver for ZksyncDriver {
         log::debug!("get_account_balance: {:?}", msg);
         let (network, _) = platform_to_network_token(msg.platform())?;
 
-        let balance = wallet::account_balance(&msg.address(), network).await?;
+        let balance =
+            wallet::account_balance(&msg.address(), network, BlockStatus::Committed).await?;
 
         log::debug!("get_account_balance - result: {}", &balance);
         Ok(balance)
@@ -227,12 +447,13 @@ impl PaymentDriver for ZksyncDriver {
             .await
             .map_err(GenericError::new)??;
 
-        let network = msg.network().unwrap_or(DEFAULT_NETWORK.to_string());
-        let token = get_network_token(
-            DbNetwork::from_str(&network).map_err(GenericError::new)?,
-            msg.token(),
-        );
+        let db_network = resolve_network(msg.network())?;
+        let network = db_network.to_string();
+        let token = get_network_token(db_network, msg.token());
         bus::register_account(self, &address, &network, &token, mode).await?;
+        if mode.contains(AccountMode::SEND) {
+            self.send_accounts.borrow_mut().add(&address, db_network);
+        }
 
         log::info!(
             "Initialised payment account. mode={:?}, address={}, driver={}, network={}, token={}",
@@ -252,8 +473,7 @@ impl PaymentDriver for ZksyncDriver {
         msg: Fund,
     ) -> Result<String, GenericError> {
         let address = msg.address();
-        let network = DbNetwork::from_str(&msg.network().unwrap_or(DEFAULT_NETWORK.to_string()))
-            .map_err(GenericError::new)?;
+        let network = resolve_network(msg.network())?;
         match network {
             DbNetwork::Rinkeby => {
                 wallet::fund(&address, network)
@@ -279,6 +499,16 @@ Mind that to be eligible you have to run your app at least once on testnet -
         }
     }
 
+    async fn health(
+        &self,
+        _db: DbExecutor,
+        _caller: String,
+        msg: DriverHealth,
+    ) -> Result<HealthStatus, GenericError> {
+        let network = resolve_network(msg.network())?;
+        wallet::health(network).await
+    }
+
     async fn transfer(
         &self,
         _db: DbExecutor,
@@ -303,10 +533,14 @@ Mind that to be eligible you have to run your app at least once on testnet -
                 "Can not schedule_payment, account not active",
             ));
         }
+        // Checked only here, because set of initialized accounts isn't persisted.
+        // Payments accepted before restart are sent regardless.
+        let (network, _) = platform_to_network_token(msg.platform())?;
+        self.send_accounts
+            .borrow()
+            .ensure_initialized(&sender, network)?;
 
-        let order_id = Uuid::new_v4().to_string();
-        self.dao.insert_payment(&order_id, &msg).await?;
-        Ok(order_id)
+        self.dao.schedule_payment(&msg).await
     }
 
     async fn verify_payment(
@@ -319,7 +553,7 @@ Mind that to be eligible you have to run your app at least once on testnet -
         let (network, _) = platform_to_network_token(msg.platform())?;
         let tx_hash = hex::encode(msg.confirmation().confirmation);
         log::info!("Verifying transaction: {}", tx_hash);
-        wallet::verify_tx(&tx_hash, network).await
+        Ok(wallet::verify_tx(&tx_hash, network).await?.details)
     }
 
     async fn validate_allocation(
@@ -329,7 +563,8 @@ Mind that to be eligible you have to run your app at least once on testnet -
         msg: ValidateAllocation,
     ) -> Result<bool, GenericError> {
         let (network, _) = platform_to_network_token(msg.platform)?;
-        let account_balance = wallet::account_balance(&msg.address, network).await?;
+        let account_balance =
+            wallet::account_balance(&msg.address, network, BlockStatus::Committed).await?;
         let total_allocated_amount: BigDecimal = msg
             .existing_allocations
             .into_iter()
@@ -417,7 +652,7 @@ impl PaymentDriverCron for ZksyncDriver {
                 let platform =
                     network_token_to_platform(Some(first_payment.network), None).unwrap(); // TODO: Catch error?
                 let details = match wallet::verify_tx(&tx_hash, first_payment.network).await {
-                    Ok(a) => a,
+                    Ok(verified) => verified.details,
                     Err(e) => {
                         log::warn!("Failed to get transaction details from zksync, creating bespoke details. Error={}", e);
 
@@ -453,8 +688,125 @@ impl PaymentDriverCron for ZksyncDriver {
     }
 
     async fn process_payments(&self) {
+        let stale = (Utc::now() - *NONCE_CACHE_WINDOW).naive_utc();
+        self.dao.clean_stale_nonces(stale).await;
         for node_id in self.active_accounts.borrow().list_accounts() {
             self.process_payments_for_account(&node_id).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zksync::mock::{self, MockRpc};
+    use zksync::zksync_types::tx::TxHash;
+
+    const ADDRESS: &str = "0xB1C4D937A1b9bfC17a2Eb92D3577F8b66763bfC1";
+
+    #[test]
+    fn test_send_initialized() {
+        let mut accounts = SendAccounts::default();
+        let network = resolve_network(None).unwrap();
+        accounts.add(ADDRESS, network);
+
+        assert!(accounts.ensure_initialized(ADDRESS, network).is_ok());
+        assert!(accounts
+            .ensure_initialized(&ADDRESS.to_lowercase(), network)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_send_not_initialized() {
+        let mut accounts = SendAccounts::default();
+        let rinkeby = resolve_network(None).unwrap();
+        let mainnet = resolve_network(Some(crate::MAINNET_NETWORK.to_string())).unwrap();
+
+        let err = accounts.ensure_initialized(ADDRESS, rinkeby).unwrap_err();
+        assert!(err.to_string().contains(ADDRESS));
+
+        // Initialization is tracked per network.
+        accounts.add(ADDRESS, rinkeby);
+        let err = accounts.ensure_initialized(ADDRESS, mainnet).unwrap_err();
+        assert!(err.to_string().contains("mainnet"));
+    }
+
+    #[test]
+    fn test_plan_first_send() {
+        assert_eq!(plan_transfer(None, 7), TransferPlan::Send(7));
+    }
+
+    fn transfer_attempt(tx_hash: &str, nonce: i32) -> TransferAttemptEntity {
+        TransferAttemptEntity {
+            order_id: "order-1".to_string(),
+            network: DbNetwork::Rinkeby,
+            sender: "0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b".to_string(),
+            nonce,
+            tx_hash: tx_hash.to_string(),
+            signed_tx: "{}".to_string(),
+            created_ts: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_plan_exact_retry() {
+        let attempt = Some((transfer_attempt("abcd", 5), TransferProbe::Executed));
+        assert_eq!(
+            plan_transfer(attempt, 7),
+            TransferPlan::Reuse("abcd".to_string(), 5)
+        );
+    }
+
+    #[test]
+    fn test_plan_unknown_outcome() {
+        // Previous transfer might be still pending. Sending it unchanged
+        // keeps its hash, so it's still tracked once executed.
+        let attempt = transfer_attempt("abcd", 5);
+        assert_eq!(
+            plan_transfer(Some((attempt.clone(), TransferProbe::Unknown)), 7),
+            TransferPlan::Resubmit(attempt)
+        );
+
+        let attempt = Some((transfer_attempt("abcd", 5), TransferProbe::Dropped));
+        assert_eq!(plan_transfer(attempt, 7), TransferPlan::Send(7));
+    }
+
+    #[actix_rt::test]
+    async fn test_retry_after_send_error() {
+        const SENDER: &str = "0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b";
+        const TX_HASH: &str = "8f6d5f8dd0d4d5a9c5b2bf7aa32f0cb4fd1f0b9dd4a4e2a5c0b52c1bb0a1d9f3";
+        let network = DbNetwork::Rinkeby;
+        let attempt = transfer_attempt(TX_HASH, 5);
+        let mock_rpc = |executed: bool, committed_nonce: u32| {
+            let address = wallet::parse_address(SENDER).unwrap();
+            let tx_hash = TxHash::from_str(&format!("sync-tx:{}", TX_HASH)).unwrap();
+            let success = if executed { Some(true) } else { None };
+            MockRpc::default()
+                .with_tx_info(tx_hash, mock::tx_info(success, None))
+                .with_account_info(
+                    address,
+                    mock::account_info(address, "tGLM", "0", "0", committed_nonce),
+                )
+                .install()
+        };
+
+        // First send of the payment.
+        let plan = plan_retry(None, SENDER, network, 5).await.unwrap();
+        assert_eq!(plan, TransferPlan::Send(5));
+
+        // Send reported an error, but transfer went through. Retry must
+        // return its hash instead of sending another one.
+        let _guard = mock_rpc(true, 6);
+        let plan = plan_retry(Some(attempt.clone()), SENDER, network, 6)
+            .await
+            .unwrap();
+        assert_eq!(plan, TransferPlan::Reuse(TX_HASH.to_string(), 5));
+
+        // Outcome unknown yet. The very same transfer is sent again.
+        let _guard = mock_rpc(false, 5);
+        let plan = plan_retry(Some(attempt.clone()), SENDER, network, 6)
+            .await
+            .unwrap();
+        assert_eq!(plan, TransferPlan::Resubmit(attempt));
+    }
+}
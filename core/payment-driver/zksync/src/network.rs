@@ -1,5 +1,6 @@
 use maplit::hashmap;
 use std::collections::HashMap;
+use std::env;
 use std::str::FromStr;
 
 // Workspace uses
@@ -30,6 +31,11 @@ lazy_static::lazy_static! {
     static ref MAINNET_DB_NETWORK: DbNetwork = DbNetwork::from_str(MAINNET_NETWORK).unwrap();
 }
 
+/// Network requested explicitly by message or `DEFAULT_NETWORK` otherwise.
+pub fn resolve_network(network: Option<String>) -> Result<DbNetwork, GenericError> {
+    DbNetwork::from_str(&network.unwrap_or(DEFAULT_NETWORK.to_string())).map_err(GenericError::new)
+}
+
 pub fn platform_to_network_token(platform: String) -> Result<(DbNetwork, String), GenericError> {
     match platform.as_str() {
         DEFAULT_PLATFORM => Ok((*DEFAULT_DB_NETWORK, DEFAULT_TOKEN.to_owned())),
@@ -73,8 +79,67 @@ pub fn network_token_to_platform(
 }
 
 pub fn get_network_token(network: DbNetwork, token: Option<String>) -> String {
+    network_token_from_vars(network, token, |name| env::var(name).ok())
+}
+
+fn token_var(network: &str) -> String {
+    format!("ZKSYNC_{}_TOKEN", network.to_uppercase())
+}
+
+/// Default token of a network can be overridden with `ZKSYNC_{NETWORK}_TOKEN`,
+/// e.g. for private deployments using different token symbol.
+fn network_token_from_vars<F>(network: DbNetwork, token: Option<String>, var: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(token) = token {
+        // TODO: Check if token in network.tokens
+        return token;
+    }
+    if let Some(token) = var(&token_var(&network.to_string())) {
+        return token;
+    }
     // Fetch network config, safe as long as all DbNetwork entries are in SUPPORTED_NETWORKS
     let network_config = (*SUPPORTED_NETWORKS).get(&(network.to_string())).unwrap();
-    // TODO: Check if token in network.tokens
-    token.unwrap_or(network_config.default_token.clone())
+    network_config.default_token.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_network() {
+        assert_eq!(resolve_network(None).unwrap(), *DEFAULT_DB_NETWORK);
+        assert_eq!(
+            resolve_network(Some(MAINNET_NETWORK.to_string())).unwrap(),
+            *MAINNET_DB_NETWORK
+        );
+        assert!(resolve_network(Some("unknown".to_string())).is_err());
+    }
+
+    fn token_vars(name: &str) -> Option<String> {
+        match name {
+            "ZKSYNC_RINKEBY_TOKEN" => Some("tLOC".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_network_token_override() {
+        let rinkeby = *DEFAULT_DB_NETWORK;
+        assert_eq!(network_token_from_vars(rinkeby, None, token_vars), "tLOC");
+        assert_eq!(
+            network_token_from_vars(rinkeby, Some("GNT".to_string()), token_vars),
+            "GNT"
+        );
+        assert_eq!(
+            network_token_from_vars(*MAINNET_DB_NETWORK, None, token_vars),
+            MAINNET_TOKEN
+        );
+        assert_eq!(
+            network_token_from_vars(rinkeby, None, |_| None),
+            DEFAULT_TOKEN
+        );
+    }
 }
@@ -0,0 +1,163 @@
+/*
+    Errors of zksync operations, detailed enough for the driver to decide
+    whether failed payment should be retried.
+*/
+
+// External crates
+use bigdecimal::BigDecimal;
+use zksync::error::ClientError;
+
+// Workspace uses
+use ya_payment_driver::model::GenericError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ZksyncError {
+    #[error("Insufficient balance. required={required}, available={available}")]
+    InsufficientBalance {
+        required: BigDecimal,
+        available: BigDecimal,
+    },
+    #[error("Invalid address `{0}`")]
+    InvalidAddress(String),
+    #[error("{}", amount_message(.amount, .reason))]
+    InvalidAmount {
+        amount: BigDecimal,
+        reason: AmountError,
+    },
+    #[error("zkSync RPC error: {0}")]
+    Rpc(String),
+    /// Node explicitly refused the transaction, so it will never be executed.
+    #[error("zkSync rejected transaction: {0}")]
+    Rejected(String),
+    #[error("Timeout waiting for transaction commit: {0}")]
+    CommitTimeout(String),
+    #[error("Unable to sign with identity {address}: {reason}. HINT: Is the identity locked? Unlock it with `yagna id unlock`")]
+    IdentityLocked { address: String, reason: String },
+    #[error("Faucet rate limited, {}", retry_hint(.retry_after))]
+    FaucetRateLimited { retry_after: Option<u64> },
+    #[error(transparent)]
+    Other(#[from] GenericError),
+}
+
+/// Why transfer amount can't be sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AmountError {
+    NotPositive,
+    RoundsToZero,
+    /// Amount is below `ZKSYNC_MIN_TRANSFER`.
+    BelowMinimum(BigDecimal),
+}
+
+fn amount_message(amount: &BigDecimal, reason: &AmountError) -> String {
+    match reason {
+        AmountError::NotPositive => format!(
+            "Invalid transfer amount {}, should be greater than 0",
+            amount
+        ),
+        AmountError::RoundsToZero => format!("Transfer amount {} rounds down to 0", amount),
+        AmountError::BelowMinimum(min) => {
+            format!("Transfer amount {} is below minimum {}", amount, min)
+        }
+    }
+}
+
+fn retry_hint(retry_after: &Option<u64>) -> String {
+    match retry_after {
+        Some(secs) => format!("retry after {} seconds", secs),
+        None => "retry later".to_string(),
+    }
+}
+
+impl ZksyncError {
+    /// Whether the same operation can succeed later. Invalid input never will,
+    /// but the network can recover and balance can be topped up.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            ZksyncError::InvalidAddress(_) | ZksyncError::InvalidAmount { .. }
+        )
+    }
+}
+
+impl From<ClientError> for ZksyncError {
+    fn from(e: ClientError) -> Self {
+        match e {
+            // Only waiting for transaction state can time out.
+            ClientError::OperationTimeout => ZksyncError::CommitTimeout(e.to_string()),
+            e => ZksyncError::Rpc(e.to_string()),
+        }
+    }
+}
+
+impl ZksyncError {
+    /// Unlike other calls, error of sending transaction tells, whether
+    /// the transaction could still be executed.
+    pub fn from_send_error(e: ClientError) -> Self {
+        match e {
+            ClientError::RpcError(_) => ZksyncError::Rejected(e.to_string()),
+            e => ZksyncError::from(e),
+        }
+    }
+}
+
+impl From<ZksyncError> for GenericError {
+    fn from(e: ZksyncError) -> Self {
+        GenericError::new(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_error_variants() {
+        let err = ZksyncError::from(ClientError::OperationTimeout);
+        assert!(matches!(err, ZksyncError::CommitTimeout(_)));
+        assert!(err.is_retryable());
+
+        let err = ZksyncError::from(ClientError::NetworkError("connection refused".to_string()));
+        assert!(matches!(err, ZksyncError::Rpc(_)));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_send_error_variants() {
+        let err = ZksyncError::from_send_error(ClientError::NetworkError("reset".to_string()));
+        assert!(matches!(err, ZksyncError::Rpc(_)));
+        let err = ZksyncError::from_send_error(ClientError::OperationTimeout);
+        assert!(matches!(err, ZksyncError::CommitTimeout(_)));
+    }
+
+    #[test]
+    fn test_invalid_amount_not_retryable() {
+        let err = ZksyncError::InvalidAmount {
+            amount: BigDecimal::from(0),
+            reason: AmountError::NotPositive,
+        };
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "Invalid transfer amount 0, should be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_into_generic_error() {
+        let err = GenericError::from(ZksyncError::InvalidAddress("0x12".to_string()));
+        assert_eq!(err.to_string(), "Invalid address `0x12`");
+    }
+
+    #[test]
+    fn test_faucet_rate_limited_message() {
+        let err = ZksyncError::FaucetRateLimited {
+            retry_after: Some(30),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Faucet rate limited, retry after 30 seconds"
+        );
+        let err = ZksyncError::FaucetRateLimited { retry_after: None };
+        assert_eq!(err.to_string(), "Faucet rate limited, retry later");
+    }
+}
@@ -3,30 +3,39 @@
 */
 
 // External crates
+use awc::http::{header, StatusCode};
 use bigdecimal::BigDecimal;
 use chrono::{Duration, Utc};
 use lazy_static::lazy_static;
 use std::{env, time};
 use tokio::time::delay_for;
+use zksync::types::BlockStatus;
 
 // Workspace uses
 use ya_payment_driver::{db::models::Network, model::GenericError};
 use ya_utils_networking::resolver;
 
 // Local uses
-use crate::zksync::wallet::account_balance;
+use crate::zksync::{error::ZksyncError, wallet::account_balance};
 
 const DEFAULT_FAUCET_SRV_PREFIX: &str = "_zk-faucet._tcp";
 const FAUCET_ADDR_ENVAR: &str = "ZKSYNC_FAUCET_ADDR";
 const MAX_FAUCET_REQUESTS: u32 = 6;
+// Used when rate limited faucet gives no hint.
+const DEFAULT_RATE_LIMIT_WAIT_SECS: u64 = 60;
 
 lazy_static! {
     static ref MIN_BALANCE: BigDecimal = BigDecimal::from(50);
     static ref MAX_WAIT: Duration = Duration::minutes(1);
+    // When set, rate limited faucet request is retried once after the time
+    // faucet asked for.
+    static ref WAIT_ON_RATE_LIMIT: bool = env::var("ZKSYNC_FAUCET_WAIT_ON_RATE_LIMIT")
+        .map(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
 }
 
 pub async fn request_tglm(address: &str, network: Network) -> Result<(), GenericError> {
-    let balance = account_balance(address, network).await?;
+    let balance = account_balance(address, network, BlockStatus::Committed).await?;
     if balance >= *MIN_BALANCE {
         return Ok(());
     }
@@ -36,9 +45,27 @@ pub async fn request_tglm(address: &str, network: Network) -> Result<(), Generic
         address
     );
 
-    for i in 0..MAX_FAUCET_REQUESTS {
+    let mut waited_on_rate_limit = false;
+    // Retry after rate limit doesn't count as an attempt.
+    let mut i = 0;
+    while i < MAX_FAUCET_REQUESTS {
         match faucet_donate(address, network).await {
             Ok(()) => break,
+            Err(ZksyncError::FaucetRateLimited { retry_after })
+                if *WAIT_ON_RATE_LIMIT && !waited_on_rate_limit =>
+            {
+                let secs = retry_after.unwrap_or(DEFAULT_RATE_LIMIT_WAIT_SECS);
+                log::warn!(
+                    "Faucet rate limited, retrying to request tGLM in {} seconds",
+                    secs
+                );
+                waited_on_rate_limit = true;
+                delay_for(time::Duration::from_secs(secs)).await;
+            }
+            Err(e @ ZksyncError::FaucetRateLimited { .. }) => {
+                log::error!("Failed to request tGLM from Faucet: {}", e);
+                return Err(e.into());
+            }
             Err(e) => {
                 // Do not warn nor sleep at the last try.
                 if i >= MAX_FAUCET_REQUESTS - 1 {
@@ -47,7 +74,7 @@ pub async fn request_tglm(address: &str, network: Network) -> Result<(), Generic
                         MAX_FAUCET_REQUESTS,
                         e
                     );
-                    return Err(e);
+                    return Err(e.into());
                 } else {
                     log::warn!(
                         "Retrying ({}/{}) to request tGLM from Faucet after failure: {:?}",
@@ -56,6 +83,7 @@ pub async fn request_tglm(address: &str, network: Network) -> Result<(), Generic
                         e
                     );
                     delay_for(time::Duration::from_secs(10)).await;
+                    i += 1;
                 }
             }
         }
@@ -68,7 +96,7 @@ async fn wait_for_tglm(address: &str, network: Network) -> Result<(), GenericErr
     log::info!("Waiting for tGLM from faucet...");
     let wait_until = Utc::now() + *MAX_WAIT;
     while Utc::now() < wait_until {
-        if account_balance(address, network).await? >= *MIN_BALANCE {
+        if account_balance(address, network, BlockStatus::Committed).await? >= *MIN_BALANCE {
             log::info!("Received tGLM from faucet.");
             return Ok(());
         }
@@ -79,7 +107,7 @@ async fn wait_for_tglm(address: &str, network: Network) -> Result<(), GenericErr
     Err(GenericError::new(msg))
 }
 
-async fn faucet_donate(address: &str, _network: Network) -> Result<(), GenericError> {
+async fn faucet_donate(address: &str, _network: Network) -> Result<(), ZksyncError> {
     // TODO: Reduce timeout to 20-30 seconds when transfer is used.
     let client = awc::Client::builder()
         .timeout(std::time::Duration::from_secs(60))
@@ -88,20 +116,67 @@ async fn faucet_donate(address: &str, _network: Network) -> Result<(), GenericEr
     let request_url = format!("{}/{}", faucet_url, address);
     let request_url = resolver::try_resolve_dns_record(&request_url).await;
     debug!("Faucet request url: {}", request_url);
-    let response = client
+    let mut response = client
         .get(request_url)
         .send()
         .await
-        .map_err(GenericError::new)?
-        .body()
-        .await
         .map_err(GenericError::new)?;
-    let response = String::from_utf8_lossy(response.as_ref());
-    log::debug!("Funds requested. Response = {}", response);
+    let retry_after = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let body = response.body().await.map_err(GenericError::new)?;
+    let body = String::from_utf8_lossy(body.as_ref());
+    log::debug!("Funds requested. Response = {}", body);
     // TODO: Verify tx hash
+    check_response(response.status(), retry_after.as_deref(), &body)
+}
+
+/// Faucet is considered reachable, when it answers with any HTTP response.
+pub async fn check_reachable(timeout: std::time::Duration) -> Result<(), GenericError> {
+    let faucet_url = resolve_faucet_url().await?;
+    let faucet_url = resolver::try_resolve_dns_record(&faucet_url).await;
+    awc::Client::builder()
+        .timeout(timeout)
+        .finish()
+        .head(faucet_url)
+        .send()
+        .await
+        .map(|_| ())
+        .map_err(|e| GenericError::new(format!("Faucet unreachable. {}", e)))
+}
+
+fn check_response(
+    status: StatusCode,
+    retry_after: Option<&str>,
+    body: &str,
+) -> Result<(), ZksyncError> {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ZksyncError::FaucetRateLimited {
+            retry_after: parse_retry_after(retry_after, body),
+        });
+    }
+    if !status.is_success() {
+        return Err(GenericError::new(format!(
+            "Faucet request failed with status {}: {}",
+            status, body
+        ))
+        .into());
+    }
     Ok(())
 }
 
+/// Seconds from `Retry-After` header, or from "retry after N" hint in body.
+fn parse_retry_after(header: Option<&str>, body: &str) -> Option<u64> {
+    if let Some(secs) = header.and_then(|value| value.trim().parse().ok()) {
+        return Some(secs);
+    }
+    let body = body.to_lowercase();
+    let hint = &body[body.find("retry after")? + "retry after".len()..];
+    hint.split_whitespace().next()?.parse().ok()
+}
+
 async fn resolve_faucet_url() -> Result<String, GenericError> {
     match env::var(FAUCET_ADDR_ENVAR) {
         Ok(addr) => Ok(addr),
@@ -114,3 +189,40 @@ async fn resolve_faucet_url() -> Result<String, GenericError> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limited_response() {
+        let err = check_response(StatusCode::TOO_MANY_REQUESTS, Some("30"), "").unwrap_err();
+        match err {
+            ZksyncError::FaucetRateLimited { retry_after } => assert_eq!(retry_after, Some(30)),
+            e => panic!("Unexpected error: {}", e),
+        }
+        assert!(check_response(StatusCode::OK, None, "0xabcd").is_ok());
+    }
+
+    #[test]
+    fn test_failed_response() {
+        let err = check_response(StatusCode::INTERNAL_SERVER_ERROR, None, "oops").unwrap_err();
+        match err {
+            ZksyncError::Other(e) => assert_eq!(
+                e.to_string(),
+                "Faucet request failed with status 500 Internal Server Error: oops"
+            ),
+            e => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(Some(" 15 "), ""), Some(15));
+        assert_eq!(
+            parse_retry_after(None, "Too many requests. Retry after 120 seconds"),
+            Some(120)
+        );
+        assert_eq!(parse_retry_after(Some("soon"), "Too many requests"), None);
+    }
+}
@@ -0,0 +1,204 @@
+/*
+    In-memory `ZksyncRpc` returning canned responses. Installed mock is used
+    by wallet functions on the current thread instead of the real provider,
+    either for all networks or for the chosen one.
+*/
+
+// External crates
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use zksync::error::ClientError;
+use zksync::types::{AccountInfo, TransactionInfo};
+use zksync::zksync_types::{fee::Fee, tx::TxHash, Address, Token, TxFeeTypes};
+
+// Workspace uses
+use ya_payment_driver::db::models::Network;
+
+// Local uses
+use crate::zksync::rpc::ZksyncRpc;
+
+thread_local! {
+    // Mocks per network name, `None` matches every network.
+    static MOCKS: RefCell<HashMap<Option<String>, Arc<dyn ZksyncRpc>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn mocked(network: Network) -> Option<Arc<dyn ZksyncRpc>> {
+    MOCKS.with(|mocks| {
+        let mocks = mocks.borrow();
+        mocks
+            .get(&Some(network.to_string()))
+            .or_else(|| mocks.get(&None))
+            .cloned()
+    })
+}
+
+/// Mock stays installed until the guard is dropped.
+pub struct MockGuard(Option<String>);
+
+impl Drop for MockGuard {
+    fn drop(&mut self) {
+        MOCKS.with(|mocks| mocks.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Responses are stored as JSON, the same way RPC server would return them.
+/// Missing ones result in `ClientError::MalformedResponse`.
+#[derive(Default)]
+pub struct MockRpc {
+    account_infos: Mutex<HashMap<Address, serde_json::Value>>,
+    tx_infos: Mutex<HashMap<TxHash, serde_json::Value>>,
+    tx_fees: Mutex<HashMap<String, serde_json::Value>>,
+    tokens: Mutex<Option<serde_json::Value>>,
+}
+
+impl MockRpc {
+    pub fn with_account_info(self, address: Address, account_info: serde_json::Value) -> Self {
+        self.account_infos
+            .lock()
+            .unwrap()
+            .insert(address, account_info);
+        self
+    }
+
+    pub fn with_tx_info(self, tx_hash: TxHash, tx_info: serde_json::Value) -> Self {
+        self.tx_infos.lock().unwrap().insert(tx_hash, tx_info);
+        self
+    }
+
+    /// Fee is returned for every transaction type paid in `token`.
+    pub fn with_tx_fee(self, token: &str, fee: serde_json::Value) -> Self {
+        self.tx_fees.lock().unwrap().insert(token.to_string(), fee);
+        self
+    }
+
+    /// Without it, only GLM and tGLM tokens with 18 decimals are known.
+    pub fn with_tokens(self, tokens: serde_json::Value) -> Self {
+        *self.tokens.lock().unwrap() = Some(tokens);
+        self
+    }
+
+    pub fn install(self) -> MockGuard {
+        self.install_as(None)
+    }
+
+    pub fn install_for(self, network: Network) -> MockGuard {
+        self.install_as(Some(network.to_string()))
+    }
+
+    fn install_as(self, key: Option<String>) -> MockGuard {
+        MOCKS.with(|mocks| mocks.borrow_mut().insert(key.clone(), Arc::new(self)));
+        MockGuard(key)
+    }
+}
+
+fn response<K, T>(
+    responses: &Mutex<HashMap<K, serde_json::Value>>,
+    key: &K,
+) -> Result<T, ClientError>
+where
+    K: std::hash::Hash + Eq + std::fmt::Debug,
+    T: DeserializeOwned,
+{
+    let value = responses.lock().unwrap().get(key).cloned().ok_or_else(|| {
+        ClientError::MalformedResponse(format!("No mocked response for {:?}", key))
+    })?;
+    serde_json::from_value(value).map_err(|e| ClientError::MalformedResponse(e.to_string()))
+}
+
+#[async_trait]
+impl ZksyncRpc for MockRpc {
+    async fn account_info(&self, address: Address) -> Result<AccountInfo, ClientError> {
+        response(&self.account_infos, &address)
+    }
+
+    async fn tx_info(&self, tx_hash: TxHash) -> Result<TransactionInfo, ClientError> {
+        response(&self.tx_infos, &tx_hash)
+    }
+
+    async fn get_tx_fee(
+        &self,
+        _tx_type: TxFeeTypes,
+        _address: Address,
+        token: &str,
+    ) -> Result<Fee, ClientError> {
+        response(&self.tx_fees, &token.to_string())
+    }
+
+    async fn tokens(&self) -> Result<HashMap<String, Token>, ClientError> {
+        let tokens = self
+            .tokens
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| tokens(&[("GLM", 18), ("tGLM", 18)]));
+        serde_json::from_value(tokens).map_err(|e| ClientError::MalformedResponse(e.to_string()))
+    }
+}
+
+/// `AccountInfo` JSON with given balances of `token`.
+pub fn account_info(
+    address: Address,
+    token: &str,
+    committed: &str,
+    verified: &str,
+    nonce: u32,
+) -> serde_json::Value {
+    let state = |balance: &str| {
+        serde_json::json!({
+            "balances": { token: balance },
+            "nonce": nonce,
+            "pubKeyHash": "sync:0000000000000000000000000000000000000000",
+        })
+    };
+    serde_json::json!({
+        "address": address,
+        "id": 1,
+        "committed": state(committed),
+        "verified": state(verified),
+        "depositing": { "balances": {} },
+    })
+}
+
+/// `TransactionInfo` JSON, transaction is not executed when `success` is `None`.
+pub fn tx_info(success: Option<bool>, fail_reason: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "executed": success.is_some(),
+        "success": success,
+        "failReason": fail_reason,
+        "block": null,
+    })
+}
+
+/// `Fee` JSON, whole fee is reported as zkp fee.
+pub fn tx_fee(total_fee: &str) -> serde_json::Value {
+    serde_json::json!({
+        "feeType": "Transfer",
+        "gasTxAmount": "0",
+        "gasPriceWei": "0",
+        "gasFee": "0",
+        "zkpFee": total_fee,
+        "totalFee": total_fee,
+    })
+}
+
+/// Token list JSON with given symbols and decimals.
+pub fn tokens(tokens: &[(&str, u8)]) -> serde_json::Value {
+    let tokens = tokens
+        .iter()
+        .enumerate()
+        .map(|(id, (symbol, decimals))| {
+            let token = serde_json::json!({
+                "id": id,
+                "address": format!("0x{:040x}", id),
+                "symbol": symbol,
+                "decimals": decimals,
+            });
+            (symbol.to_string(), token)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::Value::Object(tokens)
+}
@@ -4,6 +4,13 @@
 
 pub mod wallet;
 
+pub mod error;
 pub mod faucet;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod readonly;
+pub mod rpc;
 mod signer;
+pub mod tx_log;
+pub mod tx_state;
 pub mod utils;
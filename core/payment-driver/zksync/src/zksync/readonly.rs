@@ -0,0 +1,102 @@
+/*
+    Read-only access to zksync account state. Nothing here ever creates a
+    `Wallet` or `YagnaEthSigner`, so it is safe to use in deployments, which
+    must not be able to sign transactions (e.g. monitoring tools).
+*/
+
+// External crates
+use bigdecimal::BigDecimal;
+use zksync::types::BlockStatus;
+
+// Workspace uses
+use ya_payment_driver::{db::models::Network, model::GenericError};
+
+// Local uses
+use crate::zksync::wallet::{self, VerifiedTx};
+
+/// Account on given network, that can be inspected, but never signs.
+#[derive(Clone, Debug)]
+pub struct ReadOnlyWallet {
+    address: String,
+    network: Network,
+}
+
+impl ReadOnlyWallet {
+    pub fn new(address: &str, network: Network) -> Result<Self, GenericError> {
+        wallet::parse_address(address)?;
+        Ok(Self {
+            address: address.to_string(),
+            network,
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub async fn balance(&self, status: BlockStatus) -> Result<BigDecimal, GenericError> {
+        wallet::account_balance(&self.address, self.network, status).await
+    }
+
+    /// Nonce according to committed zksync state.
+    pub async fn nonce(&self) -> Result<u32, GenericError> {
+        wallet::get_committed_nonce(&self.address, self.network).await
+    }
+
+    pub async fn tx_fee(&self) -> Result<BigDecimal, GenericError> {
+        wallet::get_tx_fee(&self.address, self.network).await
+    }
+
+    pub async fn check_tx(&self, tx_hash: &str) -> Option<Result<(), String>> {
+        wallet::check_tx(tx_hash, self.network).await
+    }
+
+    pub async fn verify_tx(&self, tx_hash: &str) -> Result<VerifiedTx, GenericError> {
+        wallet::verify_tx(tx_hash, self.network).await
+    }
+
+    /// Recent transfers of this account, newest first.
+    pub async fn list_transactions(&self, limit: usize) -> Result<Vec<VerifiedTx>, GenericError> {
+        wallet::list_transactions(&self.address, self.network, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zksync::mock::{self, MockRpc};
+    use std::str::FromStr;
+    use zksync::zksync_types::tx::TxHash;
+
+    // Not known to identity service, so any attempt to sign would fail.
+    const ADDRESS: &str = "0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b";
+
+    #[actix_rt::test]
+    async fn test_read_only_without_signer() {
+        let address = wallet::parse_address(ADDRESS).unwrap();
+        let tx_hash = "b".repeat(64);
+        let sync_tx_hash = TxHash::from_str(&format!("sync-tx:{}", tx_hash)).unwrap();
+        let _mock = MockRpc::default()
+            .with_account_info(
+                address,
+                mock::account_info(address, "tGLM", "1000000000000000000", "0", 7),
+            )
+            .with_tx_info(sync_tx_hash, mock::tx_info(Some(true), None))
+            .install();
+
+        let readonly = ReadOnlyWallet::new(ADDRESS, Network::Rinkeby).unwrap();
+        let balance = readonly.balance(BlockStatus::Committed).await.unwrap();
+        assert_eq!(balance, BigDecimal::from(1));
+        assert_eq!(readonly.nonce().await.unwrap().committed, 7);
+        assert_eq!(readonly.check_tx(&tx_hash).await, Some(Ok(())));
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        assert!(ReadOnlyWallet::new("not-an-address", Network::Rinkeby).is_err());
+    }
+}
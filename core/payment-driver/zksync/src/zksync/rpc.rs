@@ -0,0 +1,65 @@
+/*
+    Read-only zksync RPC calls used outside of `Wallet`, abstracted so they
+    can be replaced with `mock::MockRpc` in tests.
+*/
+
+// External crates
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use zksync::error::ClientError;
+use zksync::provider::{Provider, RpcProvider};
+use zksync::types::{AccountInfo, TransactionInfo};
+use zksync::zksync_types::{fee::Fee, tx::TxHash, Address, Token, TxFeeTypes};
+
+// Workspace uses
+use ya_payment_driver::db::models::Network;
+
+#[async_trait]
+pub trait ZksyncRpc: Send + Sync {
+    async fn account_info(&self, address: Address) -> Result<AccountInfo, ClientError>;
+
+    async fn tx_info(&self, tx_hash: TxHash) -> Result<TransactionInfo, ClientError>;
+
+    async fn get_tx_fee(
+        &self,
+        tx_type: TxFeeTypes,
+        address: Address,
+        token: &str,
+    ) -> Result<Fee, ClientError>;
+
+    /// Tokens supported by the network, keyed by symbol.
+    async fn tokens(&self) -> Result<HashMap<String, Token>, ClientError>;
+}
+
+#[async_trait]
+impl ZksyncRpc for RpcProvider {
+    async fn account_info(&self, address: Address) -> Result<AccountInfo, ClientError> {
+        Provider::account_info(self, address).await
+    }
+
+    async fn tx_info(&self, tx_hash: TxHash) -> Result<TransactionInfo, ClientError> {
+        Provider::tx_info(self, tx_hash).await
+    }
+
+    async fn get_tx_fee(
+        &self,
+        tx_type: TxFeeTypes,
+        address: Address,
+        token: &str,
+    ) -> Result<Fee, ClientError> {
+        Provider::get_tx_fee(self, tx_type, address, token).await
+    }
+
+    async fn tokens(&self) -> Result<HashMap<String, Token>, ClientError> {
+        Provider::tokens(self).await
+    }
+}
+
+#[cfg(not(any(test, feature = "mock")))]
+pub fn mocked(_network: Network) -> Option<Arc<dyn ZksyncRpc>> {
+    None
+}
+
+#[cfg(any(test, feature = "mock"))]
+pub use crate::zksync::mock::mocked;
@@ -0,0 +1,202 @@
+/*
+    Structured log of zksync transaction lifecycle.
+
+    Every line carries the same `tx_id`, so all stages of single transfer
+    can be found with one grep.
+*/
+
+// External crates
+use bigdecimal::BigDecimal;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, Once};
+
+// Workspace uses
+use ya_payment_driver::{db::models::Network, model::PaymentDetails};
+
+// Local uses
+use crate::zksync::tx_state::{self, TxState};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TxStage {
+    Created,
+    Committed,
+    Failed,
+}
+
+impl fmt::Display for TxStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxStage::Created => f.write_str("created"),
+            TxStage::Committed => f.write_str("committed"),
+            TxStage::Failed => f.write_str("failed"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TxLog {
+    /// Correlation id, derived from sender, network and nonce, which
+    /// identify transfer even before it gets tx_hash.
+    pub tx_id: String,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: BigDecimal,
+    pub nonce: u32,
+    pub network: Network,
+}
+
+impl TxLog {
+    pub fn new(details: &PaymentDetails, nonce: u32, network: Network) -> Self {
+        Self {
+            tx_id: format!("{}-{}-{}", network, details.sender.to_lowercase(), nonce),
+            sender: details.sender.clone(),
+            recipient: details.recipient.clone(),
+            amount: details.amount.clone(),
+            nonce,
+            network,
+        }
+    }
+
+    pub fn format(&self, stage: TxStage, tx_hash: Option<&str>) -> String {
+        format!(
+            "tx_lifecycle stage={} tx_id={} tx_hash={} sender={} recipient={} amount={} nonce={} network={}",
+            stage,
+            self.tx_id,
+            tx_hash.unwrap_or("-"),
+            self.sender,
+            self.recipient,
+            self.amount,
+            self.nonce,
+            self.network
+        )
+    }
+
+    pub fn log(&self, stage: TxStage, tx_hash: Option<&str>) {
+        match stage {
+            TxStage::Failed => log::error!("{}", self.format(stage, tx_hash)),
+            _ => log::info!("{}", self.format(stage, tx_hash)),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SENT: Mutex<HashMap<String, TxLog>> = Mutex::new(HashMap::new());
+}
+
+static HOOK: Once = Once::new();
+
+/// Logs created stage and remembers transaction, so the following
+/// stages reported by `tx_state` are logged with the same fields.
+pub fn created(tx_log: TxLog, tx_hash: &str) {
+    HOOK.call_once(|| tx_state::register_hook(Box::new(on_state_change)));
+
+    tx_log.log(TxStage::Created, Some(tx_hash));
+    SENT.lock().unwrap().insert(tx_hash.to_string(), tx_log);
+}
+
+fn on_state_change(tx_hash: &str, old: &TxState, new: &TxState) {
+    let mut sent = SENT.lock().unwrap();
+    let (stage, tx_log) = match new {
+        // Transaction isn't polled anymore, once it is committed.
+        TxState::Committed => (TxStage::Committed, sent.remove(tx_hash)),
+        // Verified is final, but committed stage could have been skipped
+        // between two polls.
+        TxState::Verified if old != &TxState::Committed => {
+            (TxStage::Committed, sent.remove(tx_hash))
+        }
+        TxState::Failed(_) => (TxStage::Failed, sent.remove(tx_hash)),
+        TxState::Verified => {
+            sent.remove(tx_hash);
+            return;
+        }
+        TxState::Pending => return,
+    };
+    if let Some(tx_log) = tx_log {
+        tx_log.log(stage, Some(tx_hash));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps lines logged by this module, so tests can check what was
+    /// actually emitted.
+    struct CaptureLogger;
+
+    lazy_static! {
+        static ref CAPTURED: Mutex<Vec<String>> = Mutex::new(vec![]);
+    }
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == module_path!().trim_end_matches("::tests")
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                CAPTURED.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger;
+
+    fn captured(tx_hash: &str) -> Vec<String> {
+        // Logger can be set only once per process, following calls fail.
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Info);
+        }
+        let tx_hash = format!("tx_hash={} ", tx_hash);
+        CAPTURED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|line| line.contains(&tx_hash))
+            .cloned()
+            .collect()
+    }
+
+    fn field<'a>(line: &'a str, name: &str) -> &'a str {
+        line.split(' ')
+            .find_map(|kv| kv.strip_prefix(&format!("{}=", name)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_correlation_id_stable() {
+        let details = PaymentDetails {
+            recipient: "0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5".to_string(),
+            sender: "0x2F7D2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b".to_string(),
+            amount: BigDecimal::from(3),
+            date: None,
+        };
+        let tx_hash = "test_correlation_id_stable";
+        assert!(captured(tx_hash).is_empty());
+
+        created(TxLog::new(&details, 7, Network::Rinkeby), tx_hash);
+        on_state_change(tx_hash, &TxState::Pending, &TxState::Committed);
+        on_state_change(tx_hash, &TxState::Committed, &TxState::Verified);
+
+        let lines = captured(tx_hash);
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert_eq!(field(&lines[0], "stage"), "created");
+        assert_eq!(field(&lines[1], "stage"), "committed");
+        assert_eq!(field(&lines[0], "tx_id"), field(&lines[1], "tx_id"));
+        assert_eq!(
+            field(&lines[1], "tx_id"),
+            "rinkeby-0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b-7"
+        );
+
+        // The same transfer retried with the same nonce keeps its id.
+        assert_eq!(
+            TxLog::new(&details, 7, Network::Rinkeby).tx_id,
+            field(&lines[0], "tx_id")
+        );
+        assert!(SENT.lock().unwrap().get(tx_hash).is_none());
+    }
+}
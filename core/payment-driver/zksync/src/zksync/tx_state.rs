@@ -0,0 +1,153 @@
+/*
+    Track state changes of zksync transactions and notify registered hooks.
+*/
+
+// External crates
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use zksync::types::TransactionInfo;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TxState {
+    Pending,
+    Committed,
+    Verified,
+    Failed(String),
+}
+
+impl TxState {
+    pub fn from_tx_info(tx_info: &TransactionInfo) -> Self {
+        match tx_info.success {
+            None => TxState::Pending,
+            Some(true) => match &tx_info.block {
+                Some(block) if block.verified => TxState::Verified,
+                _ => TxState::Committed,
+            },
+            Some(false) => TxState::Failed(
+                tx_info
+                    .fail_reason
+                    .clone()
+                    .unwrap_or("Unknown failure".to_string()),
+            ),
+        }
+    }
+
+    fn is_final(&self) -> bool {
+        match self {
+            TxState::Verified | TxState::Failed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Called with (tx_hash, old_state, new_state) on every observed transition.
+pub type TxStateHook = Box<dyn Fn(&str, &TxState, &TxState) + Send + Sync>;
+
+/// Transactions, which nobody checked for that long, are forgotten, even if
+/// they never reached final state (e.g. waiting for them timed out).
+const STATE_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct SeenState {
+    state: TxState,
+    last_seen: Instant,
+}
+
+lazy_static! {
+    static ref HOOKS: RwLock<Vec<TxStateHook>> = RwLock::new(vec![]);
+    static ref STATES: Mutex<HashMap<String, SeenState>> = Mutex::new(HashMap::new());
+}
+
+pub fn register_hook(hook: TxStateHook) {
+    HOOKS.write().unwrap().push(hook);
+}
+
+/// Remembers last seen state of transaction and notifies hooks, if it changed.
+/// Transactions without previous state are assumed to be pending.
+pub fn update(tx_hash: &str, new_state: TxState) {
+    let old_state = {
+        let now = Instant::now();
+        let mut states = STATES.lock().unwrap();
+        prune(&mut states, now);
+        let old_state = match new_state.is_final() {
+            true => states.remove(tx_hash),
+            false => states.insert(
+                tx_hash.to_string(),
+                SeenState {
+                    state: new_state.clone(),
+                    last_seen: now,
+                },
+            ),
+        };
+        old_state.map(|seen| seen.state).unwrap_or(TxState::Pending)
+    };
+
+    if old_state == new_state {
+        return;
+    }
+
+    log::debug!(
+        "Transaction state changed. tx_hash={}, old_state={:?}, new_state={:?}",
+        tx_hash,
+        old_state,
+        new_state
+    );
+    for hook in HOOKS.read().unwrap().iter() {
+        hook(tx_hash, &old_state, &new_state);
+    }
+}
+
+fn prune(states: &mut HashMap<String, SeenState>, now: Instant) {
+    states.retain(|_, seen| now.saturating_duration_since(seen.last_seen) < STATE_TTL);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_hook_fires_on_state_change() {
+        let tx_hash = "test_hook_fires_on_state_change";
+        let changes = Arc::new(Mutex::new(vec![]));
+        let hook_changes = changes.clone();
+        register_hook(Box::new(move |hash, old, new| {
+            if hash == tx_hash {
+                hook_changes
+                    .lock()
+                    .unwrap()
+                    .push((old.clone(), new.clone()));
+            }
+        }));
+
+        update(tx_hash, TxState::Pending);
+        update(tx_hash, TxState::Committed);
+        update(tx_hash, TxState::Committed);
+        update(tx_hash, TxState::Verified);
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![
+                (TxState::Pending, TxState::Committed),
+                (TxState::Committed, TxState::Verified),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_stale_states() {
+        let now = Instant::now();
+        let seen = |state: TxState, last_seen: Instant| SeenState { state, last_seen };
+        let mut states = HashMap::new();
+        states.insert("stale".to_string(), seen(TxState::Pending, now));
+        states.insert(
+            "fresh".to_string(),
+            seen(TxState::Committed, now + STATE_TTL),
+        );
+
+        prune(&mut states, now + STATE_TTL);
+        assert!(!states.contains_key("stale"));
+        assert_eq!(states["fresh"].state, TxState::Committed);
+    }
+}
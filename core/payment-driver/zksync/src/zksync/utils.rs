@@ -4,20 +4,20 @@
 
 // External uses
 use bigdecimal::BigDecimal;
-use lazy_static::lazy_static;
 use num_bigint::{BigInt, BigUint, ToBigInt};
+use std::str::FromStr;
 use zksync::utils::{closest_packable_token_amount, is_token_amount_packable};
 
 // Workspace uses
 use ya_payment_driver::model::GenericError;
 
-lazy_static! {
-    // TODO: Get token decimals from zksync-provider / wallet
-    pub static ref PRECISION: BigDecimal = BigDecimal::from(1_000_000_000_000_000_000u64);
+/// 10^decimals, i.e. number of base units in one token.
+fn precision(decimals: u32) -> BigDecimal {
+    BigDecimal::new(BigInt::from(1), -(decimals as i64))
 }
 
-pub fn big_dec_to_big_uint(v: BigDecimal) -> Result<BigUint, GenericError> {
-    let v = v * &(*PRECISION);
+pub fn big_dec_to_big_uint(v: BigDecimal, decimals: u32) -> Result<BigUint, GenericError> {
+    let v = v * precision(decimals);
     let v = v
         .to_bigint()
         .ok_or(GenericError::new("Failed to convert to bigint"))?;
@@ -27,9 +27,47 @@ pub fn big_dec_to_big_uint(v: BigDecimal) -> Result<BigUint, GenericError> {
     Ok(v)
 }
 
-pub fn big_uint_to_big_dec(v: BigUint) -> BigDecimal {
+pub fn big_uint_to_big_dec(v: BigUint, decimals: u32) -> BigDecimal {
     let v: BigDecimal = Into::<BigInt>::into(v).into();
-    v / &(*PRECISION)
+    v / precision(decimals)
+}
+
+/// How transfer amount is rounded to zkSync's packable format, which keeps
+/// only about 10 significant digits. `Up` never underpays, but the payer may
+/// send slightly more than requested. `Down` never overpays, but the payee
+/// may get slightly less. Either way the difference is far below the fee.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PackPolicy {
+    Up,
+    Down,
+}
+
+impl Default for PackPolicy {
+    fn default() -> Self {
+        PackPolicy::Up
+    }
+}
+
+impl FromStr for PackPolicy {
+    type Err = GenericError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "up" => Ok(PackPolicy::Up),
+            "down" => Ok(PackPolicy::Down),
+            other => Err(GenericError::new(format!(
+                "Invalid pack policy: {}, expected `up` or `down`",
+                other
+            ))),
+        }
+    }
+}
+
+pub fn pack(amount: &BigUint, policy: PackPolicy) -> BigUint {
+    match policy {
+        PackPolicy::Up => pack_up(amount),
+        PackPolicy::Down => pack_down(amount),
+    }
 }
 
 /// Find the closest **bigger** packable amount
@@ -41,6 +79,15 @@ pub fn pack_up(amount: &BigUint) -> BigUint {
     packable_amount
 }
 
+/// Find the closest **smaller** packable amount
+pub fn pack_down(amount: &BigUint) -> BigUint {
+    let mut packable_amount = closest_packable_token_amount(&amount);
+    while (&packable_amount > amount) || !is_token_amount_packable(&packable_amount) {
+        packable_amount = decrease_least_significant_digit(&packable_amount);
+    }
+    packable_amount
+}
+
 fn increase_least_significant_digit(amount: &BigUint) -> BigUint {
     let digits = amount.to_radix_le(10);
     for i in 0..digits.len() {
@@ -51,10 +98,19 @@ fn increase_least_significant_digit(amount: &BigUint) -> BigUint {
     amount.clone() // zero
 }
 
+fn decrease_least_significant_digit(amount: &BigUint) -> BigUint {
+    let digits = amount.to_radix_le(10);
+    for i in 0..digits.len() {
+        if digits[i] != 0 {
+            return amount - BigUint::from(10u32).pow(i as u32);
+        }
+    }
+    amount.clone() // zero
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::str::FromStr;
 
     #[test]
     fn test_increase_least_significant_digit() {
@@ -64,6 +120,29 @@ mod tests {
         assert_eq!(increased, expected);
     }
 
+    #[test]
+    fn test_conversion_18_decimals() {
+        let amount = BigDecimal::from_str("12.345678901234567891").unwrap();
+        let base_units = big_dec_to_big_uint(amount.clone(), 18).unwrap();
+        assert_eq!(
+            base_units,
+            BigUint::from_str("12345678901234567891").unwrap()
+        );
+        assert_eq!(big_uint_to_big_dec(base_units, 18), amount);
+    }
+
+    #[test]
+    fn test_conversion_6_decimals() {
+        let amount = BigDecimal::from_str("12.345678").unwrap();
+        let base_units = big_dec_to_big_uint(amount.clone(), 6).unwrap();
+        assert_eq!(base_units, BigUint::from_str("12345678").unwrap());
+        assert_eq!(big_uint_to_big_dec(base_units, 6), amount);
+
+        // Amounts below smallest unit are truncated.
+        let amount = BigDecimal::from_str("0.0000019").unwrap();
+        assert_eq!(big_dec_to_big_uint(amount, 6).unwrap(), BigUint::from(1u32));
+    }
+
     #[test]
     fn test_pack_up() {
         let amount = BigUint::from_str("12300285190700000000").unwrap();
@@ -74,4 +153,33 @@ mod tests {
         );
         assert!(packable >= amount, "To little!");
     }
+
+    #[test]
+    fn test_pack_up_and_down() {
+        for amount in &[
+            "0",
+            "1",
+            "999000",
+            "12300285190700000000",
+            "34359738369",
+            "1000000000000000001",
+            "123456789012345678901234567",
+        ] {
+            let amount = BigUint::from_str(amount).unwrap();
+            let up = pack_up(&amount);
+            let down = pack_down(&amount);
+            assert!(is_token_amount_packable(&up), "{} not packable", up);
+            assert!(is_token_amount_packable(&down), "{} not packable", down);
+            assert!(up >= amount, "{} packed up to {}", amount, up);
+            assert!(amount >= down, "{} packed down to {}", amount, down);
+            assert_eq!(pack(&amount, PackPolicy::Down), down);
+        }
+    }
+
+    #[test]
+    fn test_pack_policy_from_str() {
+        assert_eq!(PackPolicy::from_str("up").unwrap(), PackPolicy::Up);
+        assert_eq!(PackPolicy::from_str("Down").unwrap(), PackPolicy::Down);
+        assert!(PackPolicy::from_str("nearest").is_err());
+    }
 }
@@ -4,13 +4,23 @@
 
 // External crates
 use bigdecimal::{BigDecimal, Zero};
+use futures3::{Future, Stream};
+use lazy_static::lazy_static;
 use num_bigint::BigUint;
+use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use zksync::operations::SyncTransactionHandle;
-use zksync::types::BlockStatus;
-use zksync::zksync_types::{tx::TxHash, Address, Nonce, TxFeeTypes};
+use zksync::types::{AccountInfo, BlockStatus, TransactionInfo};
+use zksync::zksync_types::{
+    fee::ChangePubKeyFeeTypeArg,
+    tx::{ChangePubKeyType, PackedEthSignature, TxHash},
+    Address, Nonce, TxFeeTypes, ZkSyncTx,
+};
 use zksync::{
+    error::ClientError,
     provider::get_rpc_addr,
     provider::{Provider, RpcProvider},
     Network as ZkNetwork, Wallet, WalletCredentials,
@@ -20,40 +30,198 @@ use zksync_eth_signer::EthereumSigner;
 // Workspace uses
 use ya_payment_driver::{
     db::models::Network,
-    model::{AccountMode, Exit, GenericError, Init, PaymentDetails},
+    model::{
+        AccountMode, Exit, ExitConfirmation, GenericError, HealthStatus, Init, PaymentDetails,
+    },
 };
 
 // Local uses
 use crate::{
-    network::get_network_token,
-    zksync::{faucet, signer::YagnaEthSigner, utils},
+    network::{get_network_token, SUPPORTED_NETWORKS},
+    zksync::{
+        error::{AmountError, ZksyncError},
+        faucet,
+        rpc::{self, ZksyncRpc},
+        signer::YagnaEthSigner,
+        tx_log::{self, TxLog, TxStage},
+        tx_state::{self, TxState},
+        utils::{self, PackPolicy},
+    },
     DEFAULT_NETWORK,
 };
 
-pub async fn account_balance(address: &str, network: Network) -> Result<BigDecimal, GenericError> {
-    let pub_address = Address::from_str(&address[2..]).map_err(GenericError::new)?;
-    let acc_info = get_provider(network)
+/// `Committed` balance includes transactions, which are not finalized yet.
+/// Only `Verified` balance is safe to rely on when withdrawing.
+pub async fn account_balance(
+    address: &str,
+    network: Network,
+    status: BlockStatus,
+) -> Result<BigDecimal, GenericError> {
+    let pub_address = parse_address(address)?;
+    let acc_info = get_rpc(network)?
         .account_info(pub_address)
         .await
         .map_err(GenericError::new)?;
     // TODO: implement tokens, replace None
     let token = get_network_token(network, None);
-    let balance_com = acc_info
-        .committed
-        .balances
-        .get(&token)
-        .map(|x| x.0.clone())
-        .unwrap_or(BigUint::zero());
-    let balance = utils::big_uint_to_big_dec(balance_com);
+    let decimals = get_token_decimals(&token, network).await?;
+    let balance = status_balance(&acc_info, &token, decimals, status);
     log::debug!(
-        "account_balance. address={}, network={}, balance={}",
+        "account_balance. address={}, network={}, status={:?}, balance={}",
         address,
         &network,
+        status,
         &balance
     );
     Ok(balance)
 }
 
+fn status_balance(
+    acc_info: &AccountInfo,
+    token: &str,
+    decimals: u32,
+    status: BlockStatus,
+) -> BigDecimal {
+    let state = match status {
+        BlockStatus::Committed => &acc_info.committed,
+        BlockStatus::Verified => &acc_info.verified,
+    };
+    let balance = state
+        .balances
+        .get(token)
+        .map(|x| x.0.clone())
+        .unwrap_or(BigUint::zero());
+    utils::big_uint_to_big_dec(balance, decimals)
+}
+
+/// Balances of an account on all supported networks.
+#[derive(Clone, Debug, Default)]
+pub struct AggregatedBalance {
+    pub balances: HashMap<String, BigDecimal>,
+    /// Networks, which balance couldn't be fetched for, e.g. because of
+    /// misconfigured or unreachable RPC.
+    pub errors: HashMap<String, String>,
+    pub total: BigDecimal,
+}
+
+/// Committed balance of `address` summed over all supported networks.
+/// Failure on a single network doesn't abort the aggregation.
+pub async fn aggregate_balance(address: &str) -> AggregatedBalance {
+    let mut aggregated = AggregatedBalance::default();
+    for network_key in SUPPORTED_NETWORKS.keys() {
+        let balance = match Network::from_str(network_key) {
+            Ok(network) => account_balance(address, network, BlockStatus::Committed).await,
+            Err(e) => Err(GenericError::new(e)),
+        };
+        match balance {
+            Ok(balance) => {
+                aggregated.total += balance.clone();
+                aggregated.balances.insert(network_key.clone(), balance);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch balance. address={}, network={}, error={}",
+                    address,
+                    network_key,
+                    e
+                );
+                aggregated.errors.insert(network_key.clone(), e.to_string());
+            }
+        }
+    }
+    aggregated
+}
+
+/// Polls balance of the account every `interval` and yields it only, when
+/// it changed since the previous poll. Polling stops, when stream is dropped.
+pub fn watch_balance(
+    address: String,
+    network: Network,
+    interval: Duration,
+) -> impl Stream<Item = BigDecimal> {
+    watch_changes(interval, move || {
+        let address = address.clone();
+        async move { account_balance(&address, network, BlockStatus::Committed).await }
+    })
+}
+
+fn watch_changes<F, Fut>(interval: Duration, fetch: F) -> impl Stream<Item = BigDecimal>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<BigDecimal, GenericError>>,
+{
+    futures3::stream::unfold(
+        (fetch, None, true),
+        move |(mut fetch, last, first): (F, Option<BigDecimal>, bool)| async move {
+            if !first {
+                tokio::time::delay_for(interval).await;
+            }
+            loop {
+                match fetch().await {
+                    Ok(balance) if Some(&balance) != last.as_ref() => {
+                        return Some((balance.clone(), (fetch, Some(balance), false)));
+                    }
+                    Ok(_) => (),
+                    Err(e) => log::warn!("Failed to poll account balance. {}", e),
+                }
+                tokio::time::delay_for(interval).await;
+            }
+        },
+    )
+}
+
+/// Health check gives up after this time, so readiness probes don't hang.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks if zkSync RPC of given network responds, using cheap call
+/// fetching contract address. On testnets faucet has to be reachable too.
+/// Meant for readiness probes.
+pub async fn health(network: Network) -> Result<HealthStatus, GenericError> {
+    let mut status = provider_health(network, get_provider(network)?).await;
+    if status.reachable && network != Network::Mainnet {
+        if let Err(e) = faucet::check_reachable(HEALTH_CHECK_TIMEOUT).await {
+            status.reachable = false;
+            status.error = Some(format!("Faucet unreachable: {}", e));
+        }
+    }
+    Ok(status)
+}
+
+async fn provider_health(network: Network, provider: RpcProvider) -> HealthStatus {
+    check_health(network, async move {
+        provider
+            .contract_address()
+            .await
+            .map(|_| ())
+            .map_err(GenericError::new)
+    })
+    .await
+}
+
+async fn check_health(
+    network: Network,
+    call: impl Future<Output = Result<(), GenericError>>,
+) -> HealthStatus {
+    let start = Instant::now();
+    let result = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, call).await {
+        Ok(result) => result,
+        Err(_) => Err(GenericError::new(format!(
+            "No response within {}s",
+            HEALTH_CHECK_TIMEOUT.as_secs()
+        ))),
+    };
+    let latency_ms = start.elapsed().as_millis() as u64;
+    if let Err(e) = &result {
+        log::warn!("zkSync RPC unreachable. network={}, error={}", network, e);
+    }
+    HealthStatus {
+        network: network.to_string(),
+        reachable: result.is_ok(),
+        latency_ms,
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
 pub async fn init_wallet(msg: &Init) -> Result<(), GenericError> {
     log::debug!("init_wallet. msg={:?}", msg);
     let mode = msg.mode();
@@ -63,7 +231,7 @@ pub async fn init_wallet(msg: &Init) -> Result<(), GenericError> {
 
     if mode.contains(AccountMode::SEND) {
         let wallet = get_wallet(&address, network).await?;
-        unlock_wallet(&wallet, network).await?;
+        unlock_wallet(&wallet, network, &msg.fee_tokens()).await?;
     }
     Ok(())
 }
@@ -77,18 +245,17 @@ pub async fn fund(address: &str, network: Network) -> Result<(), GenericError> {
 }
 
 pub async fn exit(msg: &Exit) -> Result<String, GenericError> {
-    let network = msg.network().unwrap_or(DEFAULT_NETWORK.to_string());
-    let network = Network::from_str(&network).map_err(|e| GenericError::new(e))?;
-    let wallet = get_wallet(&msg.sender(), network).await?;
-    unlock_wallet(&wallet, network).await?;
-    let tx_handle = withdraw(wallet, network, msg.amount(), msg.to()).await?;
-    let tx_info = tx_handle
-        .wait_for_commit()
-        .await
-        .map_err(GenericError::new)?;
+    let (tx_hash, tx_handle) = exit_async(msg).await?;
+    let tx_info = wait_for_confirmation(
+        msg.confirmation(),
+        tx_handle.wait_for_commit(),
+        tx_handle.wait_for_verify(),
+    )
+    .await
+    .map_err(GenericError::new)?;
 
     match tx_info.success {
-        Some(true) => Ok(hash_to_hex(tx_handle.hash())),
+        Some(true) => Ok(tx_hash),
         Some(false) => Err(GenericError::new(
             tx_info
                 .fail_reason
@@ -98,16 +265,48 @@ pub async fn exit(msg: &Exit) -> Result<String, GenericError> {
     }
 }
 
+/// Only the future matching `confirmation` is awaited.
+async fn wait_for_confirmation<T>(
+    confirmation: ExitConfirmation,
+    commit: impl Future<Output = T>,
+    verify: impl Future<Output = T>,
+) -> T {
+    log::debug!("Waiting for withdrawal confirmation={:?}", confirmation);
+    match confirmation {
+        ExitConfirmation::Commit => commit.await,
+        ExitConfirmation::Verify => verify.await,
+    }
+}
+
+/// Sends withdrawal transaction and returns its hash without waiting for commit.
+/// Caller can await commit using returned handle or poll it with `check_tx`.
+pub async fn exit_async(
+    msg: &Exit,
+) -> Result<(String, SyncTransactionHandle<RpcProvider>), GenericError> {
+    let network = msg.network().unwrap_or(DEFAULT_NETWORK.to_string());
+    let network = Network::from_str(&network).map_err(|e| GenericError::new(e))?;
+    let wallet = get_wallet(&msg.sender(), network).await?;
+    unlock_wallet(&wallet, network, &[]).await?;
+    let tx_handle = withdraw(wallet, network, msg.amount(), msg.percent(), msg.to()).await?;
+    let tx_hash = hash_to_hex(tx_handle.hash());
+    log::info!(
+        "Created zksync withdrawal transaction with hash={}",
+        tx_hash
+    );
+    Ok((tx_hash, tx_handle))
+}
+
+/// Only reads fee from provider, so unlike `get_wallet` it doesn't need signer.
 pub async fn get_tx_fee(address: &str, network: Network) -> Result<BigDecimal, GenericError> {
     let token = get_network_token(network, None);
-    let wallet = get_wallet(&address, network).await?;
-    let tx_fee = wallet
-        .provider
-        .get_tx_fee(TxFeeTypes::Transfer, wallet.address(), token.as_str())
+    let pub_address = parse_address(address)?;
+    let tx_fee = get_rpc(network)?
+        .get_tx_fee(TxFeeTypes::Transfer, pub_address, token.as_str())
         .await
         .map_err(GenericError::new)?
         .total_fee;
-    let tx_fee_bigdec = utils::big_uint_to_big_dec(tx_fee);
+    let decimals = get_token_decimals(&token, network).await?;
+    let tx_fee_bigdec = utils::big_uint_to_big_dec(tx_fee, decimals);
 
     log::debug!("Transaction fee {:.5} {}", tx_fee_bigdec, token.as_str());
     Ok(tx_fee_bigdec)
@@ -118,52 +317,189 @@ fn hash_to_hex(hash: TxHash) -> String {
     hex::encode(hash.as_ref())
 }
 
-pub async fn get_nonce(address: &str, network: Network) -> u32 {
-    let addr = match Address::from_str(&address[2..]) {
-        Ok(a) => a,
-        Err(e) => {
-            log::error!("Unable to parse address, failed to get nonce. {:?}", e);
-            return 0;
-        }
-    };
-    let provider = get_provider(network);
-    let account_info = match provider.account_info(addr).await {
-        Ok(i) => i,
-        Err(e) => {
-            log::error!("Unable to get account info, failed to get nonce. {:?}", e);
-            return 0;
+/// Nonces known to the driver for given account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountNonce {
+    /// Nonce of the account according to committed zksync state.
+    pub committed: u32,
+    /// Highest nonce used by transactions sent from this driver, which
+    /// are possibly not committed yet. Taken from persisted nonce cache.
+    pub pending: Option<u32>,
+}
+
+impl AccountNonce {
+    /// First nonce, that won't collide neither with committed nor with
+    /// already sent transactions.
+    pub fn next(&self) -> u32 {
+        match self.pending {
+            Some(pending) if pending >= self.committed => pending + 1,
+            _ => self.committed,
         }
-    };
-    *account_info.committed.nonce
+    }
+}
+
+/// Committed nonce from zksync lags behind transactions, that were sent,
+/// but not committed yet. Use `AccountNonce` to take them into account.
+pub async fn get_committed_nonce(address: &str, network: Network) -> Result<u32, GenericError> {
+    let addr = parse_address(address)?;
+    let account_info = get_rpc(network)?
+        .account_info(addr)
+        .await
+        .map_err(GenericError::new)?;
+    Ok(*account_info.committed.nonce)
+}
+
+pub fn parse_address(address: &str) -> Result<Address, ZksyncError> {
+    let invalid = || ZksyncError::InvalidAddress(address.to_string());
+    let hex = address.strip_prefix("0x").ok_or_else(invalid)?;
+    Address::from_str(hex).map_err(|_| invalid())
+}
+
+fn check_balance(balance: &BigUint, amount: &BigUint, decimals: u32) -> Result<(), ZksyncError> {
+    if balance < amount {
+        return Err(ZksyncError::InsufficientBalance {
+            required: utils::big_uint_to_big_dec(amount.clone(), decimals),
+            available: utils::big_uint_to_big_dec(balance.clone(), decimals),
+        });
+    }
+    Ok(())
+}
+
+lazy_static! {
+    // Transfers smaller than that would cost more in fees than they are worth.
+    static ref MIN_TRANSFER: BigDecimal =
+        match env::var("ZKSYNC_MIN_TRANSFER").map(|s| s.parse()) {
+            Ok(Ok(x)) => x,
+            Ok(Err(e)) => {
+                log::warn!(
+                    "Invalid ZKSYNC_MIN_TRANSFER={:?}, no minimum will be applied. {}",
+                    env::var("ZKSYNC_MIN_TRANSFER").unwrap_or_default(),
+                    e
+                );
+                BigDecimal::zero()
+            }
+            Err(_) => BigDecimal::zero(),
+        };
+}
+
+/// Fails with `AmountError::BelowMinimum`, so the payment layer can tell it
+/// apart and accumulate the payment with later ones instead.
+fn check_min_transfer(
+    amount: &BigUint,
+    min: &BigDecimal,
+    decimals: u32,
+) -> Result<(), ZksyncError> {
+    let min_amount = utils::big_dec_to_big_uint(min.clone(), decimals)?;
+    if *amount < min_amount {
+        return Err(ZksyncError::InvalidAmount {
+            amount: utils::big_uint_to_big_dec(amount.clone(), decimals),
+            reason: AmountError::BelowMinimum(min.clone()),
+        });
+    }
+    Ok(())
+}
+
+/// Transfer signed, but not sent yet. Its hash is known before sending, so
+/// it can be persisted to recognize retries of the same payment.
+pub struct PreparedTransfer {
+    tx: ZkSyncTx,
+    eth_signature: Option<PackedEthSignature>,
+    tx_log: TxLog,
+    sender: String,
+    nonce: u32,
+    network: Network,
+}
+
+impl PreparedTransfer {
+    pub fn tx_hash(&self) -> String {
+        hash_to_hex(self.tx.hash())
+    }
+
+    /// Serialized transaction with its signature, to be stored with transfer
+    /// attempt.
+    pub fn signed_tx(&self) -> Result<String, GenericError> {
+        serde_json::to_string(&SignedTransfer {
+            tx: self.tx.clone(),
+            eth_signature: self.eth_signature.clone(),
+        })
+        .map_err(GenericError::new)
+    }
+
+    /// Restores transfer from `signed_tx`, so it can be sent again unchanged.
+    pub fn from_signed_tx(
+        signed_tx: &str,
+        details: &PaymentDetails,
+        nonce: u32,
+        network: Network,
+    ) -> Result<Self, GenericError> {
+        let SignedTransfer { tx, eth_signature } =
+            serde_json::from_str(signed_tx).map_err(GenericError::new)?;
+        Ok(PreparedTransfer {
+            tx,
+            eth_signature,
+            tx_log: TxLog::new(details, nonce, network),
+            sender: details.sender.clone(),
+            nonce,
+            network,
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SignedTransfer {
+    tx: ZkSyncTx,
+    eth_signature: Option<PackedEthSignature>,
 }
 
-pub async fn make_transfer(
+/// Amount is packed according to `pack` policy and the packed value is what
+/// gets checked against balance and sent.
+pub async fn prepare_transfer(
     details: &PaymentDetails,
     nonce: u32,
     network: Network,
-) -> Result<String, GenericError> {
-    log::debug!("make_transfer. {:?}", details);
+    pack: PackPolicy,
+) -> Result<PreparedTransfer, ZksyncError> {
+    log::debug!("prepare_transfer. {:?}", details);
+    if details.amount <= BigDecimal::zero() {
+        return Err(ZksyncError::InvalidAmount {
+            amount: details.amount.clone(),
+            reason: AmountError::NotPositive,
+        });
+    }
+    let recipient = parse_address(&details.recipient)?;
+    let token = get_network_token(network, None);
+    let decimals = get_token_decimals(&token, network).await?;
     let amount = details.amount.clone();
-    let amount = utils::big_dec_to_big_uint(amount)?;
-    let amount = utils::pack_up(&amount);
+    let amount = utils::big_dec_to_big_uint(amount, decimals)?;
+    let amount = utils::pack(&amount, pack);
+    log::debug!(
+        "Transfer amount packed. original={}, packed={}, policy={:?}",
+        details.amount,
+        utils::big_uint_to_big_dec(amount.clone(), decimals),
+        pack
+    );
+    if amount.is_zero() {
+        return Err(ZksyncError::InvalidAmount {
+            amount: details.amount.clone(),
+            reason: AmountError::RoundsToZero,
+        });
+    }
+    check_min_transfer(&amount, &MIN_TRANSFER, decimals)?;
 
     let sender = details.sender.clone();
     let wallet = get_wallet(&sender, network).await?;
-    let token = get_network_token(network, None);
 
     let balance = wallet
         .get_balance(BlockStatus::Committed, token.as_str())
-        .await
-        .map_err(GenericError::new)?;
+        .await?;
     log::debug!("balance before transfer={}", balance);
+    check_balance(&balance, &amount, decimals)?;
 
     let transfer_builder = wallet
         .start_transfer()
         .nonce(Nonce(nonce))
-        .str_to(&details.recipient[2..])
-        .map_err(GenericError::new)?
-        .token(token.as_str())
-        .map_err(GenericError::new)?
+        .to(recipient)
+        .token(token.as_str())?
         .amount(amount.clone());
     log::debug!(
         "transfer raw data. nonce={}, to={}, token={}, amount={}",
@@ -172,19 +508,113 @@ pub async fn make_transfer(
         token,
         amount
     );
-    let transfer = transfer_builder.send().await.map_err(GenericError::new)?;
+    let (tx, eth_signature) = transfer_builder.tx().await?;
+    Ok(PreparedTransfer {
+        tx,
+        eth_signature,
+        tx_log: TxLog::new(details, nonce, network),
+        sender,
+        nonce,
+        network,
+    })
+}
+
+pub async fn send_transfer(transfer: PreparedTransfer) -> Result<String, ZksyncError> {
+    let PreparedTransfer {
+        tx,
+        eth_signature,
+        tx_log,
+        sender,
+        nonce,
+        network,
+    } = transfer;
+    let provider = get_provider(network)?;
+    let hash = provider.send_tx(tx, eth_signature).await.map_err(|e| {
+        tx_log.log(TxStage::Failed, None);
+        ZksyncError::from_send_error(e)
+    })?;
 
-    let tx_hash = hex::encode(transfer.hash());
+    let tx_hash = hash_to_hex(hash);
     log::info!("Created zksync transaction with hash={}", tx_hash);
+    tx_log::created(tx_log, &tx_hash);
     Ok(tx_hash)
 }
 
+/// Outcome of a transfer, which was signed, but it isn't known whether
+/// sending it succeeded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferProbe {
+    /// Transfer was executed, it must not be sent again.
+    Executed,
+    /// Nonce of the transfer was used by another transaction, so this
+    /// transfer will never be executed.
+    Dropped,
+    /// Transfer wasn't executed and its nonce is still free. It might still
+    /// be pending, so only the very same signed transaction can be sent again.
+    Unknown,
+}
+
+impl TransferProbe {
+    pub fn new(executed: bool, committed_nonce: u32, nonce: u32) -> Self {
+        match (executed, committed_nonce > nonce) {
+            (true, _) => TransferProbe::Executed,
+            (false, true) => TransferProbe::Dropped,
+            (false, false) => TransferProbe::Unknown,
+        }
+    }
+}
+
+pub async fn probe_transfer(
+    tx_hash: &str,
+    sender: &str,
+    nonce: u32,
+    network: Network,
+) -> Result<TransferProbe, GenericError> {
+    let sync_tx_hash =
+        TxHash::from_str(&format!("sync-tx:{}", tx_hash)).map_err(GenericError::new)?;
+    let tx_info = get_rpc(network)?
+        .tx_info(sync_tx_hash)
+        .await
+        .map_err(GenericError::new)?;
+    let committed_nonce = get_committed_nonce(sender, network).await?;
+    let probe = TransferProbe::new(tx_info.executed, committed_nonce, nonce);
+    log::debug!(
+        "probe_transfer. tx_hash={}, nonce={}, committed_nonce={}, probe={:?}",
+        tx_hash,
+        nonce,
+        committed_nonce,
+        probe
+    );
+    Ok(probe)
+}
+
 pub async fn check_tx(tx_hash: &str, network: Network) -> Option<Result<(), String>> {
-    let provider = get_provider(network);
-    let tx_hash = format!("sync-tx:{}", tx_hash);
-    let tx_hash = TxHash::from_str(&tx_hash).unwrap();
-    let tx_info = provider.tx_info(tx_hash).await.unwrap();
+    let provider = match get_rpc(network) {
+        Ok(provider) => provider,
+        Err(e) => {
+            log::error!(
+                "Unable to check transaction. tx_hash={}, error={}",
+                tx_hash,
+                e
+            );
+            return None;
+        }
+    };
+    let sync_tx_hash = format!("sync-tx:{}", tx_hash);
+    let sync_tx_hash = TxHash::from_str(&sync_tx_hash).unwrap();
+    let tx_info = match provider.tx_info(sync_tx_hash).await {
+        Ok(tx_info) => tx_info,
+        Err(e) => {
+            log::error!(
+                "Unable to check transaction. tx_hash={}, error={}",
+                tx_hash,
+                e
+            );
+            return None;
+        }
+    };
     log::trace!("tx_info: {:?}", tx_info);
+    tx_state::update(tx_hash, TxState::from_tx_info(&tx_info));
     match tx_info.success {
         None => None,
         Some(true) => Some(Ok(())),
@@ -195,67 +625,508 @@ pub async fn check_tx(tx_hash: &str, network: Network) -> Option<Result<(), Stri
     }
 }
 
+/// Polls `check_tx` every `interval`, until transaction is committed or failed.
+pub async fn wait_for_tx(
+    tx_hash: &str,
+    network: Network,
+    interval: Duration,
+    timeout: Duration,
+) -> Result<(), GenericError> {
+    poll_tx(tx_hash, interval, timeout, || check_tx(tx_hash, network)).await
+}
+
+async fn poll_tx<F, Fut>(
+    tx_hash: &str,
+    interval: Duration,
+    timeout: Duration,
+    check: F,
+) -> Result<(), GenericError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Option<Result<(), String>>>,
+{
+    let poll = async {
+        loop {
+            match check().await {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(e)) => {
+                    return Err(GenericError::new(format!(
+                        "Transaction failed. tx_hash={}, error={}",
+                        tx_hash, e
+                    )))
+                }
+                None => tokio::time::delay_for(interval).await,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, poll).await.map_err(|_| {
+        GenericError::new(format!(
+            "Transaction not confirmed within {}s. tx_hash={}",
+            timeout.as_secs_f32(),
+            tx_hash
+        ))
+    })?
+}
+
+/// Filters out hashes of transactions, which are already committed or failed.
+pub async fn pending_transactions(tx_hashes: Vec<String>, network: Network) -> Vec<String> {
+    retain_pending(tx_hashes, |tx_hash| async move {
+        check_tx(&tx_hash, network).await
+    })
+    .await
+}
+
+async fn retain_pending<F, Fut>(tx_hashes: Vec<String>, check: F) -> Vec<String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Option<Result<(), String>>>,
+{
+    let mut pending = vec![];
+    for tx_hash in tx_hashes {
+        // `check_tx` returns None, when the result is unknown yet.
+        if check(tx_hash.clone()).await.is_none() {
+            pending.push(tx_hash);
+        }
+    }
+    pending
+}
+
 #[derive(serde::Deserialize)]
 struct TxRespObj {
     to: String,
     from: String,
+    #[serde(default)]
+    token: Option<String>,
     amount: String,
     created_at: String,
 }
 
-pub async fn verify_tx(tx_hash: &str, network: Network) -> Result<PaymentDetails, GenericError> {
-    let provider_url = match get_rpc_addr_from_env(network) {
+/// Payment details of verified transaction along with token and network,
+/// it was made on.
+#[derive(Clone, Debug)]
+pub struct VerifiedTx {
+    pub details: PaymentDetails,
+    pub token: String,
+    pub network: Network,
+}
+
+pub async fn verify_tx(tx_hash: &str, network: Network) -> Result<VerifiedTx, GenericError> {
+    let req_url = format!("{}/transactions_all/{}", api_url(network, "v0.1")?, tx_hash);
+    let response = api_get(req_url).await?;
+    let verified = parse_tx_response(&response, network, &token_decimals(network).await?)?;
+    log::debug!("PaymentDetails from server: {:?}", &verified);
+
+    if *CROSS_CHECK_TX {
+        cross_check_tx(tx_hash, network, &verified).await?;
+    }
+    Ok(verified)
+}
+
+/// Max page size accepted by v0.1 api history endpoint.
+const HISTORY_PAGE_SIZE: usize = 100;
+
+/// Recent transfers sent or received by `address`, newest first.
+/// Other transaction types (e.g. deposits, unlocks) are skipped.
+pub async fn list_transactions(
+    address: &str,
+    network: Network,
+    limit: usize,
+) -> Result<Vec<VerifiedTx>, GenericError> {
+    parse_address(address)?;
+    let api_url = api_url(network, "v0.1")?;
+    let decimals = token_decimals(network).await?;
+    let mut transfers = vec![];
+    let mut offset = 0;
+    while transfers.len() < limit {
+        let page_size = HISTORY_PAGE_SIZE.min(limit - transfers.len());
+        let req_url = format!(
+            "{}/account/{}/history/{}/{}",
+            api_url, address, offset, page_size
+        );
+        let response = api_get(req_url).await?;
+        let (entries, page) = parse_history_response(&response, network, &decimals)?;
+        transfers.extend(page);
+        if entries < page_size {
+            break;
+        }
+        offset += entries;
+    }
+    transfers.truncate(limit);
+    Ok(transfers)
+}
+
+/// HACK: Transaction data is taken from REST api, RPC doesn't provide it.
+fn api_url(network: Network, version: &str) -> Result<String, GenericError> {
+    let deployment = get_deployment(network)?;
+    let provider_url = match deployment.rpc_addr {
         Some(rpc_addr) => rpc_addr,
-        None => get_rpc_addr(get_zk_network(network)).to_string(),
+        None => get_rpc_addr(deployment.zk_network).to_string(),
     };
-    // HACK: Get the transaction data from v0.1 api
-    let api_url = provider_url.replace("/jsrpc", "/api/v0.1");
-    let req_url = format!("{}/transactions_all/{}", api_url, tx_hash);
-    log::debug!("Request URL: {}", &req_url);
+    Ok(provider_url.replace("/jsrpc", &format!("/api/{}", version)))
+}
 
+async fn api_get(req_url: String) -> Result<String, GenericError> {
+    log::debug!("Request URL: {}", &req_url);
     let client = awc::Client::new();
-    let response = client
-        .get(req_url)
+    let mut response = client
+        .get(req_url.as_str())
         .send()
         .await
-        .map_err(GenericError::new)?
-        .body()
+        .map_err(GenericError::new)?;
+    let body = response.body().await.map_err(GenericError::new)?;
+    let body = String::from_utf8_lossy(body.as_ref()).to_string();
+    log::trace!("Request response: {}", &body);
+    if !response.status().is_success() {
+        return Err(GenericError::new(format!(
+            "Request {} failed with status {}: {}",
+            req_url,
+            response.status(),
+            body
+        )));
+    }
+    Ok(body)
+}
+
+lazy_static! {
+    // When set, transactions reported by v0.1 REST API are also checked
+    // with RPC provider and v0.2 REST API, in case v0.1 endpoint is stale
+    // or compromised.
+    static ref CROSS_CHECK_TX: bool = env::var("ZKSYNC_CROSS_CHECK_TX")
+        .map(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+}
+
+/// RPC's `tx_info` carries only execution status, so transfer body is taken
+/// from v0.2 api. Transaction must be executed successfully and transfer
+/// the same amount between the same accounts according to all sources.
+async fn cross_check_tx(
+    tx_hash: &str,
+    network: Network,
+    verified: &VerifiedTx,
+) -> Result<(), GenericError> {
+    check_tx_status(tx_hash, network).await?;
+    let req_url = format!(
+        "{}/transactions/0x{}/data",
+        api_url(network, "v0.2")?,
+        tx_hash.trim_start_matches("0x")
+    );
+    let response = api_get(req_url).await?;
+    let decimals = get_token_decimals(&verified.token, network).await?;
+    match_tx_data(tx_hash, verified, decimals, &response)
+}
+
+async fn check_tx_status(tx_hash: &str, network: Network) -> Result<(), GenericError> {
+    let sync_tx_hash = TxHash::from_str(&format!("sync-tx:{}", tx_hash))
+        .map_err(|e| GenericError::new(format!("Invalid tx_hash={}: {}", tx_hash, e)))?;
+    let tx_info = get_rpc(network)?
+        .tx_info(sync_tx_hash)
         .await
         .map_err(GenericError::new)?;
-    let response = String::from_utf8_lossy(response.as_ref());
-    log::trace!("Request response: {}", &response);
-    let v: TxRespObj = serde_json::from_str(&response).map_err(GenericError::new)?;
-
-    let recipient = v.to;
-    let sender = v.from;
-    let amount =
-        utils::big_uint_to_big_dec(BigUint::from_str(&v.amount).map_err(GenericError::new)?);
-    let date_str = format!("{}Z", v.created_at);
-    let date = Some(chrono::DateTime::from_str(&date_str).map_err(GenericError::new)?);
-    let details = PaymentDetails {
-        recipient,
-        sender,
-        amount,
-        date,
-    };
-    log::debug!("PaymentDetails from server: {:?}", &details);
+    match_tx_info(tx_hash, &tx_info)
+}
 
-    Ok(details)
+fn match_tx_info(tx_hash: &str, tx_info: &TransactionInfo) -> Result<(), GenericError> {
+    match (tx_info.executed, tx_info.success) {
+        (true, Some(true)) => Ok(()),
+        (executed, success) => Err(GenericError::new(format!(
+            "REST API and RPC disagree on transaction {}. RPC reports executed={}, success={:?}, fail_reason={:?}",
+            tx_hash, executed, success, tx_info.fail_reason
+        ))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TxDataResp {
+    result: Option<TxDataResult>,
 }
 
-fn get_provider(network: Network) -> RpcProvider {
-    let zk_network = get_zk_network(network);
-    let provider: RpcProvider = match get_rpc_addr_from_env(network) {
-        Some(rpc_addr) => RpcProvider::from_addr_and_network(rpc_addr, zk_network),
-        None => RpcProvider::new(zk_network),
+#[derive(serde::Deserialize)]
+struct TxDataResult {
+    tx: TxData,
+}
+
+#[derive(serde::Deserialize)]
+struct TxData {
+    op: TxDataOp,
+}
+
+#[derive(serde::Deserialize)]
+struct TxDataOp {
+    #[serde(rename = "type")]
+    tx_type: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(default)]
+    amount: String,
+}
+
+fn match_tx_data(
+    tx_hash: &str,
+    verified: &VerifiedTx,
+    decimals: u32,
+    response: &str,
+) -> Result<(), GenericError> {
+    let resp: TxDataResp = serde_json::from_str(response).map_err(GenericError::new)?;
+    let op = match resp.result {
+        Some(result) => result.tx.op,
+        None => {
+            return Err(GenericError::new(format!(
+                "Transaction {} not found in v0.2 api: {}",
+                tx_hash, response
+            )))
+        }
     };
-    provider.clone()
+    let amount = BigUint::from_str(&op.amount)
+        .map(|amount| utils::big_uint_to_big_dec(amount, decimals))
+        .ok();
+    let details = &verified.details;
+    if op.tx_type != "Transfer"
+        || !op.from.eq_ignore_ascii_case(&details.sender)
+        || !op.to.eq_ignore_ascii_case(&details.recipient)
+        || amount.as_ref() != Some(&details.amount)
+    {
+        return Err(GenericError::new(format!(
+            "REST APIs disagree on transaction {}. v0.1 reports transfer of {} from {} to {}, v0.2 reports {} of {} from {} to {}",
+            tx_hash,
+            details.amount,
+            details.sender,
+            details.recipient,
+            op.tx_type,
+            op.amount,
+            op.from,
+            op.to
+        )));
+    }
+    Ok(())
+}
+
+fn parse_tx_response(
+    response: &str,
+    network: Network,
+    decimals: &TokenDecimals,
+) -> Result<VerifiedTx, GenericError> {
+    let v: TxRespObj = serde_json::from_str(response).map_err(GenericError::new)?;
+    v.into_verified(network, decimals)
+}
+
+#[derive(serde::Deserialize)]
+struct HistoryEntry {
+    tx: serde_json::Value,
+    #[serde(default)]
+    success: Option<bool>,
+    #[serde(default, alias = "failReason")]
+    fail_reason: Option<String>,
+    created_at: String,
+}
+
+/// Returns number of all entries in the page, along with successful transfers
+/// parsed from them. Transfers are sorted newest first.
+fn parse_history_response(
+    response: &str,
+    network: Network,
+    decimals: &TokenDecimals,
+) -> Result<(usize, Vec<VerifiedTx>), GenericError> {
+    let entries: Vec<HistoryEntry> = serde_json::from_str(response).map_err(GenericError::new)?;
+    let count = entries.len();
+    let mut transfers = entries
+        .into_iter()
+        .filter(|entry| entry.tx["type"] == "Transfer")
+        .filter(|entry| {
+            if entry.success != Some(true) {
+                log::debug!(
+                    "Skipping unsuccessful transfer. tx={}, success={:?}, fail_reason={:?}",
+                    entry.tx,
+                    entry.success,
+                    entry.fail_reason
+                );
+            }
+            entry.success == Some(true)
+        })
+        .filter_map(|entry| {
+            let mut tx = entry.tx;
+            tx["created_at"] = entry.created_at.into();
+            let tx = match serde_json::from_value::<TxRespObj>(tx) {
+                Ok(tx) => tx,
+                Err(e) => return Some(Err(GenericError::new(e))),
+            };
+            // E.g. ETH paid as fee or deposited.
+            let token = tx.token.as_deref().unwrap_or_default();
+            if !token.is_empty() && !decimals.contains_key(token) {
+                log::debug!(
+                    "Skipping transfer of unknown token. token={}, from={}, to={}",
+                    token,
+                    tx.from,
+                    tx.to
+                );
+                return None;
+            }
+            Some(tx.into_verified(network, decimals))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    transfers.sort_by(|a, b| b.details.date.cmp(&a.details.date));
+    Ok((count, transfers))
 }
 
-fn get_rpc_addr_from_env(network: Network) -> Option<String> {
-    match network {
-        Network::Mainnet => env::var("ZKSYNC_MAINNET_RPC_ADDRESS").ok(),
-        Network::Rinkeby => env::var("ZKSYNC_RINKEBY_RPC_ADDRESS").ok(),
+impl TxRespObj {
+    /// Token missing in API response means network's default token.
+    fn into_verified(
+        self,
+        network: Network,
+        decimals: &TokenDecimals,
+    ) -> Result<VerifiedTx, GenericError> {
+        let token = self
+            .token
+            .unwrap_or_else(|| get_network_token(network, None));
+        let amount = utils::big_uint_to_big_dec(
+            BigUint::from_str(&self.amount).map_err(GenericError::new)?,
+            find_decimals(decimals, &token)?,
+        );
+        let details = PaymentDetails {
+            recipient: self.to,
+            sender: self.from,
+            amount,
+            date: Some(parse_api_date(&self.created_at)?),
+        };
+        Ok(VerifiedTx {
+            details,
+            token,
+            network,
+        })
+    }
+}
+
+/// Api returns dates in UTC, but not every endpoint marks them as such.
+fn parse_api_date(date: &str) -> Result<chrono::DateTime<chrono::Utc>, GenericError> {
+    chrono::DateTime::from_str(date)
+        .or_else(|_| chrono::DateTime::from_str(&format!("{}Z", date)))
+        .map_err(GenericError::new)
+}
+
+/// Number of decimal places of token amounts, keyed by token symbol.
+pub type TokenDecimals = HashMap<String, u32>;
+
+lazy_static! {
+    static ref TOKEN_DECIMALS: Mutex<HashMap<String, TokenDecimals>> = Mutex::new(HashMap::new());
+}
+
+/// Token list is taken from provider once per network, it changes rarely.
+/// Lists returned by mocks aren't cached.
+async fn token_decimals(network: Network) -> Result<TokenDecimals, GenericError> {
+    if let Some(decimals) = TOKEN_DECIMALS.lock().unwrap().get(&network.to_string()) {
+        return Ok(decimals.clone());
+    }
+    let mocked = rpc::mocked(network).is_some();
+    let tokens = get_rpc(network)?
+        .tokens()
+        .await
+        .map_err(GenericError::new)?;
+    let decimals = tokens
+        .into_iter()
+        .map(|(_, token)| (token.symbol, token.decimals as u32))
+        .collect::<TokenDecimals>();
+    if !mocked {
+        TOKEN_DECIMALS
+            .lock()
+            .unwrap()
+            .insert(network.to_string(), decimals.clone());
+    }
+    Ok(decimals)
+}
+
+pub async fn get_token_decimals(token: &str, network: Network) -> Result<u32, GenericError> {
+    find_decimals(&token_decimals(network).await?, token)
+}
+
+fn find_decimals(decimals: &TokenDecimals, token: &str) -> Result<u32, GenericError> {
+    decimals
+        .get(token)
+        .copied()
+        .ok_or_else(|| GenericError::new(format!("Unable to find decimals for token: {}", token)))
+}
+
+lazy_static! {
+    static ref PROVIDERS: Mutex<HashMap<String, RpcProvider>> = Mutex::new(HashMap::new());
+}
+
+/// Providers are cached per network, so frequent calls (e.g. balance polling)
+/// don't have to create new one each time.
+fn get_provider(network: Network) -> Result<RpcProvider, GenericError> {
+    let mut providers = PROVIDERS.lock().unwrap();
+    if let Some(provider) = providers.get(&network.to_string()) {
+        return Ok(provider.clone());
+    }
+
+    let provider = get_deployment(network)?.provider();
+    providers.insert(network.to_string(), provider.clone());
+    Ok(provider)
+}
+
+/// Provider for calls, which don't need a `Wallet`. Mock installed
+/// in tests takes precedence.
+fn get_rpc(network: Network) -> Result<Arc<dyn ZksyncRpc>, GenericError> {
+    if let Some(rpc) = rpc::mocked(network) {
+        return Ok(rpc);
+    }
+    Ok(Arc::new(get_provider(network)?))
+}
+
+/// zkSync deployment used for given network.
+struct Deployment {
+    rpc_addr: Option<String>,
+    /// Determines chain id of signed transactions.
+    zk_network: ZkNetwork,
+}
+
+impl Deployment {
+    fn provider(&self) -> RpcProvider {
+        match &self.rpc_addr {
+            Some(rpc_addr) => RpcProvider::from_addr_and_network(rpc_addr.clone(), self.zk_network),
+            None => RpcProvider::new(self.zk_network),
+        }
+    }
+}
+
+fn get_deployment(network: Network) -> Result<Deployment, GenericError> {
+    deployment_from_vars(network, |name| env::var(name).ok())
+}
+
+/// Public deployment is used, unless `ZKSYNC_{NETWORK}_CUSTOM_NETWORK` is set
+/// to name of zkSync network (e.g. `localhost`) of a private deployment.
+/// Custom deployment requires `ZKSYNC_{NETWORK}_RPC_ADDRESS` as well.
+fn deployment_from_vars<F>(network: Network, var: F) -> Result<Deployment, GenericError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let prefix = format!("ZKSYNC_{}", network.to_string().to_uppercase());
+    let rpc_var = format!("{}_RPC_ADDRESS", prefix);
+    let custom_var = format!("{}_CUSTOM_NETWORK", prefix);
+    let rpc_addr = var(&rpc_var);
+    let custom_network = match var(&custom_var) {
+        Some(custom_network) => custom_network,
+        None => {
+            return Ok(Deployment {
+                rpc_addr,
+                zk_network: get_zk_network(network)?,
+            })
+        }
+    };
+    let zk_network = ZkNetwork::from_str(&custom_network).map_err(|_| {
+        GenericError::new(format!(
+            "Unsupported zksync network {}={}",
+            custom_var, custom_network
+        ))
+    })?;
+    match rpc_addr {
+        Some(rpc_addr) => Ok(Deployment {
+            rpc_addr: Some(rpc_addr),
+            zk_network,
+        }),
+        None => Err(GenericError::new(format!(
+            "{} is required when {} is set",
+            rpc_var, custom_var
+        ))),
     }
 }
 
@@ -264,45 +1135,70 @@ async fn get_wallet(
     network: Network,
 ) -> Result<Wallet<YagnaEthSigner, RpcProvider>, GenericError> {
     log::debug!("get_wallet {:?}", address);
-    let addr = Address::from_str(&address[2..]).map_err(GenericError::new)?;
-    let provider = get_provider(network);
+    let addr = parse_address(address)?;
+    let provider = get_provider(network)?;
     let signer = YagnaEthSigner::new(addr);
-    let credentials = WalletCredentials::from_eth_signer(addr, signer, get_zk_network(network))
-        .await
-        .map_err(GenericError::new)?;
+    let zk_network = get_deployment(network)?.zk_network;
+    let credentials = wallet_credentials(addr, signer, zk_network).await?;
     let wallet = Wallet::new(provider, credentials)
         .await
         .map_err(GenericError::new)?;
     Ok(wallet)
 }
 
-fn get_zk_network(network: Network) -> ZkNetwork {
-    ZkNetwork::from_str(&network.to_string()).unwrap() // _or(ZkNetwork::Rinkeby)
+/// Deriving credentials requires signing a message with the yagna identity,
+/// which fails when the identity is locked. Such failure is reported
+/// separately from network errors, so the user knows what to do.
+async fn wallet_credentials<S: EthereumSigner + Clone>(
+    addr: Address,
+    signer: S,
+    zk_network: ZkNetwork,
+) -> Result<WalletCredentials<S>, GenericError> {
+    WalletCredentials::from_eth_signer(addr, signer, zk_network)
+        .await
+        .map_err(|e| match e {
+            ClientError::SigningError(e) => ZksyncError::IdentityLocked {
+                address: format!("{:#x}", addr),
+                reason: e.to_string(),
+            },
+            e => ZksyncError::from(e),
+        })
+        .map_err(GenericError::from)
+}
+
+fn get_zk_network(network: Network) -> Result<ZkNetwork, GenericError> {
+    ZkNetwork::from_str(&network.to_string())
+        .map_err(|_| GenericError::new(format!("Unsupported network for zksync: {}", network)))
 }
 
 async fn unlock_wallet<S: EthereumSigner + Clone, P: Provider + Clone>(
     wallet: &Wallet<S, P>,
     network: Network,
+    fee_tokens: &[String],
 ) -> Result<(), GenericError> {
-    log::debug!("unlock_wallet");
+    log::debug!("unlock_wallet. fee_tokens={:?}", fee_tokens);
     if !wallet
         .is_signing_key_set()
         .await
         .map_err(GenericError::new)?
     {
         log::info!("Unlocking wallet... address = {}", wallet.signer.address);
-        let token = get_network_token(network, None);
+        let fee_token = if fee_tokens.is_empty() {
+            unlock_fee_token(wallet, &get_network_token(network, None)).await?
+        } else {
+            preferred_fee_token(wallet, fee_tokens).await?
+        };
 
         let unlock = wallet
             .start_change_pubkey()
-            .fee_token(token.as_str())
+            .fee_token(fee_token.as_str())
             .map_err(|e| GenericError::new(format!("Failed to create change_pubkey request: {}", e)))?
             .send()
             .await
             .map_err(|e| GenericError::new(format!("Failed to send change_pubkey request: '{}'. HINT: Did you run `yagna payment fund` and follow the instructions?", e)))?;
         log::info!("Unlock send. tx_hash= {}", unlock.hash().to_string());
 
-        let tx_info = unlock.wait_for_commit().await.map_err(GenericError::new)?;
+        let tx_info = unlock.wait_for_commit().await.map_err(ZksyncError::from)?;
         log::debug!("tx_info = {:?}", tx_info);
         match tx_info.success {
             Some(true) => log::info!("Wallet successfully unlocked. address = {}", wallet.signer.address),
@@ -313,20 +1209,132 @@ async fn unlock_wallet<S: EthereumSigner + Clone, P: Provider + Clone>(
     Ok(())
 }
 
+const ETH_TOKEN: &str = "ETH";
+
+/// Picks the token to pay change-pubkey fee with. Network token is preferred,
+/// ETH is used only when the token balance can't cover the fee.
+async fn unlock_fee_token<S: EthereumSigner + Clone, P: Provider + Clone>(
+    wallet: &Wallet<S, P>,
+    token: &str,
+) -> Result<String, GenericError> {
+    let token_funds = unlock_funds(wallet, token).await?;
+    if token_funds.covers_fee() {
+        return Ok(token.to_string());
+    }
+    let eth_funds = unlock_funds(wallet, ETH_TOKEN).await?;
+    choose_fee_token(token, &token_funds, &eth_funds)
+}
+
+async fn unlock_funds<S: EthereumSigner + Clone, P: Provider + Clone>(
+    wallet: &Wallet<S, P>,
+    token: &str,
+) -> Result<UnlockFunds, GenericError> {
+    let balance = wallet
+        .get_balance(BlockStatus::Committed, token)
+        .await
+        .map_err(GenericError::new)?;
+    let fee_type = TxFeeTypes::ChangePubKey(ChangePubKeyFeeTypeArg::ContractsV4Version(
+        ChangePubKeyType::ECDSA,
+    ));
+    let fee = wallet
+        .provider
+        .get_tx_fee(fee_type, wallet.address(), token)
+        .await
+        .map_err(GenericError::new)?
+        .total_fee;
+    Ok(UnlockFunds { balance, fee })
+}
+
+struct UnlockFunds {
+    balance: BigUint,
+    fee: BigUint,
+}
+
+impl UnlockFunds {
+    fn covers_fee(&self) -> bool {
+        self.balance >= self.fee
+    }
+}
+
+fn choose_fee_token(
+    token: &str,
+    token_funds: &UnlockFunds,
+    eth_funds: &UnlockFunds,
+) -> Result<String, GenericError> {
+    if token_funds.covers_fee() {
+        return Ok(token.to_string());
+    }
+    if eth_funds.covers_fee() {
+        log::info!(
+            "Insufficient {} balance to pay unlock fee, paying with {}",
+            token,
+            ETH_TOKEN
+        );
+        return Ok(ETH_TOKEN.to_string());
+    }
+    Err(GenericError::new(format!(
+        "Insufficient funds to pay unlock fee. {} balance={} fee={}, {} balance={} fee={}. HINT: Did you run `yagna payment fund` and follow the instructions?",
+        token, token_funds.balance, token_funds.fee, ETH_TOKEN, eth_funds.balance, eth_funds.fee
+    )))
+}
+
+/// Explicitly requested fee tokens are tried in order, without falling back to ETH.
+/// Balances are checked lazily, so tokens after the first sufficient one aren't queried.
+async fn preferred_fee_token<S: EthereumSigner + Clone, P: Provider + Clone>(
+    wallet: &Wallet<S, P>,
+    tokens: &[String],
+) -> Result<String, GenericError> {
+    let mut attempted = vec![];
+    for token in tokens {
+        let funds = unlock_funds(wallet, token).await?;
+        if funds.covers_fee() {
+            return Ok(token.clone());
+        }
+        attempted.push((token.as_str(), funds));
+    }
+    check_fee_tokens(&attempted)
+}
+
+/// Picks the first token, which balance covers the unlock fee. Error lists
+/// every attempted token with its shortfall.
+fn check_fee_tokens(candidates: &[(&str, UnlockFunds)]) -> Result<String, GenericError> {
+    if let Some((token, _)) = candidates.iter().find(|(_, funds)| funds.covers_fee()) {
+        return Ok(token.to_string());
+    }
+    let shortfalls = candidates
+        .iter()
+        .map(|(token, funds)| {
+            format!(
+                "{} balance={} required={} missing={}",
+                token,
+                funds.balance,
+                funds.fee,
+                &funds.fee - &funds.balance
+            )
+        })
+        .collect::<Vec<_>>();
+    Err(GenericError::new(format!(
+        "Insufficient balance to pay unlock fee in any of preferred tokens. {}",
+        shortfalls.join(", ")
+    )))
+}
+
 pub async fn withdraw<S: EthereumSigner + Clone, P: Provider + Clone>(
     wallet: Wallet<S, P>,
     network: Network,
     amount: Option<BigDecimal>,
+    percent: Option<u8>,
     recipient: Option<String>,
 ) -> Result<SyncTransactionHandle<P>, GenericError> {
     let token = get_network_token(network, None);
+    let decimals = get_token_decimals(&token, network).await?;
     let balance = wallet
         .get_balance(BlockStatus::Committed, token.as_str())
         .await
         .map_err(GenericError::new)?;
     info!(
         "Wallet funded with {} {} available for withdrawal",
-        utils::big_uint_to_big_dec(balance.clone()),
+        utils::big_uint_to_big_dec(balance.clone(), decimals),
         token
     );
 
@@ -340,23 +1348,23 @@ pub async fn withdraw<S: EthereumSigner + Clone, P: Provider + Clone>(
         .total_fee;
     info!(
         "Withdrawal transaction fee {:.5} {}",
-        utils::big_uint_to_big_dec(withdraw_fee.clone()),
+        utils::big_uint_to_big_dec(withdraw_fee.clone(), decimals),
         token
     );
 
     let amount = match amount {
-        Some(amount) => utils::big_dec_to_big_uint(amount)?,
-        None => balance.clone(),
+        Some(amount) => Some(utils::big_dec_to_big_uint(amount, decimals)?),
+        None => None,
     };
-    let withdraw_amount = std::cmp::min(balance - withdraw_fee, amount);
+    let withdraw_amount = compute_withdraw_amount(balance, withdraw_fee, amount, percent)?;
     info!(
         "Withdrawal of {:.5} {} started",
-        utils::big_uint_to_big_dec(withdraw_amount.clone()),
+        utils::big_uint_to_big_dec(withdraw_amount.clone(), decimals),
         token
     );
 
     let recipient_address = match recipient {
-        Some(addr) => Address::from_str(&addr[2..]).map_err(GenericError::new)?,
+        Some(addr) => parse_address(&addr)?,
         None => address,
     };
 
@@ -376,3 +1384,794 @@ pub async fn withdraw<S: EthereumSigner + Clone, P: Provider + Clone>(
 
     Ok(withdraw_handle)
 }
+
+/// Amount to withdraw after reserving the fee. Explicit `amount` takes precedence
+/// over `percent`, when neither is set whole available balance is withdrawn.
+/// Fails instead of returning zero, so no fee is paid for an empty withdrawal.
+fn compute_withdraw_amount(
+    balance: BigUint,
+    fee: BigUint,
+    amount: Option<BigUint>,
+    percent: Option<u8>,
+) -> Result<BigUint, GenericError> {
+    let available = match balance.checked_sub(&fee) {
+        Some(available) => available,
+        None => {
+            return Err(GenericError::new(format!(
+                "Insufficient funds to cover fee. balance={}, fee={}",
+                balance, fee
+            )))
+        }
+    };
+    let withdraw_amount = match (amount, percent) {
+        (Some(amount), _) => std::cmp::min(available, amount),
+        (None, Some(percent)) if percent == 0 || percent > 100 => {
+            return Err(GenericError::new(format!(
+                "Invalid withdrawal percentage {}, should be between 1 and 100",
+                percent
+            )))
+        }
+        (None, Some(percent)) => available * BigUint::from(percent) / BigUint::from(100u32),
+        (None, None) => available,
+    };
+    if withdraw_amount.is_zero() {
+        return Err(GenericError::new(format!(
+            "Nothing to withdraw. balance={}, fee={}",
+            balance, fee
+        )));
+    }
+    Ok(withdraw_amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zksync::mock::{self, MockRpc};
+    use zksync::zksync_types::tx::TxEthSignature;
+    use zksync_eth_signer::{error::SignerError, RawTransaction};
+
+    #[test]
+    fn test_get_zk_network() {
+        assert_eq!(
+            get_zk_network(Network::Mainnet).unwrap(),
+            ZkNetwork::Mainnet
+        );
+        assert_eq!(
+            get_zk_network(Network::Rinkeby).unwrap(),
+            ZkNetwork::Rinkeby
+        );
+    }
+
+    fn custom_vars(name: &str) -> Option<String> {
+        match name {
+            "ZKSYNC_RINKEBY_CUSTOM_NETWORK" => Some("localhost".to_string()),
+            "ZKSYNC_RINKEBY_RPC_ADDRESS" => Some("http://127.0.0.1:3030".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_custom_deployment_provider() {
+        let deployment = deployment_from_vars(Network::Rinkeby, custom_vars).unwrap();
+        assert_eq!(deployment.zk_network, ZkNetwork::Localhost);
+        assert_eq!(deployment.provider().network, ZkNetwork::Localhost);
+
+        // Other networks are not affected.
+        let deployment = deployment_from_vars(Network::Mainnet, custom_vars).unwrap();
+        assert_eq!(deployment.zk_network, ZkNetwork::Mainnet);
+        assert_eq!(deployment.rpc_addr, None);
+    }
+
+    #[test]
+    fn test_custom_deployment_validation() {
+        let err = deployment_from_vars(Network::Rinkeby, |name| match name {
+            "ZKSYNC_RINKEBY_CUSTOM_NETWORK" => Some("localhost".to_string()),
+            _ => None,
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("ZKSYNC_RINKEBY_RPC_ADDRESS"));
+
+        let err = deployment_from_vars(Network::Rinkeby, |name| match name {
+            "ZKSYNC_RINKEBY_CUSTOM_NETWORK" => Some("nonexistent".to_string()),
+            name => custom_vars(name),
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("nonexistent"));
+    }
+
+    #[test]
+    fn test_next_nonce_without_pending() {
+        let nonce = AccountNonce {
+            committed: 5,
+            pending: None,
+        };
+        assert_eq!(nonce.next(), 5);
+    }
+
+    #[test]
+    fn test_next_nonce_skips_pending() {
+        let nonce = AccountNonce {
+            committed: 5,
+            pending: Some(7),
+        };
+        assert_eq!(nonce.next(), 8);
+
+        // Pending transactions were already committed.
+        let nonce = AccountNonce {
+            committed: 9,
+            pending: Some(7),
+        };
+        assert_eq!(nonce.next(), 9);
+    }
+
+    #[actix_rt::test]
+    async fn test_prepare_transfer_zero_amount() {
+        let details = PaymentDetails {
+            recipient: "0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5".to_string(),
+            sender: "0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b".to_string(),
+            amount: BigDecimal::zero(),
+            date: None,
+        };
+        let err = match prepare_transfer(&details, 0, Network::Rinkeby, PackPolicy::Up).await {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("Transfer of 0 shouldn't be prepared"),
+        };
+        assert_eq!(err, "Invalid transfer amount 0, should be greater than 0");
+    }
+
+    #[actix_rt::test]
+    async fn test_watch_balance_yields_changes() {
+        use futures3::StreamExt;
+
+        let polls = std::sync::Arc::new(Mutex::new(vec![1, 1, 2, 2].into_iter()));
+        let counter = polls.clone();
+        let stream = watch_changes(Duration::from_millis(1), move || {
+            let next = counter.lock().unwrap().next();
+            async move {
+                next.map(BigDecimal::from)
+                    .ok_or(GenericError::new("no more balances"))
+            }
+        });
+
+        let balances = stream.take(2).collect::<Vec<_>>().await;
+        assert_eq!(balances, vec![BigDecimal::from(1), BigDecimal::from(2)]);
+        // Unchanged balance from the second poll was skipped.
+        assert_eq!(polls.lock().unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_health_reachable() {
+        let status = check_health(Network::Rinkeby, async {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            Ok(())
+        })
+        .await;
+        assert!(status.reachable);
+        assert!(status.latency_ms >= 10);
+        assert_eq!(status.network, "rinkeby");
+        assert_eq!(status.error, None);
+    }
+
+    #[actix_rt::test]
+    async fn test_health_unreachable() {
+        let status = check_health(Network::Mainnet, async {
+            Err(GenericError::new("connection refused"))
+        })
+        .await;
+        assert!(!status.reachable);
+        assert_eq!(status.network, "mainnet");
+        assert_eq!(status.error, Some("connection refused".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_health_unreachable_rpc() {
+        // Nothing listens on port 1, so connection is refused right away.
+        let deployment = deployment_from_vars(Network::Rinkeby, |name| match name {
+            "ZKSYNC_RINKEBY_RPC_ADDRESS" => Some("http://127.0.0.1:1/jsrpc".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        let start = Instant::now();
+        let status = provider_health(Network::Rinkeby, deployment.provider()).await;
+        assert!(!status.reachable);
+        assert!(status.error.is_some());
+        assert!(start.elapsed() < HEALTH_CHECK_TIMEOUT + Duration::from_secs(1));
+    }
+
+    fn compute(amount: Option<u32>, percent: Option<u8>) -> Result<BigUint, GenericError> {
+        compute_withdraw_amount(
+            BigUint::from(1010u32),
+            BigUint::from(10u32),
+            amount.map(BigUint::from),
+            percent,
+        )
+    }
+
+    #[test]
+    fn test_withdraw_amount_full_balance() {
+        assert_eq!(compute(None, None).unwrap(), BigUint::from(1000u32));
+    }
+
+    #[test]
+    fn test_withdraw_amount_explicit() {
+        assert_eq!(compute(Some(300), None).unwrap(), BigUint::from(300u32));
+        assert_eq!(compute(Some(5000), None).unwrap(), BigUint::from(1000u32));
+    }
+
+    #[test]
+    fn test_withdraw_amount_zero_percent() {
+        assert!(compute(None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_withdraw_amount_zero() {
+        assert!(compute(Some(0), None).is_err());
+        let err = compute_withdraw_amount(BigUint::from(10u32), BigUint::from(10u32), None, None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Nothing to withdraw. balance=10, fee=10");
+    }
+
+    #[test]
+    fn test_withdraw_amount_full_percent() {
+        assert_eq!(compute(None, Some(100)).unwrap(), BigUint::from(1000u32));
+    }
+
+    #[test]
+    fn test_withdraw_amount_percent_out_of_range() {
+        assert!(compute(None, Some(150)).is_err());
+    }
+
+    #[test]
+    fn test_withdraw_amount_fee_exceeds_balance() {
+        let err = compute_withdraw_amount(BigUint::from(5u32), BigUint::from(10u32), None, None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Insufficient funds to cover fee. balance=5, fee=10"
+        );
+    }
+
+    fn funds(balance: u32, fee: u32) -> UnlockFunds {
+        UnlockFunds {
+            balance: BigUint::from(balance),
+            fee: BigUint::from(fee),
+        }
+    }
+
+    #[test]
+    fn test_unlock_fee_in_token() {
+        let token = choose_fee_token("tGLM", &funds(100, 10), &funds(100, 1)).unwrap();
+        assert_eq!(token, "tGLM");
+    }
+
+    #[test]
+    fn test_unlock_fee_in_eth() {
+        let token = choose_fee_token("tGLM", &funds(0, 10), &funds(100, 1)).unwrap();
+        assert_eq!(token, ETH_TOKEN);
+    }
+
+    #[test]
+    fn test_unlock_fee_insufficient() {
+        let err = choose_fee_token("tGLM", &funds(5, 10), &funds(0, 1)).unwrap_err();
+        assert!(err.to_string().contains("Insufficient funds"));
+    }
+
+    #[test]
+    fn test_unlock_fee_in_chosen_token() {
+        // Wallet holds no network token, only the explicitly chosen one.
+        assert!(choose_fee_token("tGLM", &funds(0, 10), &funds(0, 1)).is_err());
+        let token = check_fee_tokens(&[(ETH_TOKEN, funds(100, 1))]).unwrap();
+        assert_eq!(token, ETH_TOKEN);
+
+        let err = check_fee_tokens(&[("tGLM", funds(0, 10))])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("tGLM"), "{}", err);
+        assert!(err.contains("required=10"), "{}", err);
+    }
+
+    #[test]
+    fn test_unlock_fee_token_preference() {
+        // First preferred token lacks funds, second one covers the fee.
+        let token = check_fee_tokens(&[("tGLM", funds(5, 10)), (ETH_TOKEN, funds(3, 1))]).unwrap();
+        assert_eq!(token, ETH_TOKEN);
+        let token = check_fee_tokens(&[("tGLM", funds(10, 10)), (ETH_TOKEN, funds(3, 1))]).unwrap();
+        assert_eq!(token, "tGLM");
+
+        let err = check_fee_tokens(&[("tGLM", funds(5, 10)), (ETH_TOKEN, funds(0, 1))])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("tGLM balance=5 required=10 missing=5"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("ETH balance=0 required=1 missing=1"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_transfer_probe() {
+        assert_eq!(TransferProbe::new(true, 5, 4), TransferProbe::Executed);
+        assert_eq!(TransferProbe::new(false, 5, 4), TransferProbe::Dropped);
+        assert_eq!(TransferProbe::new(false, 4, 4), TransferProbe::Unknown);
+    }
+
+    const SENDER: &str = "0x2f7d2ca2bd1d5c4e2b6d0d4d1c6e3f0a3c4ef44b";
+
+    fn account_info(committed: &str, verified: &str) -> AccountInfo {
+        let address = parse_address(SENDER).unwrap();
+        let json = mock::account_info(address, "tGLM", committed, verified, 0);
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_account_balance_mocked() {
+        let address = parse_address(SENDER).unwrap();
+        let _mock = MockRpc::default()
+            .with_account_info(
+                address,
+                mock::account_info(address, "tGLM", "2500000000000000000", "0", 3),
+            )
+            .install();
+
+        let balance = account_balance(SENDER, Network::Rinkeby, BlockStatus::Committed)
+            .await
+            .unwrap();
+        assert_eq!(balance, BigDecimal::from_str("2.5").unwrap());
+        let balance = account_balance(SENDER, Network::Rinkeby, BlockStatus::Verified)
+            .await
+            .unwrap();
+        assert_eq!(balance, BigDecimal::zero());
+
+        let nonce = get_committed_nonce(SENDER, Network::Rinkeby).await.unwrap();
+        assert_eq!(nonce, 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_tx_fee_mocked() {
+        let _mock = MockRpc::default()
+            .with_tx_fee("tGLM", mock::tx_fee("150000000000000000"))
+            .install();
+
+        let fee = get_tx_fee(SENDER, Network::Rinkeby).await.unwrap();
+        assert_eq!(fee, BigDecimal::from_str("0.15").unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_aggregate_balance() {
+        let address = parse_address(SENDER).unwrap();
+        let _rinkeby = MockRpc::default()
+            .with_account_info(
+                address,
+                mock::account_info(address, "tGLM", "2000000000000000000", "0", 0),
+            )
+            .install_for(Network::Rinkeby);
+        let mainnet = MockRpc::default()
+            .with_account_info(
+                address,
+                mock::account_info(address, "GLM", "500000000000000000", "0", 0),
+            )
+            .install_for(Network::Mainnet);
+
+        let aggregated = aggregate_balance(SENDER).await;
+        assert!(aggregated.errors.is_empty(), "{:?}", aggregated.errors);
+        assert_eq!(aggregated.balances["rinkeby"], BigDecimal::from(2));
+        assert_eq!(
+            aggregated.balances["mainnet"],
+            BigDecimal::from_str("0.5").unwrap()
+        );
+        assert_eq!(aggregated.total, BigDecimal::from_str("2.5").unwrap());
+
+        // Mainnet RPC doesn't know the account.
+        drop(mainnet);
+        let _mainnet = MockRpc::default().install_for(Network::Mainnet);
+        let aggregated = aggregate_balance(SENDER).await;
+        assert_eq!(aggregated.balances.len(), 1);
+        assert!(aggregated.errors.contains_key("mainnet"));
+        assert_eq!(aggregated.total, BigDecimal::from(2));
+    }
+
+    #[actix_rt::test]
+    async fn test_check_tx_mocked() {
+        let tx_hash = "a".repeat(64);
+        let sync_tx_hash = TxHash::from_str(&format!("sync-tx:{}", tx_hash)).unwrap();
+        let _mock = MockRpc::default()
+            .with_tx_info(
+                sync_tx_hash,
+                mock::tx_info(Some(false), Some("Nonce mismatch")),
+            )
+            .install();
+
+        let result = check_tx(&tx_hash, Network::Rinkeby).await;
+        assert_eq!(result, Some(Err("Nonce mismatch".to_string())));
+    }
+
+    #[actix_rt::test]
+    async fn test_wait_for_tx_polls_until_result() {
+        let results = Mutex::new(vec![Some(Ok(())), None, None]);
+        let polls = Mutex::new(0);
+        let check = || {
+            *polls.lock().unwrap() += 1;
+            let result = results.lock().unwrap().pop().unwrap();
+            async move { result }
+        };
+
+        poll_tx(
+            "0x1",
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+            check,
+        )
+        .await
+        .unwrap();
+        assert_eq!(*polls.lock().unwrap(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_wait_for_tx_failure_and_timeout() {
+        let failed = poll_tx(
+            "0x1",
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+            || async { Some(Err("Nonce mismatch".to_string())) },
+        )
+        .await
+        .unwrap_err();
+        assert!(failed.to_string().contains("Nonce mismatch"), "{}", failed);
+
+        let pending = poll_tx(
+            "0x1",
+            Duration::from_millis(1),
+            Duration::from_millis(20),
+            || async { None },
+        )
+        .await
+        .unwrap_err();
+        assert!(pending.to_string().contains("not confirmed"), "{}", pending);
+    }
+
+    #[test]
+    fn test_committed_and_verified_balance() {
+        let acc_info = account_info("3000000000000000000", "1000000000000000000");
+        let committed = status_balance(&acc_info, "tGLM", 18, BlockStatus::Committed);
+        let verified = status_balance(&acc_info, "tGLM", 18, BlockStatus::Verified);
+        assert_eq!(committed, BigDecimal::from(3));
+        assert_eq!(verified, BigDecimal::from(1));
+
+        let other = status_balance(&acc_info, "GLM", 18, BlockStatus::Verified);
+        assert_eq!(other, BigDecimal::zero());
+    }
+
+    #[test]
+    fn test_min_transfer() {
+        let min = BigDecimal::from_str("0.01").unwrap();
+        let amount =
+            |s: &str| utils::big_dec_to_big_uint(BigDecimal::from_str(s).unwrap(), 18).unwrap();
+
+        let err = check_min_transfer(&amount("0.009999"), &min, 18).unwrap_err();
+        assert!(
+            matches!(err, ZksyncError::InvalidAmount { reason: AmountError::BelowMinimum(ref m), .. } if *m == min),
+            "{}",
+            err
+        );
+        assert!(!err.is_retryable());
+        assert!(check_min_transfer(&amount("0.01"), &min, 18).is_ok());
+        assert!(check_min_transfer(&amount("0.010001"), &min, 18).is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_retain_pending() {
+        let tx_hashes = vec!["confirmed".to_string(), "pending".to_string()];
+        let pending = retain_pending(tx_hashes, |tx_hash| async move {
+            match tx_hash.as_str() {
+                "confirmed" => Some(Ok(())),
+                _ => None,
+            }
+        })
+        .await;
+        assert_eq!(pending, vec!["pending".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert!(parse_address("0xd39a168f0480b8502c2531b2d6a8d7ef3b51b4b5").is_ok());
+        for address in &[
+            "d39a168f0480b8502c2531b2d6a8d7ef3b51b4b5",
+            "0x12",
+            "0xzz",
+            "",
+        ] {
+            let err = parse_address(address).unwrap_err();
+            assert!(matches!(err, ZksyncError::InvalidAddress(_)), "{}", address);
+            assert!(!err.is_retryable());
+        }
+    }
+
+    #[test]
+    fn test_check_balance() {
+        assert!(check_balance(&BigUint::from(10u32), &BigUint::from(10u32), 0).is_ok());
+        let err = check_balance(&BigUint::from(9u32), &BigUint::from(10u32), 0).unwrap_err();
+        match err {
+            ZksyncError::InsufficientBalance {
+                required,
+                available,
+            } => {
+                assert_eq!(required, BigDecimal::from(10));
+                assert_eq!(available, BigDecimal::from(9));
+            }
+            e => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_exit_waits_for_verify() {
+        let commit_polled = std::sync::Arc::new(Mutex::new(false));
+        let polled = commit_polled.clone();
+        let commit = async move {
+            *polled.lock().unwrap() = true;
+            "committed"
+        };
+        let verify = async { "verified" };
+
+        let result = wait_for_confirmation(ExitConfirmation::Verify, commit, verify).await;
+        assert_eq!(result, "verified");
+        assert!(!*commit_polled.lock().unwrap());
+
+        let result =
+            wait_for_confirmation(ExitConfirmation::Commit, async { "committed" }, async {
+                "verified"
+            })
+            .await;
+        assert_eq!(result, "committed");
+    }
+
+    fn glm_decimals() -> TokenDecimals {
+        maplit::hashmap! {
+            "GLM".to_string() => 18,
+            "tGLM".to_string() => 18,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_token_decimals_from_provider() {
+        let _mock = MockRpc::default()
+            .with_tokens(mock::tokens(&[("tGLM", 18), ("USDC", 6)]))
+            .install();
+
+        assert_eq!(
+            get_token_decimals("USDC", Network::Rinkeby).await.unwrap(),
+            6
+        );
+        assert_eq!(
+            get_token_decimals("tGLM", Network::Rinkeby).await.unwrap(),
+            18
+        );
+        let err = get_token_decimals("ETH", Network::Rinkeby)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unable to find decimals for token: ETH");
+    }
+
+    #[test]
+    fn test_parse_tx_response_token_and_network() {
+        let response = serde_json::json!({
+            "from": SENDER,
+            "to": "0x0000000000000000000000000000000000000001",
+            "token": "GLM",
+            "amount": "1500000000000000000",
+            "created_at": "2021-01-20T10:11:12.123456",
+        })
+        .to_string();
+        let verified = parse_tx_response(&response, Network::Mainnet, &glm_decimals()).unwrap();
+        assert_eq!(verified.token, "GLM");
+        assert_eq!(verified.network, Network::Mainnet);
+        assert_eq!(verified.details.sender, SENDER);
+        assert_eq!(
+            verified.details.amount,
+            BigDecimal::from_str("1.5").unwrap()
+        );
+
+        // Older API responses don't report token.
+        let response = serde_json::json!({
+            "from": SENDER,
+            "to": "0x0000000000000000000000000000000000000001",
+            "amount": "1000000000000000000",
+            "created_at": "2021-01-20T10:11:12.123456",
+        })
+        .to_string();
+        let verified = parse_tx_response(&response, Network::Rinkeby, &glm_decimals()).unwrap();
+        assert_eq!(verified.token, get_network_token(Network::Rinkeby, None));
+        assert_eq!(verified.network, Network::Rinkeby);
+    }
+
+    #[test]
+    fn test_parse_history_response() {
+        let transfer = |to: &str, amount: &str, created_at: &str| {
+            serde_json::json!({
+                "tx": {
+                    "type": "Transfer",
+                    "from": SENDER,
+                    "to": to,
+                    "token": "tGLM",
+                    "amount": amount,
+                    "fee": "0",
+                    "nonce": 1,
+                },
+                "success": true,
+                "created_at": created_at,
+            })
+        };
+        let response = serde_json::json!([
+            transfer(
+                "0x0000000000000000000000000000000000000001",
+                "1000000000000000000",
+                "2021-01-20T10:00:00.000000Z"
+            ),
+            {
+                "tx": { "type": "ChangePubKey", "account": SENDER },
+                "success": true,
+                "created_at": "2021-01-20T11:00:00.000000Z",
+            },
+            transfer(
+                "0x0000000000000000000000000000000000000002",
+                "2500000000000000000",
+                "2021-01-20T12:00:00.000000Z"
+            ),
+            {
+                "tx": {
+                    "type": "Transfer",
+                    "from": SENDER,
+                    "to": "0x0000000000000000000000000000000000000003",
+                    "token": "tGLM",
+                    "amount": "1000000000000000000",
+                    "fee": "0",
+                    "nonce": 2,
+                },
+                "success": false,
+                "fail_reason": "Not enough balance",
+                "created_at": "2021-01-20T13:00:00.000000Z",
+            },
+            {
+                "tx": {
+                    "type": "Transfer",
+                    "from": SENDER,
+                    "to": "0x0000000000000000000000000000000000000004",
+                    "token": "ETH",
+                    "amount": "1000000000000000",
+                    "fee": "0",
+                    "nonce": 3,
+                },
+                "success": true,
+                "created_at": "2021-01-20T14:00:00.000000Z",
+            },
+        ])
+        .to_string();
+
+        let (entries, transfers) =
+            parse_history_response(&response, Network::Rinkeby, &glm_decimals()).unwrap();
+        // Failed transfer and transfer of token with unknown decimals count
+        // as entries, but aren't reported.
+        assert_eq!(entries, 5);
+        assert_eq!(transfers.len(), 2);
+        // Newest first.
+        assert_eq!(
+            transfers[0].details.recipient,
+            "0x0000000000000000000000000000000000000002"
+        );
+        assert_eq!(
+            transfers[0].details.amount,
+            BigDecimal::from_str("2.5").unwrap()
+        );
+        assert_eq!(
+            transfers[1].details.recipient,
+            "0x0000000000000000000000000000000000000001"
+        );
+        assert!(transfers[0].details.date > transfers[1].details.date);
+        assert!(transfers.iter().all(|tx| tx.token == "tGLM"));
+
+        let error = "{\"error\": \"oops\"}";
+        assert!(parse_history_response(error, Network::Rinkeby, &glm_decimals()).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_cross_check_tx() {
+        let tx_hash = "8f6d5f8dd0d4d5a9c5b2bf7aa32f0cb4fd1f0b9dd4a4e2a5c0b52c1bb0a1d9f3";
+        let sync_tx_hash = TxHash::from_str(&format!("sync-tx:{}", tx_hash)).unwrap();
+
+        let _guard = MockRpc::default()
+            .with_tx_info(sync_tx_hash, mock::tx_info(Some(true), None))
+            .install();
+        check_tx_status(tx_hash, Network::Rinkeby).await.unwrap();
+
+        // REST API reported transfer, which RPC knows to have failed.
+        let _guard = MockRpc::default()
+            .with_tx_info(
+                sync_tx_hash,
+                mock::tx_info(Some(false), Some("Not enough balance")),
+            )
+            .install();
+        let err = check_tx_status(tx_hash, Network::Rinkeby)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("disagree"), "{}", err);
+        assert!(err.contains("Not enough balance"), "{}", err);
+
+        let _guard = MockRpc::default()
+            .with_tx_info(sync_tx_hash, mock::tx_info(None, None))
+            .install();
+        assert!(check_tx_status(tx_hash, Network::Rinkeby).await.is_err());
+    }
+
+    #[test]
+    fn test_match_tx_data() {
+        let tx_hash = "8f6d5f8dd0d4d5a9c5b2bf7aa32f0cb4fd1f0b9dd4a4e2a5c0b52c1bb0a1d9f3";
+        let verified = VerifiedTx {
+            details: PaymentDetails {
+                recipient: "0x0000000000000000000000000000000000000001".to_string(),
+                sender: SENDER.to_string(),
+                amount: BigDecimal::from_str("1.5").unwrap(),
+                date: None,
+            },
+            token: "tGLM".to_string(),
+            network: Network::Rinkeby,
+        };
+        let response = |amount: &str| {
+            format!(
+                r#"{{"status": "success", "result": {{"tx": {{"op": {{"type": "Transfer", "from": "{}", "to": "0x0000000000000000000000000000000000000001", "token": 1, "amount": "{}"}}}}}}}}"#,
+                SENDER.to_uppercase().replace("0X", "0x"),
+                amount
+            )
+        };
+
+        match_tx_data(tx_hash, &verified, 18, &response("1500000000000000000")).unwrap();
+
+        // REST v0.1 reported different amount, than was actually transferred.
+        let err = match_tx_data(tx_hash, &verified, 18, &response("1000000000000000000"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("disagree"), "{}", err);
+
+        let not_found = r#"{"status": "success", "result": null}"#;
+        assert!(match_tx_data(tx_hash, &verified, 18, not_found).is_err());
+    }
+
+    /// Fails like yagna identity service does for locked identity.
+    #[derive(Clone)]
+    struct FailingSigner(Address);
+
+    #[async_trait::async_trait]
+    impl EthereumSigner for FailingSigner {
+        async fn get_address(&self) -> Result<Address, SignerError> {
+            Ok(self.0)
+        }
+
+        async fn sign_message(&self, _message: &[u8]) -> Result<TxEthSignature, SignerError> {
+            Err(SignerError::SigningFailed("sign error".to_string()))
+        }
+
+        async fn sign_transaction(&self, _raw_tx: RawTransaction) -> Result<Vec<u8>, SignerError> {
+            Err(SignerError::SigningFailed("sign error".to_string()))
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_credentials_locked_identity() {
+        let address = parse_address(SENDER).unwrap();
+        let result = wallet_credentials(address, FailingSigner(address), ZkNetwork::Rinkeby).await;
+        let err = match result {
+            Ok(_) => panic!("Credentials created without signature"),
+            Err(e) => e.to_string(),
+        };
+        assert!(err.contains("yagna id unlock"), "{}", err);
+        assert!(err.contains(SENDER), "{}", err);
+        assert!(err.contains("sign error"), "{}", err);
+    }
+}
@@ -21,6 +21,8 @@ pub(crate) struct Account {
     pub network: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fee_tokens: Vec<String>,
     pub send: bool,
     pub receive: bool,
 }
@@ -31,12 +33,10 @@ pub(crate) async fn init_account(account: Account) -> anyhow::Result<()> {
     mode.set(AccountMode::SEND, account.send);
     mode.set(AccountMode::RECV, account.receive);
     bus::service(driver_bus_id(account.driver))
-        .call(Init::new(
-            account.address,
-            account.network,
-            account.token,
-            mode,
-        ))
+        .call(
+            Init::new(account.address, account.network, account.token, mode)
+                .with_fee_tokens(account.fee_tokens),
+        )
         .await??;
     log::debug!("Account initialized.");
     Ok(())
@@ -84,6 +84,7 @@ pub async fn save_default_account(data_dir: &Path, drivers: Vec<String>) -> anyh
             address: default_node_id.to_string(),
             network: None, // Use default
             token: None,   // Use default
+            fee_tokens: vec![],
             send: false,
             receive: true,
         })
@@ -5,7 +5,7 @@ use std::str::FromStr;
 use structopt::*;
 
 // Workspace uses
-use ya_core_model::{identity as id_api, payment::local as pay};
+use ya_core_model::{driver::ExitConfirmation, identity as id_api, payment::local as pay};
 use ya_service_api::{CliCtx, CommandOutput, ResponseTable};
 use ya_service_bus::{typed as bus, RpcEndpoint};
 
@@ -33,6 +33,11 @@ pub enum PaymentCli {
         sender: bool,
         #[structopt(long, help = "Initialize account for receiving")]
         receiver: bool,
+        #[structopt(
+            long = "fee-token",
+            help = "Token to pay the account unlock fee with. Repeat to give more in order of preference"
+        )]
+        fee_tokens: Vec<String>,
     },
 
     /// Display account balance and a summary of sent/received payments
@@ -59,6 +64,18 @@ pub enum PaymentCli {
         to_address: Option<String>,
         #[structopt(long, help = "Optional amount to exit [default: <ALL_FUNDS>]")]
         amount: Option<String>,
+        #[structopt(
+            long,
+            conflicts_with = "amount",
+            parse(try_from_str = parse_percent),
+            help = "Optional percentage of funds to exit, between 1 and 100 [default: 100]"
+        )]
+        percent: Option<u8>,
+        #[structopt(
+            long,
+            help = "Wait until withdrawal is finalized on Ethereum instead of only committed"
+        )]
+        wait_for_verify: bool,
     },
 
     // TODO: Uncomment when operation is supported by drivers
@@ -105,12 +122,14 @@ impl PaymentCli {
                 account,
                 sender,
                 receiver,
+                fee_tokens,
             } => {
                 let account = Account {
                     driver: account.driver(),
                     address: resolve_address(account.address()).await?,
                     network: Some(account.network()),
                     token: None, // Use default -- we don't yet support other tokens than GLM
+                    fee_tokens,
                     send: sender,
                     receive: receiver,
                 };
@@ -127,9 +146,25 @@ impl PaymentCli {
                         token: None,
                     })
                     .await??;
+                let health = wallet::health(account.driver(), Some(account.network())).await;
                 if ctx.json_output {
-                    return CommandOutput::object(status);
+                    let mut output = serde_json::to_value(status)?;
+                    output["driverHealth"] = match health {
+                        Ok(health) => serde_json::to_value(health)?,
+                        Err(e) => serde_json::json!({ "reachable": false, "error": e.to_string() }),
+                    };
+                    return CommandOutput::object(output);
                 }
+                let health = match health {
+                    Ok(health) if health.reachable => {
+                        format!("reachable ({} ms)", health.latency_ms)
+                    }
+                    Ok(health) => format!(
+                        "unreachable ({})",
+                        health.error.unwrap_or_else(|| "unknown error".to_string())
+                    ),
+                    Err(e) => format!("unknown ({})", e),
+                };
 
                 Ok(ResponseTable {
                     columns: vec![
@@ -167,7 +202,10 @@ impl PaymentCli {
                         ]},
                     ],
                 }
-                .with_header(format!("\nStatus for account: {}\n", address)))
+                .with_header(format!(
+                    "\nStatus for account: {}\nDriver health: {}\n",
+                    address, health
+                )))
             }
             PaymentCli::Accounts => {
                 let accounts = bus::service(pay::BUS_ID)
@@ -232,16 +270,24 @@ impl PaymentCli {
                 account,
                 to_address,
                 amount,
+                percent,
+                wait_for_verify,
             } => {
                 let amount = match amount {
                     None => None,
                     Some(a) => Some(BigDecimal::from_str(&a)?),
                 };
+                let confirmation = match wait_for_verify {
+                    true => ExitConfirmation::Verify,
+                    false => ExitConfirmation::Commit,
+                };
                 CommandOutput::object(
                     wallet::exit(
                         resolve_address(account.address()).await?,
                         to_address,
                         amount,
+                        percent,
+                        confirmation,
                         account.driver(),
                         Some(account.network()),
                         None,
@@ -316,3 +362,10 @@ async fn resolve_address(address: Option<String>) -> anyhow::Result<String> {
 
     anyhow::bail!("Default identity not found")
 }
+
+fn parse_percent(s: &str) -> anyhow::Result<u8> {
+    match s.parse::<u8>()? {
+        percent @ 1..=100 => Ok(percent),
+        percent => anyhow::bail!("Percentage should be between 1 and 100, got {}", percent),
+    }
+}
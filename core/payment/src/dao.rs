@@ -7,6 +7,9 @@ mod invoice;
 mod invoice_event;
 mod order;
 mod payment;
+mod payment_event;
+#[cfg(test)]
+mod test_utils;
 
 pub use self::activity::ActivityDao;
 pub use self::agreement::AgreementDao;
@@ -17,3 +20,4 @@ pub use self::invoice::InvoiceDao;
 pub use self::invoice_event::InvoiceEventDao;
 pub use self::order::OrderDao;
 pub use self::payment::PaymentDao;
+pub use self::payment_event::{PaymentEvent, PaymentEventDao};
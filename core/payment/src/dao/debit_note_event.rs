@@ -1,17 +1,20 @@
 use crate::error::DbResult;
 use crate::models::debit_note_event::{ReadObj, WriteObj};
+use crate::schema::pay_debit_note::dsl as debit_note_dsl;
 use crate::schema::pay_debit_note_event::dsl as write_dsl;
 use crate::schema::pay_debit_note_event_read::dsl as read_dsl;
 use chrono::NaiveDateTime;
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
 use serde::Serialize;
 use std::convert::TryInto;
-use ya_client_model::payment::{DebitNoteEvent, DebitNoteEventType};
+use ya_client_model::payment::{DebitNoteEvent, DebitNoteEventType, DocumentStatus};
 use ya_client_model::NodeId;
 use ya_persistence::executor::{
     do_with_transaction, readonly_transaction, AsDao, ConnType, PoolType,
 };
 
+/// Idempotent, event of the same type already stored for the debit note
+/// is kept and the new one is ignored. This way retried writes don't fail.
 pub fn create<T: Serialize>(
     debit_note_id: String,
     owner_id: NodeId,
@@ -20,9 +23,16 @@ pub fn create<T: Serialize>(
     conn: &ConnType,
 ) -> DbResult<()> {
     let event = WriteObj::new(debit_note_id, owner_id, event_type, details)?;
-    diesel::insert_into(write_dsl::pay_debit_note_event)
-        .values(event)
+    let num_inserted = diesel::insert_or_ignore_into(write_dsl::pay_debit_note_event)
+        .values(&event)
         .execute(conn)?;
+    if num_inserted == 0 {
+        log::debug!(
+            "DebitNoteEvent `{}` of debit note [{}] already exists",
+            event.event_type,
+            event.debit_note_id
+        );
+    }
     Ok(())
 }
 
@@ -50,6 +60,18 @@ impl<'c> DebitNoteEventDao<'c> {
         .await
     }
 
+    /// Inserts all events in single transaction. If any of them already
+    /// exists, none of them is inserted.
+    pub async fn create_events(&self, events: Vec<WriteObj>) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let num_inserted = diesel::insert_into(write_dsl::pay_debit_note_event)
+                .values(&events)
+                .execute(conn)?;
+            Ok(num_inserted)
+        })
+        .await
+    }
+
     pub async fn get_for_node_id(
         &self,
         node_id: NodeId,
@@ -58,22 +80,655 @@ impl<'c> DebitNoteEventDao<'c> {
         app_session_id: Option<String>,
     ) -> DbResult<Vec<DebitNoteEvent>> {
         readonly_transaction(self.pool, move |conn| {
+            let events =
+                load_for_node_id(conn, node_id, after_timestamp, max_events, app_session_id)?;
+            into_events(events)
+        })
+        .await
+    }
+
+    /// Like `get_for_node_id`, but events which can't be converted are logged
+    /// and skipped instead of failing the whole query.
+    pub async fn get_for_node_id_lenient(
+        &self,
+        node_id: NodeId,
+        after_timestamp: Option<NaiveDateTime>,
+        max_events: Option<u32>,
+        app_session_id: Option<String>,
+    ) -> DbResult<Vec<DebitNoteEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let events =
+                load_for_node_id(conn, node_id, after_timestamp, max_events, app_session_id)?;
+            Ok(into_events_lenient(events))
+        })
+        .await
+    }
+
+    /// Like `get_for_node_id`, but only events of given types are loaded.
+    /// Types are matched by the same string representation, which
+    /// `WriteObj::new` stores.
+    pub async fn get_for_event_types(
+        &self,
+        node_id: NodeId,
+        event_types: Vec<DebitNoteEventType>,
+        after_timestamp: Option<NaiveDateTime>,
+        max_events: Option<u32>,
+    ) -> DbResult<Vec<DebitNoteEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let event_types = event_types
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
             let mut query = read_dsl::pay_debit_note_event_read
                 .filter(read_dsl::owner_id.eq(node_id))
+                .filter(read_dsl::event_type.eq_any(event_types))
                 .order_by(read_dsl::timestamp.asc())
                 .into_boxed();
             if let Some(timestamp) = after_timestamp {
                 query = query.filter(read_dsl::timestamp.gt(timestamp));
             }
-            if let Some(app_session_id) = app_session_id {
-                query = query.filter(read_dsl::app_session_id.eq(app_session_id));
-            }
             if let Some(limit) = max_events {
                 query = query.limit(limit.into());
             }
             let events: Vec<ReadObj> = query.load(conn)?;
-            events.into_iter().map(TryInto::try_into).collect()
+            into_events(events)
+        })
+        .await
+    }
+
+    /// Returns events in stable `(timestamp, debit_note_id, event_type)` order.
+    /// Primary key of the last received event should be passed as `after_event`
+    /// together with its timestamp, so events sharing the same timestamp
+    /// are neither skipped nor duplicated between pages.
+    pub async fn get_debit_note_events_after(
+        &self,
+        owner_id: NodeId,
+        after_timestamp: Option<NaiveDateTime>,
+        after_event: Option<(String, DebitNoteEventType)>,
+        limit: Option<u32>,
+    ) -> DbResult<Vec<DebitNoteEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut query = read_dsl::pay_debit_note_event_read
+                .filter(read_dsl::owner_id.eq(owner_id))
+                .order_by((
+                    read_dsl::timestamp.asc(),
+                    read_dsl::debit_note_id.asc(),
+                    read_dsl::event_type.asc(),
+                ))
+                .into_boxed();
+            match (after_timestamp, after_event) {
+                (Some(timestamp), Some((debit_note_id, event_type))) => {
+                    let same_key_after = read_dsl::debit_note_id.gt(debit_note_id.clone()).or(
+                        read_dsl::debit_note_id
+                            .eq(debit_note_id)
+                            .and(read_dsl::event_type.gt(event_type.to_string())),
+                    );
+                    query = query.filter(
+                        read_dsl::timestamp
+                            .gt(timestamp)
+                            .or(read_dsl::timestamp.eq(timestamp).and(same_key_after)),
+                    );
+                }
+                (Some(timestamp), None) => {
+                    query = query.filter(read_dsl::timestamp.gt(timestamp));
+                }
+                (None, _) => {}
+            }
+            if let Some(limit) = limit {
+                query = query.limit(limit.into());
+            }
+            let events: Vec<ReadObj> = query.load(conn)?;
+            into_events(events)
+        })
+        .await
+    }
+
+    /// Returns events of all owners in timestamp order. Intended for
+    /// operator tools, which aggregate events of every identity on the node.
+    pub async fn get_all_debit_note_events(
+        &self,
+        event_type: Option<DebitNoteEventType>,
+        after_timestamp: Option<NaiveDateTime>,
+        limit: Option<u32>,
+    ) -> DbResult<Vec<DebitNoteEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut query = read_dsl::pay_debit_note_event_read
+                .order_by((
+                    read_dsl::timestamp.asc(),
+                    read_dsl::debit_note_id.asc(),
+                    read_dsl::event_type.asc(),
+                ))
+                .into_boxed();
+            if let Some(event_type) = event_type {
+                query = query.filter(read_dsl::event_type.eq(event_type.to_string()));
+            }
+            if let Some(timestamp) = after_timestamp {
+                query = query.filter(read_dsl::timestamp.gt(timestamp));
+            }
+            if let Some(limit) = limit {
+                query = query.limit(limit.into());
+            }
+            let events: Vec<ReadObj> = query.load(conn)?;
+            into_events(events)
+        })
+        .await
+    }
+
+    /// Deletes events of `owner_id` older than `cutoff`. Events of debit notes,
+    /// which are still open (not settled, rejected, failed nor cancelled),
+    /// are kept regardless of their age.
+    pub async fn archive_events_before(
+        &self,
+        owner_id: NodeId,
+        cutoff: NaiveDateTime,
+    ) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let closed_debit_notes = debit_note_dsl::pay_debit_note
+                .filter(debit_note_dsl::owner_id.eq(owner_id))
+                .filter(debit_note_dsl::status.eq_any(vec![
+                    DocumentStatus::Settled.to_string(),
+                    DocumentStatus::Rejected.to_string(),
+                    DocumentStatus::Failed.to_string(),
+                    DocumentStatus::Cancelled.to_string(),
+                ]))
+                .select(debit_note_dsl::id);
+            let removed = diesel::delete(
+                write_dsl::pay_debit_note_event
+                    .filter(write_dsl::owner_id.eq(owner_id))
+                    .filter(write_dsl::timestamp.lt(cutoff))
+                    .filter(write_dsl::debit_note_id.eq_any(closed_debit_notes)),
+            )
+            .execute(conn)?;
+            Ok(removed)
+        })
+        .await
+    }
+}
+
+fn load_for_node_id(
+    conn: &ConnType,
+    node_id: NodeId,
+    after_timestamp: Option<NaiveDateTime>,
+    max_events: Option<u32>,
+    app_session_id: Option<String>,
+) -> DbResult<Vec<ReadObj>> {
+    let mut query = read_dsl::pay_debit_note_event_read
+        .filter(read_dsl::owner_id.eq(node_id))
+        .order_by(read_dsl::timestamp.asc())
+        .into_boxed();
+    if let Some(timestamp) = after_timestamp {
+        query = query.filter(read_dsl::timestamp.gt(timestamp));
+    }
+    if let Some(app_session_id) = app_session_id {
+        query = query.filter(read_dsl::app_session_id.eq(app_session_id));
+    }
+    if let Some(limit) = max_events {
+        query = query.limit(limit.into());
+    }
+    Ok(query.load(conn)?)
+}
+
+fn into_events(events: Vec<ReadObj>) -> DbResult<Vec<DebitNoteEvent>> {
+    events.into_iter().map(TryInto::try_into).collect()
+}
+
+fn into_events_lenient(events: Vec<ReadObj>) -> Vec<DebitNoteEvent> {
+    events
+        .into_iter()
+        .filter_map(|event| match event.try_into() {
+            Ok(event) => Some(event),
+            Err(e) => {
+                log::warn!("Skipping corrupted debit note event: {}", e);
+                None
+            }
         })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::test_utils::{execute, init_db, insert_agreement, insert_debit_note};
+    use ya_persistence::executor::DbExecutor;
+
+    const OWNER_ID: &str = "0xbabe000000000000000000000000000000000000";
+    const OTHER_OWNER_ID: &str = "0xcafe000000000000000000000000000000000000";
+    const TIMESTAMP: &str = "2021-02-01 12:00:00.500";
+
+    /// Inserts two debit notes with two events each. All ids are prefixed
+    /// with `prefix`, so the same fixture can be inserted for many owners.
+    fn insert_debit_notes(db: &DbExecutor, owner: &str, prefix: &str) {
+        let activity_id = format!("{}activity-1", prefix);
+        insert_agreement(db, owner, &format!("{}agreement-1", prefix), &activity_id);
+        for debit_note_id in &["debit-note-1", "debit-note-2"] {
+            insert_debit_note(
+                db,
+                owner,
+                &activity_id,
+                &format!("{}{}", prefix, debit_note_id),
+                &[("RECEIVED", TIMESTAMP), ("ACCEPTED", TIMESTAMP)],
+            );
+        }
+    }
+
+    fn read_obj(debit_note_id: &str, event_type: &str) -> ReadObj {
+        ReadObj {
+            debit_note_id: debit_note_id.to_string(),
+            owner_id: OWNER_ID.parse().unwrap(),
+            event_type: event_type.to_string(),
+            timestamp: NaiveDateTime::parse_from_str(TIMESTAMP, "%Y-%m-%d %H:%M:%S%.f").unwrap(),
+            details: None,
+            app_session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_corrupted_event_type_strict() {
+        let events = vec![
+            read_obj("debit-note-1", "RECEIVED"),
+            read_obj("debit-note-2", "BOGUS"),
+        ];
+        let err = into_events(events).unwrap_err().to_string();
+        assert!(err.contains("debit-note-2"), "{}", err);
+        assert!(err.contains("BOGUS"), "{}", err);
+    }
+
+    #[test]
+    fn test_corrupted_event_type_skipped() {
+        let events = vec![
+            read_obj("debit-note-1", "RECEIVED"),
+            read_obj("debit-note-2", "BOGUS"),
+            read_obj("debit-note-3", "ACCEPTED"),
+        ];
+        let events = into_events_lenient(events);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].debit_note_id, "debit-note-1");
+        assert_eq!(events[1].debit_note_id, "debit-note-3");
+    }
+
+    #[actix_rt::test]
+    async fn test_events_after_with_colliding_timestamps() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let owner_id: NodeId = OWNER_ID.parse().unwrap();
+        let dao: DebitNoteEventDao = db.as_dao();
+
+        let first_page = dao
+            .get_debit_note_events_after(owner_id, None, None, Some(3))
+            .await
+            .unwrap();
+        assert_eq!(first_page.len(), 3);
+
+        let last = first_page.last().unwrap();
+        let second_page = dao
+            .get_debit_note_events_after(
+                owner_id,
+                Some(last.event_date.naive_utc()),
+                Some((last.debit_note_id.clone(), last.event_type.clone())),
+                Some(3),
+            )
+            .await
+            .unwrap();
+        assert_eq!(second_page.len(), 1);
+
+        let mut keys = first_page
+            .iter()
+            .chain(second_page.iter())
+            .map(|event| (event.debit_note_id.clone(), event.event_type.to_string()))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_events_after_single_event_pages() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let owner_id: NodeId = OWNER_ID.parse().unwrap();
+        let dao: DebitNoteEventDao = db.as_dao();
+
+        let mut keys = vec![];
+        let mut cursor = None;
+        loop {
+            let (after_timestamp, after_event) = match cursor.take() {
+                Some((timestamp, key)) => (Some(timestamp), Some(key)),
+                None => (None, None),
+            };
+            let page = dao
+                .get_debit_note_events_after(owner_id, after_timestamp, after_event, Some(1))
+                .await
+                .unwrap();
+            let event = match page.into_iter().next() {
+                Some(event) => event,
+                None => break,
+            };
+            keys.push((event.debit_note_id.clone(), event.event_type.to_string()));
+            cursor = Some((
+                event.event_date.naive_utc(),
+                (event.debit_note_id, event.event_type),
+            ));
+            assert!(keys.len() <= 4, "events repeated: {:?}", keys);
+        }
+
+        let expected = vec![
+            ("debit-note-1", "ACCEPTED"),
+            ("debit-note-1", "RECEIVED"),
+            ("debit-note-2", "ACCEPTED"),
+            ("debit-note-2", "RECEIVED"),
+        ]
+        .into_iter()
+        .map(|(id, event_type)| (id.to_string(), event_type.to_string()))
+        .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_all_owners_events() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "a-");
+        insert_debit_notes(&db, OTHER_OWNER_ID, "b-");
+
+        let dao: DebitNoteEventDao = db.as_dao();
+        let events = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 8);
+
+        let events = dao
+            .get_all_debit_note_events(Some(DebitNoteEventType::DebitNoteAcceptedEvent), None, None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().any(|e| e.debit_note_id.starts_with("a-")));
+        assert!(events.iter().any(|e| e.debit_note_id.starts_with("b-")));
+        assert!(events
+            .iter()
+            .all(|e| e.event_type.to_string() == "ACCEPTED"));
+    }
+
+    #[actix_rt::test]
+    async fn test_events_filtered_by_type() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+        insert_debit_notes(&db, OTHER_OWNER_ID, "other-");
+
+        let owner_id: NodeId = OWNER_ID.parse().unwrap();
+        let dao: DebitNoteEventDao = db.as_dao();
+        dao.create_events(vec![write_obj(
+            "debit-note-1",
+            DebitNoteEventType::DebitNoteSettledEvent,
+        )])
+        .await
+        .unwrap();
+
+        let events = dao
+            .get_for_event_types(
+                owner_id,
+                vec![
+                    DebitNoteEventType::DebitNoteAcceptedEvent,
+                    DebitNoteEventType::DebitNoteSettledEvent,
+                ],
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let mut keys = events
+            .iter()
+            .map(|e| (e.debit_note_id.clone(), e.event_type.to_string()))
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                ("debit-note-1".to_string(), "ACCEPTED".to_string()),
+                ("debit-note-1".to_string(), "SETTLED".to_string()),
+                ("debit-note-2".to_string(), "ACCEPTED".to_string()),
+            ]
+        );
+
+        let events = dao
+            .get_for_event_types(owner_id, vec![], None, None)
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+    }
+
+    fn write_obj(debit_note_id: &str, event_type: DebitNoteEventType) -> WriteObj {
+        WriteObj::new::<()>(
+            debit_note_id.to_string(),
+            OWNER_ID.parse().unwrap(),
+            event_type,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_create_events_batch() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let dao: DebitNoteEventDao = db.as_dao();
+        let batch = vec![
+            write_obj("debit-note-1", DebitNoteEventType::DebitNoteSettledEvent),
+            write_obj("debit-note-2", DebitNoteEventType::DebitNoteSettledEvent),
+            write_obj("debit-note-1", DebitNoteEventType::DebitNoteCancelledEvent),
+        ];
+        assert_eq!(dao.create_events(batch).await.unwrap(), 3);
+
+        let events = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 7);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_duplicate_ignored() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let dao: DebitNoteEventDao = db.as_dao();
+        for _ in 0..2 {
+            dao.create::<()>(
+                "debit-note-1".to_string(),
+                OWNER_ID.parse().unwrap(),
+                DebitNoteEventType::DebitNoteSettledEvent,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        // Already inserted by fixture.
+        dao.create::<()>(
+            "debit-note-1".to_string(),
+            OWNER_ID.parse().unwrap(),
+            DebitNoteEventType::DebitNoteReceivedEvent,
+            None,
+        )
         .await
+        .unwrap();
+
+        let events = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 5);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_events_batch_rollback() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let dao: DebitNoteEventDao = db.as_dao();
+        let batch = vec![
+            write_obj("debit-note-1", DebitNoteEventType::DebitNoteSettledEvent),
+            // Already inserted by fixture.
+            write_obj("debit-note-2", DebitNoteEventType::DebitNoteReceivedEvent),
+        ];
+        assert!(dao.create_events(batch).await.is_err());
+
+        let events = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_sub_second_timestamps_keep_order() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let ids = (0..10)
+            .map(|i| format!("rapid-debit-note-{}", i))
+            .collect::<Vec<_>>();
+        for id in ids.iter() {
+            insert_debit_note(&db, OWNER_ID, "activity-1", id, &[]);
+        }
+
+        // Timestamps are injected, so all events land in the same second,
+        // but never share the same millisecond.
+        let base = NaiveDateTime::parse_from_str(TIMESTAMP, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+        let timestamps = (0..ids.len() as i64)
+            .map(|i| base + chrono::Duration::milliseconds(i))
+            .collect::<Vec<_>>();
+        let dao: DebitNoteEventDao = db.as_dao();
+        for (id, timestamp) in ids.iter().zip(timestamps.iter()) {
+            let mut event = write_obj(id, DebitNoteEventType::DebitNoteReceivedEvent);
+            event.timestamp = *timestamp;
+            dao.create_events(vec![event]).await.unwrap();
+        }
+
+        let events = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.debit_note_id.starts_with("rapid-"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| e.debit_note_id.clone())
+                .collect::<Vec<_>>(),
+            ids
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| e.event_date.naive_utc())
+                .collect::<Vec<_>>(),
+            timestamps
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_archive_events_before() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+        insert_debit_notes(&db, OTHER_OWNER_ID, "other-");
+        execute(
+            &db,
+            "UPDATE pay_debit_note SET status = 'SETTLED' \
+             WHERE id IN ('debit-note-1', 'other-debit-note-1');",
+        );
+
+        let owner_id: NodeId = OWNER_ID.parse().unwrap();
+        let dao: DebitNoteEventDao = db.as_dao();
+        dao.create_events(vec![write_obj(
+            "debit-note-1",
+            DebitNoteEventType::DebitNoteSettledEvent,
+        )])
+        .await
+        .unwrap();
+
+        let cutoff =
+            NaiveDateTime::parse_from_str("2021-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            dao.archive_events_before(owner_id, cutoff).await.unwrap(),
+            2
+        );
+
+        let mut keys = dao
+            .get_all_debit_note_events(None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.debit_note_id, e.event_type.to_string()))
+            .collect::<Vec<_>>();
+        keys.sort();
+        let remaining = keys
+            .iter()
+            .filter(|(id, _)| !id.starts_with("other-"))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Recent event of closed debit note and old events of open one are kept.
+        assert_eq!(
+            remaining,
+            vec![
+                ("debit-note-1".to_string(), "SETTLED".to_string()),
+                ("debit-note-2".to_string(), "ACCEPTED".to_string()),
+                ("debit-note-2".to_string(), "RECEIVED".to_string()),
+            ]
+        );
+        assert_eq!(keys.len() - remaining.len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_timestamp_round_trip_is_utc() {
+        let dir = tempdir::TempDir::new("debit_note_events").unwrap();
+        let db = init_db(&dir);
+        insert_debit_notes(&db, OWNER_ID, "");
+
+        let dao: DebitNoteEventDao = db.as_dao();
+        let before = chrono::Utc::now();
+        dao.create::<()>(
+            "debit-note-1".to_string(),
+            OWNER_ID.parse().unwrap(),
+            DebitNoteEventType::DebitNoteSettledEvent,
+            None,
+        )
+        .await
+        .unwrap();
+        let after = chrono::Utc::now();
+
+        let event = dao
+            .get_for_event_types(
+                OWNER_ID.parse().unwrap(),
+                vec![DebitNoteEventType::DebitNoteSettledEvent],
+                None,
+                None,
+            )
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let tolerance = chrono::Duration::milliseconds(1);
+        assert!(
+            event.event_date >= before - tolerance,
+            "{}",
+            event.event_date
+        );
+        assert!(
+            event.event_date <= after + tolerance,
+            "{}",
+            event.event_date
+        );
     }
 }
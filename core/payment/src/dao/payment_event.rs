@@ -0,0 +1,160 @@
+use crate::error::DbResult;
+use crate::models::{debit_note_event, invoice_event};
+use crate::schema::pay_debit_note_event_read::dsl as debit_note_dsl;
+use crate::schema::pay_invoice_event_read::dsl as invoice_dsl;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::convert::TryInto;
+use ya_client_model::payment::{DebitNoteEvent, InvoiceEvent};
+use ya_client_model::NodeId;
+use ya_persistence::executor::{readonly_transaction, AsDao, PoolType};
+
+/// Debit note or invoice event, so both can be polled as single stream.
+#[derive(Clone, Debug)]
+pub enum PaymentEvent {
+    DebitNote(DebitNoteEvent),
+    Invoice(InvoiceEvent),
+}
+
+impl PaymentEvent {
+    pub fn event_date(&self) -> DateTime<Utc> {
+        match self {
+            PaymentEvent::DebitNote(event) => event.event_date,
+            PaymentEvent::Invoice(event) => event.event_date,
+        }
+    }
+}
+
+pub struct PaymentEventDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for PaymentEventDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> PaymentEventDao<'c> {
+    /// Debit note and invoice events of `node_id` merged in timestamp order.
+    pub async fn get_for_node_id(
+        &self,
+        node_id: NodeId,
+        after_timestamp: Option<NaiveDateTime>,
+        max_events: Option<u32>,
+    ) -> DbResult<Vec<PaymentEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut debit_note_query = debit_note_dsl::pay_debit_note_event_read
+                .filter(debit_note_dsl::owner_id.eq(node_id))
+                .order_by(debit_note_dsl::timestamp.asc())
+                .into_boxed();
+            let mut invoice_query = invoice_dsl::pay_invoice_event_read
+                .filter(invoice_dsl::owner_id.eq(node_id))
+                .order_by(invoice_dsl::timestamp.asc())
+                .into_boxed();
+            if let Some(timestamp) = after_timestamp {
+                debit_note_query = debit_note_query.filter(debit_note_dsl::timestamp.gt(timestamp));
+                invoice_query = invoice_query.filter(invoice_dsl::timestamp.gt(timestamp));
+            }
+            // Each of merged streams can't contribute more than `max_events`.
+            if let Some(limit) = max_events {
+                debit_note_query = debit_note_query.limit(limit.into());
+                invoice_query = invoice_query.limit(limit.into());
+            }
+
+            let debit_note_events: Vec<debit_note_event::ReadObj> = debit_note_query.load(conn)?;
+            let invoice_events: Vec<invoice_event::ReadObj> = invoice_query.load(conn)?;
+
+            let mut events = debit_note_events
+                .into_iter()
+                .map(|event| event.try_into().map(PaymentEvent::DebitNote))
+                .chain(
+                    invoice_events
+                        .into_iter()
+                        .map(|event| event.try_into().map(PaymentEvent::Invoice)),
+                )
+                .collect::<DbResult<Vec<_>>>()?;
+            // Stable sort keeps debit note events first, when timestamps are equal.
+            events.sort_by_key(PaymentEvent::event_date);
+            if let Some(limit) = max_events {
+                events.truncate(limit as usize);
+            }
+            Ok(events)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::test_utils::{insert_agreement, insert_debit_note, insert_invoice};
+    use ya_persistence::executor::DbExecutor;
+
+    const OWNER_ID: &str = "0xbabe000000000000000000000000000000000000";
+
+    fn init_db(dir: &tempdir::TempDir) -> DbExecutor {
+        let db = crate::dao::test_utils::init_db(dir);
+        insert_agreement(&db, OWNER_ID, "agreement-1", "activity-1");
+        insert_debit_note(
+            &db,
+            OWNER_ID,
+            "activity-1",
+            "debit-note-1",
+            &[
+                ("RECEIVED", "2021-02-01 12:00:01"),
+                ("ACCEPTED", "2021-02-01 12:00:03"),
+            ],
+        );
+        insert_invoice(
+            &db,
+            OWNER_ID,
+            "agreement-1",
+            "invoice-1",
+            &[
+                ("RECEIVED", "2021-02-01 12:00:02"),
+                ("ACCEPTED", "2021-02-01 12:00:04"),
+            ],
+        );
+        db
+    }
+
+    fn describe(event: &PaymentEvent) -> String {
+        match event {
+            PaymentEvent::DebitNote(e) => format!("{} {}", e.debit_note_id, e.event_type),
+            PaymentEvent::Invoice(e) => format!("{} {}", e.invoice_id, e.event_type),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_merged_ordering() {
+        let dir = tempdir::TempDir::new("payment_events").unwrap();
+        let db = init_db(&dir);
+        let dao: PaymentEventDao = db.as_dao();
+        let owner_id: NodeId = OWNER_ID.parse().unwrap();
+
+        let events = dao.get_for_node_id(owner_id, None, None).await.unwrap();
+        assert_eq!(
+            events.iter().map(describe).collect::<Vec<_>>(),
+            vec![
+                "debit-note-1 RECEIVED",
+                "invoice-1 RECEIVED",
+                "debit-note-1 ACCEPTED",
+                "invoice-1 ACCEPTED",
+            ]
+        );
+        assert!(events
+            .windows(2)
+            .all(|pair| pair[0].event_date() <= pair[1].event_date()));
+
+        let after = events[1].event_date().naive_utc();
+        let events = dao
+            .get_for_node_id(owner_id, Some(after), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            events.iter().map(describe).collect::<Vec<_>>(),
+            vec!["debit-note-1 ACCEPTED"]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Raw SQL fixtures shared by DAO tests. Rows are inserted directly, so tests
+//! control event timestamps and don't depend on other DAOs.
+
+use diesel::connection::SimpleConnection;
+use ya_persistence::executor::DbExecutor;
+
+pub fn init_db(dir: &tempdir::TempDir) -> DbExecutor {
+    let db = DbExecutor::from_data_dir(dir.path(), "payment").unwrap();
+    db.apply_migration(crate::migrations::run_with_output)
+        .unwrap();
+    db
+}
+
+/// Inserts Requestor's Agreement with single Activity.
+pub fn insert_agreement(db: &DbExecutor, owner: &str, agreement_id: &str, activity_id: &str) {
+    execute(
+        db,
+        format!(
+            r#"
+            INSERT INTO pay_agreement(id, owner_id, role, peer_id, payee_addr, payer_addr,
+                payment_platform, total_amount_due, total_amount_accepted,
+                total_amount_scheduled, total_amount_paid)
+            VALUES ('{agreement}', '{owner}', 'R', '{owner}', '0x01', '0x02',
+                'dummy-glm', '0', '0', '0', '0');
+            INSERT INTO pay_activity(id, owner_id, role, agreement_id, total_amount_due,
+                total_amount_accepted, total_amount_scheduled, total_amount_paid)
+            VALUES ('{activity}', '{owner}', 'R', '{agreement}', '0', '0', '0', '0');
+            "#,
+            agreement = agreement_id,
+            activity = activity_id,
+            owner = owner,
+        ),
+    );
+}
+
+/// Inserts received debit note together with `events` given as
+/// `(event_type, timestamp)` pairs.
+pub fn insert_debit_note(
+    db: &DbExecutor,
+    owner: &str,
+    activity_id: &str,
+    debit_note_id: &str,
+    events: &[(&str, &str)],
+) {
+    let mut sql = format!(
+        "INSERT INTO pay_debit_note(id, owner_id, role, activity_id, status, total_amount_due) \
+         VALUES ('{}', '{}', 'R', '{}', 'RECEIVED', '1');",
+        debit_note_id, owner, activity_id
+    );
+    for (event_type, timestamp) in events {
+        sql.push_str(&format!(
+            "INSERT INTO pay_debit_note_event(debit_note_id, owner_id, event_type, timestamp) \
+             VALUES ('{}', '{}', '{}', '{}');",
+            debit_note_id, owner, event_type, timestamp
+        ));
+    }
+    execute(db, sql);
+}
+
+/// Inserts received invoice together with `events` given as
+/// `(event_type, timestamp)` pairs.
+pub fn insert_invoice(
+    db: &DbExecutor,
+    owner: &str,
+    agreement_id: &str,
+    invoice_id: &str,
+    events: &[(&str, &str)],
+) {
+    let mut sql = format!(
+        "INSERT INTO pay_invoice(id, owner_id, role, agreement_id, status, amount, \
+         payment_due_date) \
+         VALUES ('{}', '{}', 'R', '{}', 'RECEIVED', '1', '2021-02-02 12:00:00');",
+        invoice_id, owner, agreement_id
+    );
+    for (event_type, timestamp) in events {
+        sql.push_str(&format!(
+            "INSERT INTO pay_invoice_event(invoice_id, owner_id, event_type, timestamp) \
+             VALUES ('{}', '{}', '{}', '{}');",
+            invoice_id, owner, event_type, timestamp
+        ));
+    }
+    execute(db, sql);
+}
+
+pub fn execute(db: &DbExecutor, sql: impl AsRef<str>) {
+    db.conn().unwrap().batch_execute(sql.as_ref()).unwrap();
+}
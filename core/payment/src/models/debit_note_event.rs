@@ -2,11 +2,35 @@ use crate::error::{DbError, DbResult};
 use crate::schema::{pay_debit_note_event, pay_debit_note_event_read};
 use crate::utils::{json_from_str, json_to_string};
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
-use ya_client_model::payment::{DebitNoteEvent, DebitNoteEventType};
+use ya_client_model::payment::{DebitNoteEvent, DebitNoteEventType, Rejection};
 use ya_client_model::NodeId;
 
+/// Structured payload stored in `details` column of debit note events.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DebitNoteEventDetails {
+    Rejected {
+        rejection: Rejection,
+    },
+    /// Provider voided debit note before it was accepted.
+    Cancelled {
+        reason: Option<String>,
+    },
+}
+
+impl DebitNoteEventDetails {
+    /// Whether details can be attached to event of given type.
+    fn matches(&self, event_type: &DebitNoteEventType) -> bool {
+        match (self, event_type) {
+            (Self::Rejected { .. }, DebitNoteEventType::DebitNoteRejectedEvent { .. }) => true,
+            (Self::Cancelled { .. }, DebitNoteEventType::DebitNoteCancelledEvent) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Identifiable, Insertable)]
 #[table_name = "pay_debit_note_event"]
 #[primary_key(debit_note_id, event_type)]
@@ -14,27 +38,94 @@ pub struct WriteObj {
     pub debit_note_id: String,
     pub owner_id: NodeId,
     pub event_type: String,
+    /// Set explicitly instead of relying on DB default, which has only
+    /// seconds precision in SQLite.
+    pub timestamp: NaiveDateTime,
     pub details: Option<String>,
 }
 
 impl WriteObj {
+    /// Rejection events must carry details, cancellation events can have them
+    /// and other events can't.
     pub fn new<T: Serialize>(
         debit_note_id: String,
         owner_id: NodeId,
         event_type: DebitNoteEventType,
         details: Option<T>,
     ) -> DbResult<Self> {
+        let requires_details = match event_type {
+            DebitNoteEventType::DebitNoteRejectedEvent { .. } => Some(true),
+            DebitNoteEventType::DebitNoteCancelledEvent => None,
+            _ => Some(false),
+        };
+        match (requires_details, details.is_some()) {
+            (Some(true), false) => {
+                return Err(DbError::Integrity(format!(
+                    "DebitNoteEvent `{}` of debit note [{}] requires details",
+                    event_type, debit_note_id
+                )))
+            }
+            (Some(false), true) => {
+                return Err(DbError::Integrity(format!(
+                    "DebitNoteEvent `{}` of debit note [{}] can't have details",
+                    event_type, debit_note_id
+                )))
+            }
+            _ => (),
+        }
+
         let details = match details {
-            Some(details) => Some(json_to_string(&details)?),
+            Some(details) => {
+                let details = json_to_string(&details)?;
+                let valid = json_from_str::<DebitNoteEventDetails>(&details)
+                    .map(|parsed| parsed.matches(&event_type))
+                    .unwrap_or(false);
+                if !valid {
+                    return Err(DbError::Integrity(format!(
+                        "DebitNoteEvent `{}` of debit note [{}] has invalid details: {}",
+                        event_type, debit_note_id, details
+                    )));
+                }
+                Some(details)
+            }
             None => None,
         };
         Ok(Self {
             debit_note_id,
             owner_id,
             event_type: event_type.to_string(),
+            timestamp: Utc::now().naive_utc(),
             details,
         })
     }
+
+    pub fn rejected(
+        debit_note_id: String,
+        owner_id: NodeId,
+        rejection: Rejection,
+    ) -> DbResult<Self> {
+        Self::new(
+            debit_note_id,
+            owner_id,
+            DebitNoteEventType::DebitNoteRejectedEvent {
+                rejection: rejection.clone(),
+            },
+            Some(DebitNoteEventDetails::Rejected { rejection }),
+        )
+    }
+
+    pub fn cancelled(
+        debit_note_id: String,
+        owner_id: NodeId,
+        reason: Option<String>,
+    ) -> DbResult<Self> {
+        Self::new(
+            debit_note_id,
+            owner_id,
+            DebitNoteEventType::DebitNoteCancelledEvent,
+            Some(DebitNoteEventDetails::Cancelled { reason }),
+        )
+    }
 }
 
 #[derive(Queryable, Debug, Identifiable)]
@@ -49,21 +140,78 @@ pub struct ReadObj {
     pub app_session_id: Option<String>,
 }
 
+impl ReadObj {
+    pub fn details(&self) -> DbResult<Option<DebitNoteEventDetails>> {
+        match &self.details {
+            Some(s) => json_from_str(s).map(Some).map_err(|e| {
+                DbError::Integrity(format!(
+                    "DebitNoteEvent `{}` of debit note [{}] has malformed details `{}`: {}",
+                    &self.event_type, &self.debit_note_id, s, e
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// `None` for events other than rejection.
+    pub fn rejection(&self) -> DbResult<Option<Rejection>> {
+        if !matches!(
+            self.event_type()?,
+            DebitNoteEventType::DebitNoteRejectedEvent { .. }
+        ) {
+            return Ok(None);
+        }
+        match self.details()? {
+            Some(DebitNoteEventDetails::Rejected { rejection }) => Ok(Some(rejection)),
+            _ => Err(self.unexpected_details()),
+        }
+    }
+
+    /// `None` for events other than cancellation and for cancellations
+    /// without reason.
+    pub fn cancellation_reason(&self) -> DbResult<Option<String>> {
+        if !matches!(
+            self.event_type()?,
+            DebitNoteEventType::DebitNoteCancelledEvent
+        ) {
+            return Ok(None);
+        }
+        match self.details()? {
+            Some(DebitNoteEventDetails::Cancelled { reason }) => Ok(reason),
+            None => Ok(None),
+            _ => Err(self.unexpected_details()),
+        }
+    }
+
+    /// Rejection parsed from its name lacks details, see `TryFrom<ReadObj>`.
+    fn event_type(&self) -> DbResult<DebitNoteEventType> {
+        self.event_type.parse().map_err(|e| {
+            DbError::Integrity(format!(
+                "DebitNoteEvent type `{}` of debit note [{}] parsing failed: {}",
+                &self.event_type, &self.debit_note_id, e
+            ))
+        })
+    }
+
+    fn unexpected_details(&self) -> DbError {
+        DbError::Integrity(format!(
+            "DebitNoteEvent `{}` of debit note [{}] has unexpected details `{}`",
+            &self.event_type,
+            &self.debit_note_id,
+            self.details.as_deref().unwrap_or_default()
+        ))
+    }
+}
+
 impl TryFrom<ReadObj> for DebitNoteEvent {
     type Error = DbError;
 
     fn try_from(event: ReadObj) -> DbResult<Self> {
-        let event_type = event.event_type.parse().map_err(|e| {
-            DbError::Integrity(format!(
-                "DebitNoteEvent type `{}` parsing failed: {}",
-                &event.event_type, e
-            ))
-        })?;
-        // TODO Attach details when event_type=REJECTED
-        let _details = match event.details {
-            Some(s) => Some(json_from_str(&s)?),
-            None => None,
+        let event_type = match event.rejection()? {
+            Some(rejection) => DebitNoteEventType::DebitNoteRejectedEvent { rejection },
+            None => event.event_type()?,
         };
+        event.cancellation_reason()?;
         Ok(Self {
             debit_note_id: event.debit_note_id,
             event_date: Utc.from_utc_datetime(&event.timestamp),
@@ -71,3 +219,216 @@ impl TryFrom<ReadObj> for DebitNoteEvent {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use ya_client_model::payment::RejectionReason;
+
+    fn rejection(message: &str) -> Rejection {
+        Rejection {
+            rejection_reason: RejectionReason::IncorrectAmount,
+            total_amount_accepted: BigDecimal::from(0),
+            message: Some(message.to_string()),
+        }
+    }
+
+    fn rejected_type() -> DebitNoteEventType {
+        DebitNoteEventType::DebitNoteRejectedEvent {
+            rejection: rejection("Too expensive"),
+        }
+    }
+
+    #[test]
+    fn test_rejection_details_round_trip() {
+        let write = WriteObj::rejected(
+            "debit-note-1".to_string(),
+            "0xbabe000000000000000000000000000000000000"
+                .parse()
+                .unwrap(),
+            rejection("Too expensive"),
+        )
+        .unwrap();
+
+        let read = ReadObj {
+            debit_note_id: write.debit_note_id,
+            owner_id: write.owner_id,
+            event_type: write.event_type,
+            timestamp: write.timestamp,
+            details: write.details,
+            app_session_id: None,
+        };
+        assert_eq!(read.rejection().unwrap(), Some(rejection("Too expensive")));
+
+        let event = DebitNoteEvent::try_from(read).unwrap();
+        assert_eq!(event.debit_note_id, "debit-note-1");
+        match event.event_type {
+            DebitNoteEventType::DebitNoteRejectedEvent { rejection: typed } => {
+                assert_eq!(typed, rejection("Too expensive"))
+            }
+            other => panic!("Expected DebitNoteRejectedEvent, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cancellation_round_trip() {
+        let write = WriteObj::cancelled(
+            "debit-note-1".to_string(),
+            "0xbabe000000000000000000000000000000000000"
+                .parse()
+                .unwrap(),
+            Some("Service terminated".to_string()),
+        )
+        .unwrap();
+
+        let read = ReadObj {
+            debit_note_id: write.debit_note_id,
+            owner_id: write.owner_id,
+            event_type: write.event_type,
+            timestamp: write.timestamp,
+            details: write.details,
+            app_session_id: None,
+        };
+        assert_eq!(
+            read.details().unwrap(),
+            Some(DebitNoteEventDetails::Cancelled {
+                reason: Some("Service terminated".to_string())
+            })
+        );
+
+        let event = DebitNoteEvent::try_from(read).unwrap();
+        assert_eq!(
+            event.event_type.to_string(),
+            DebitNoteEventType::DebitNoteCancelledEvent.to_string()
+        );
+    }
+
+    #[test]
+    fn test_details_presence_validation() {
+        let owner_id: NodeId = "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap();
+        let details = || {
+            Some(DebitNoteEventDetails::Rejected {
+                rejection: rejection("Bad service"),
+            })
+        };
+        let new = |event_type, details| {
+            WriteObj::new("debit-note-1".to_string(), owner_id, event_type, details)
+        };
+
+        assert!(new(rejected_type(), details()).is_ok());
+        assert!(new(rejected_type(), None).is_err());
+        assert!(new(DebitNoteEventType::DebitNoteReceivedEvent, None).is_ok());
+        assert!(new(DebitNoteEventType::DebitNoteReceivedEvent, details()).is_err());
+        assert!(new(DebitNoteEventType::DebitNoteAcceptedEvent, None).is_ok());
+        assert!(new(DebitNoteEventType::DebitNoteAcceptedEvent, details()).is_err());
+        assert!(new(DebitNoteEventType::DebitNoteSettledEvent, None).is_ok());
+        assert!(new(DebitNoteEventType::DebitNoteSettledEvent, details()).is_err());
+        assert!(new(DebitNoteEventType::DebitNoteCancelledEvent, None).is_ok());
+    }
+
+    #[test]
+    fn test_details_shape_validation() {
+        let owner_id: NodeId = "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap();
+        let new = |event_type, details| {
+            WriteObj::new(
+                "debit-note-1".to_string(),
+                owner_id,
+                event_type,
+                Some(details),
+            )
+        };
+
+        let err = new(
+            rejected_type(),
+            serde_json::json!({ "reason": "not a rejection" }),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("debit-note-1"), "{}", err);
+
+        let cancelled =
+            serde_json::to_value(DebitNoteEventDetails::Cancelled { reason: None }).unwrap();
+        assert!(new(rejected_type(), cancelled.clone()).is_err());
+        assert!(new(DebitNoteEventType::DebitNoteCancelledEvent, cancelled).is_ok());
+    }
+
+    fn corrupted(event_type: &str, details: Option<&str>) -> ReadObj {
+        ReadObj {
+            debit_note_id: "debit-note-1".to_string(),
+            owner_id: "0xbabe000000000000000000000000000000000000"
+                .parse()
+                .unwrap(),
+            event_type: event_type.to_string(),
+            timestamp: Utc::now().naive_utc(),
+            details: details.map(ToString::to_string),
+            app_session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_corrupted_event_type() {
+        let err = DebitNoteEvent::try_from(corrupted("NOT_AN_EVENT", None))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("debit-note-1"), "{}", err);
+        assert!(err.contains("NOT_AN_EVENT"), "{}", err);
+    }
+
+    #[test]
+    fn test_corrupted_details() {
+        let err = DebitNoteEvent::try_from(corrupted("REJECTED", Some("{not json")))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("debit-note-1"), "{}", err);
+        assert!(err.contains("{not json"), "{}", err);
+    }
+
+    #[test]
+    fn test_typed_rejection_details() {
+        let details = json_to_string(&DebitNoteEventDetails::Rejected {
+            rejection: rejection("Too expensive"),
+        })
+        .unwrap();
+        let rejected = corrupted("REJECTED", Some(&details));
+        assert_eq!(
+            rejected.rejection().unwrap(),
+            Some(rejection("Too expensive"))
+        );
+        assert_eq!(rejected.cancellation_reason().unwrap(), None);
+    }
+
+    #[test]
+    fn test_typed_cancellation_details() {
+        let cancelled = corrupted("CANCELLED", Some(r#"{"type":"cancelled","reason":"Done"}"#));
+        assert_eq!(
+            cancelled.cancellation_reason().unwrap(),
+            Some("Done".to_string())
+        );
+        assert_eq!(cancelled.rejection().unwrap(), None);
+
+        let received = corrupted("RECEIVED", None);
+        assert_eq!(received.rejection().unwrap(), None);
+        assert_eq!(received.cancellation_reason().unwrap(), None);
+    }
+
+    #[test]
+    fn test_typed_details_malformed() {
+        let err = corrupted("REJECTED", Some("{not json"))
+            .rejection()
+            .unwrap_err();
+        assert!(err.to_string().contains("debit-note-1"), "{}", err);
+
+        // Well-formed details of other event type.
+        let err = corrupted("REJECTED", Some(r#"{"type":"cancelled","reason":null}"#))
+            .rejection()
+            .unwrap_err();
+        assert!(err.to_string().contains("unexpected details"), "{}", err);
+
+        let err = corrupted("REJECTED", None).rejection().unwrap_err();
+        assert!(err.to_string().contains("debit-note-1"), "{}", err);
+    }
+}
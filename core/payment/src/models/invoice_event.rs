@@ -2,11 +2,35 @@ use crate::error::{DbError, DbResult};
 use crate::schema::{pay_invoice_event, pay_invoice_event_read};
 use crate::utils::{json_from_str, json_to_string};
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
-use ya_client_model::payment::{InvoiceEvent, InvoiceEventType};
+use ya_client_model::payment::{InvoiceEvent, InvoiceEventType, Rejection};
 use ya_client_model::NodeId;
 
+/// Structured payload stored in `details` column of invoice events.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum InvoiceEventDetails {
+    Rejected {
+        rejection: Rejection,
+    },
+    /// Provider voided invoice before it was accepted.
+    Cancelled {
+        reason: Option<String>,
+    },
+}
+
+impl InvoiceEventDetails {
+    /// Whether details can be attached to event of given type.
+    fn matches(&self, event_type: &InvoiceEventType) -> bool {
+        match (self, event_type) {
+            (Self::Rejected { .. }, InvoiceEventType::InvoiceRejectedEvent { .. }) => true,
+            (Self::Cancelled { .. }, InvoiceEventType::InvoiceCancelledEvent) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Identifiable, Insertable)]
 #[table_name = "pay_invoice_event"]
 #[primary_key(invoice_id, event_type)]
@@ -14,27 +38,90 @@ pub struct WriteObj {
     pub invoice_id: String,
     pub owner_id: NodeId,
     pub event_type: String,
+    /// Set explicitly instead of relying on DB default, which has only
+    /// seconds precision in SQLite.
+    pub timestamp: NaiveDateTime,
     pub details: Option<String>,
 }
 
 impl WriteObj {
+    /// Rejection events must carry details, cancellation events can have them
+    /// and other events can't.
     pub fn new<T: Serialize>(
         invoice_id: String,
         owner_id: NodeId,
         event_type: InvoiceEventType,
         details: Option<T>,
     ) -> DbResult<Self> {
+        let requires_details = match event_type {
+            InvoiceEventType::InvoiceRejectedEvent { .. } => Some(true),
+            InvoiceEventType::InvoiceCancelledEvent => None,
+            _ => Some(false),
+        };
+        match (requires_details, details.is_some()) {
+            (Some(true), false) => {
+                return Err(DbError::Integrity(format!(
+                    "InvoiceEvent `{}` of invoice [{}] requires details",
+                    event_type, invoice_id
+                )))
+            }
+            (Some(false), true) => {
+                return Err(DbError::Integrity(format!(
+                    "InvoiceEvent `{}` of invoice [{}] can't have details",
+                    event_type, invoice_id
+                )))
+            }
+            _ => (),
+        }
+
         let details = match details {
-            Some(details) => Some(json_to_string(&details)?),
+            Some(details) => {
+                let details = json_to_string(&details)?;
+                let valid = json_from_str::<InvoiceEventDetails>(&details)
+                    .map(|parsed| parsed.matches(&event_type))
+                    .unwrap_or(false);
+                if !valid {
+                    return Err(DbError::Integrity(format!(
+                        "InvoiceEvent `{}` of invoice [{}] has invalid details: {}",
+                        event_type, invoice_id, details
+                    )));
+                }
+                Some(details)
+            }
             None => None,
         };
         Ok(Self {
             invoice_id,
             owner_id,
             event_type: event_type.to_string(),
+            timestamp: Utc::now().naive_utc(),
             details,
         })
     }
+
+    pub fn rejected(invoice_id: String, owner_id: NodeId, rejection: Rejection) -> DbResult<Self> {
+        Self::new(
+            invoice_id,
+            owner_id,
+            InvoiceEventType::InvoiceRejectedEvent {
+                rejection: rejection.clone(),
+            },
+            Some(InvoiceEventDetails::Rejected { rejection }),
+        )
+    }
+
+    pub fn cancelled(
+        invoice_id: String,
+        owner_id: NodeId,
+        reason: Option<String>,
+    ) -> DbResult<Self> {
+        Self::new(
+            invoice_id,
+            owner_id,
+            InvoiceEventType::InvoiceCancelledEvent,
+            Some(InvoiceEventDetails::Cancelled { reason }),
+        )
+    }
 }
 
 #[derive(Queryable, Debug, Identifiable)]
@@ -49,23 +136,48 @@ pub struct ReadObj {
     pub app_session_id: Option<String>,
 }
 
-impl TryFrom<ReadObj> for InvoiceEvent {
-    type Error = DbError;
+impl ReadObj {
+    pub fn details(&self) -> DbResult<Option<InvoiceEventDetails>> {
+        match &self.details {
+            Some(s) => json_from_str(s).map(Some).map_err(|e| {
+                DbError::Integrity(format!(
+                    "InvoiceEvent `{}` of invoice [{}] has malformed details `{}`: {}",
+                    &self.event_type, &self.invoice_id, s, e
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
 
-    fn try_from(event: ReadObj) -> DbResult<Self> {
-        let event_type = event.event_type.parse().map_err(|e| {
+    /// Rejection parsed from its name lacks details, see `TryFrom<ReadObj>`.
+    fn event_type(&self) -> DbResult<InvoiceEventType> {
+        self.event_type.parse().map_err(|e| {
             DbError::Integrity(format!(
-                "InvoiceEvent type `{}` parsing failed: {}",
-                event.event_type, e
+                "InvoiceEvent type `{}` of invoice [{}] parsing failed: {}",
+                &self.event_type, &self.invoice_id, e
             ))
-        })?;
+        })
+    }
+}
 
-        // TODO Attach details when event_type=REJECTED
-        let _details = match event.details {
-            Some(s) => Some(json_from_str(&s)?),
-            None => None,
-        };
+impl TryFrom<ReadObj> for InvoiceEvent {
+    type Error = DbError;
 
+    fn try_from(event: ReadObj) -> DbResult<Self> {
+        let details = event.details()?;
+        let event_type = match (event.event_type()?, details) {
+            (
+                InvoiceEventType::InvoiceRejectedEvent { .. },
+                Some(InvoiceEventDetails::Rejected { rejection }),
+            ) => InvoiceEventType::InvoiceRejectedEvent { rejection },
+            (InvoiceEventType::InvoiceRejectedEvent { .. }, details) => {
+                return Err(DbError::Integrity(format!(
+                    "InvoiceEvent `{}` of invoice [{}] has unexpected details `{:?}`",
+                    &event.event_type, &event.invoice_id, details
+                )))
+            }
+            (event_type, _) => event_type,
+        };
         Ok(Self {
             invoice_id: event.invoice_id,
             event_date: Utc.from_utc_datetime(&event.timestamp),
@@ -73,3 +185,156 @@ impl TryFrom<ReadObj> for InvoiceEvent {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use ya_client_model::payment::RejectionReason;
+
+    fn owner_id() -> NodeId {
+        "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap()
+    }
+
+    fn into_read(write: WriteObj) -> ReadObj {
+        ReadObj {
+            invoice_id: write.invoice_id,
+            owner_id: write.owner_id,
+            event_type: write.event_type,
+            timestamp: write.timestamp,
+            details: write.details,
+            app_session_id: None,
+        }
+    }
+
+    fn rejection() -> Rejection {
+        Rejection {
+            rejection_reason: RejectionReason::UnsolicitedService,
+            total_amount_accepted: BigDecimal::from(0),
+            message: Some("Too expensive".to_string()),
+        }
+    }
+
+    fn rejected_type() -> InvoiceEventType {
+        InvoiceEventType::InvoiceRejectedEvent {
+            rejection: rejection(),
+        }
+    }
+
+    #[test]
+    fn test_rejection_details_round_trip() {
+        let write = WriteObj::rejected("invoice-1".to_string(), owner_id(), rejection()).unwrap();
+
+        let read = into_read(write);
+        assert_eq!(
+            read.details().unwrap(),
+            Some(InvoiceEventDetails::Rejected {
+                rejection: rejection()
+            })
+        );
+
+        let event = InvoiceEvent::try_from(read).unwrap();
+        assert_eq!(event.invoice_id, "invoice-1");
+        match event.event_type {
+            InvoiceEventType::InvoiceRejectedEvent { rejection: typed } => {
+                assert_eq!(typed, rejection())
+            }
+            other => panic!("Expected InvoiceRejectedEvent, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cancellation_round_trip() {
+        let write = WriteObj::cancelled(
+            "invoice-1".to_string(),
+            owner_id(),
+            Some("Agreement terminated".to_string()),
+        )
+        .unwrap();
+
+        let read = into_read(write);
+        assert_eq!(
+            read.details().unwrap(),
+            Some(InvoiceEventDetails::Cancelled {
+                reason: Some("Agreement terminated".to_string())
+            })
+        );
+
+        let event = InvoiceEvent::try_from(read).unwrap();
+        assert_eq!(
+            event.event_type.to_string(),
+            InvoiceEventType::InvoiceCancelledEvent.to_string()
+        );
+    }
+
+    #[test]
+    fn test_details_presence_validation() {
+        let details = || {
+            Some(InvoiceEventDetails::Rejected {
+                rejection: rejection(),
+            })
+        };
+        let new = |event_type, details| {
+            WriteObj::new("invoice-1".to_string(), owner_id(), event_type, details)
+        };
+
+        assert!(new(rejected_type(), details()).is_ok());
+        assert!(new(rejected_type(), None).is_err());
+        assert!(new(InvoiceEventType::InvoiceReceivedEvent, None).is_ok());
+        assert!(new(InvoiceEventType::InvoiceReceivedEvent, details()).is_err());
+        assert!(new(InvoiceEventType::InvoiceAcceptedEvent, None).is_ok());
+        assert!(new(InvoiceEventType::InvoiceAcceptedEvent, details()).is_err());
+        assert!(new(InvoiceEventType::InvoiceSettledEvent, None).is_ok());
+        assert!(new(InvoiceEventType::InvoiceSettledEvent, details()).is_err());
+        assert!(new(InvoiceEventType::InvoiceCancelledEvent, None).is_ok());
+    }
+
+    #[test]
+    fn test_corrupted_event_type() {
+        let read = ReadObj {
+            invoice_id: "invoice-1".to_string(),
+            owner_id: owner_id(),
+            event_type: "NOT_AN_EVENT".to_string(),
+            timestamp: Utc::now().naive_utc(),
+            details: None,
+            app_session_id: None,
+        };
+        let err = InvoiceEvent::try_from(read).unwrap_err().to_string();
+        assert!(err.contains("invoice-1"));
+    }
+
+    #[test]
+    fn test_details_shape_validation() {
+        let owner_id: NodeId = "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap();
+        let new = |event_type, details| {
+            WriteObj::new("invoice-1".to_string(), owner_id, event_type, Some(details))
+        };
+
+        let err = new(
+            rejected_type(),
+            serde_json::json!({ "reason": "not a rejection" }),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invoice-1"), "{}", err);
+
+        let cancelled =
+            serde_json::to_value(InvoiceEventDetails::Cancelled { reason: None }).unwrap();
+        assert!(new(rejected_type(), cancelled.clone()).is_err());
+        assert!(new(InvoiceEventType::InvoiceCancelledEvent, cancelled).is_ok());
+    }
+
+    #[test]
+    fn test_corrupted_details() {
+        let read = ReadObj {
+            details: Some("{not json".to_string()),
+            ..into_read(WriteObj::cancelled("invoice-1".to_string(), owner_id(), None).unwrap())
+        };
+        let err = InvoiceEvent::try_from(read).unwrap_err().to_string();
+        assert!(err.contains("invoice-1"), "{}", err);
+        assert!(err.contains("{not json"), "{}", err);
+    }
+}
@@ -2,7 +2,9 @@
 use bigdecimal::BigDecimal;
 
 // Workspace uses
-use ya_core_model::driver::{driver_bus_id, Enter, Exit, Fund, Transfer};
+use ya_core_model::driver::{
+    driver_bus_id, DriverHealth, Enter, Exit, ExitConfirmation, Fund, HealthStatus, Transfer,
+};
 use ya_service_bus::typed as bus;
 
 pub async fn fund(
@@ -34,12 +36,16 @@ pub async fn exit(
     sender: String,
     to: Option<String>,
     amount: Option<BigDecimal>,
+    percent: Option<u8>,
+    confirmation: ExitConfirmation,
     driver: String,
     network: Option<String>,
     token: Option<String>,
 ) -> anyhow::Result<String> {
     let driver_id = driver_bus_id(driver);
-    let message = Exit::new(sender, to, amount, network, token);
+    let message = Exit::new(sender, to, amount, network, token)
+        .with_percent(percent)
+        .with_confirmation(confirmation);
     let tx_id = bus::service(driver_id).call(message).await??;
     Ok(tx_id)
 }
@@ -57,3 +63,10 @@ pub async fn transfer(
     let tx_id = bus::service(driver_id).call(message).await??;
     Ok(tx_id)
 }
+
+pub async fn health(driver: String, network: Option<String>) -> anyhow::Result<HealthStatus> {
+    let driver_id = driver_bus_id(driver);
+    let message = DriverHealth::new(network);
+    let status = bus::service(driver_id).call(message).await??;
+    Ok(status)
+}
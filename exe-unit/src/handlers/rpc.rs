@@ -223,7 +223,7 @@ impl<R: Runtime> Handler<RpcEnvelope<sgx::CallEncryptedService>> for ExeUnit<R>
             let batch_id = request.batch_id;
             let timeout = request.timeout;
 
-            Ok(match request.command {
+            Ok::<_, SgxFailure>(match request.command {
                 RequestCommand::Exec { exe_script } => {
                     let msg = Exec {
                         activity_id,
@@ -236,8 +236,8 @@ impl<R: Runtime> Handler<RpcEnvelope<sgx::CallEncryptedService>> for ExeUnit<R>
                             .await
                             .map_err(|_| {
                                 SgxMessageError::Service("fatal: exe-unit disconnected".to_string())
-                            })?
-                            .map_err(rpc_to_sgx_error),
+                            })
+                            .map(sgx_result)??,
                     )
                 }
                 RequestCommand::GetExecBatchResults { command_index } => {
@@ -252,8 +252,8 @@ impl<R: Runtime> Handler<RpcEnvelope<sgx::CallEncryptedService>> for ExeUnit<R>
                             .await
                             .map_err(|_e| {
                                 SgxMessageError::Service("fatal: exe-unit disconnected".to_string())
-                            })?
-                            .map_err(rpc_to_sgx_error),
+                            })
+                            .map(sgx_result)??,
                     )
                 }
                 RequestCommand::GetRunningCommand => {
@@ -266,15 +266,16 @@ impl<R: Runtime> Handler<RpcEnvelope<sgx::CallEncryptedService>> for ExeUnit<R>
                             .await
                             .map_err(|_e| {
                                 SgxMessageError::Service("fatal: exe-unit disconnected".to_string())
-                            })?
-                            .map_err(rpc_to_sgx_error),
+                            })
+                            .map(sgx_result)??,
                     )
                 }
             })
         }
         .then(move |v| {
             let response = match v {
-                Err(e) => Response::Error(e),
+                Err(SgxFailure::Plain(e)) => return future::err(e),
+                Err(SgxFailure::Encrypted(e)) => Response::Error(e),
                 Ok(v) => v,
             };
             match enc.encrypt(&response) {
@@ -290,14 +291,43 @@ impl<R: Runtime> Handler<RpcEnvelope<sgx::CallEncryptedService>> for ExeUnit<R>
 }
 
 #[cfg(feature = "sgx")]
-fn rpc_to_sgx_error(error: RpcMessageError) -> SgxMessageError {
-    match error {
+enum SgxFailure {
+    Encrypted(SgxMessageError),
+    Plain(RpcMessageError),
+}
+
+#[cfg(feature = "sgx")]
+impl From<SgxMessageError> for SgxFailure {
+    fn from(error: SgxMessageError) -> Self {
+        SgxFailure::Encrypted(error)
+    }
+}
+
+#[cfg(feature = "sgx")]
+fn sgx_result<T>(
+    result: Result<T, RpcMessageError>,
+) -> Result<Result<T, SgxMessageError>, SgxFailure> {
+    match result.map_err(rpc_to_sgx_error) {
+        Ok(value) => Ok(Ok(value)),
+        Err(SgxFailure::Encrypted(e)) => Ok(Err(e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Encrypted errors have no Conflict and TooManyRequests counterparts, so these
+/// are returned unencrypted. This way Requestor still responds with 409 or 429.
+#[cfg(feature = "sgx")]
+fn rpc_to_sgx_error(error: RpcMessageError) -> SgxFailure {
+    SgxFailure::Encrypted(match error {
         RpcMessageError::Service(m) => SgxMessageError::Service(m),
         RpcMessageError::Activity(m) => SgxMessageError::Activity(m),
         RpcMessageError::BadRequest(m) => SgxMessageError::BadRequest(m),
         RpcMessageError::UsageLimitExceeded(m) => SgxMessageError::UsageLimitExceeded(m),
         RpcMessageError::NotFound(m) => SgxMessageError::NotFound(m),
         RpcMessageError::Forbidden(m) => SgxMessageError::Forbidden(m),
-        RpcMessageError::Timeout => SgxMessageError::Timeout,
-    }
+        e @ RpcMessageError::Conflict(_) | e @ RpcMessageError::TooManyRequests(_) => {
+            return SgxFailure::Plain(e)
+        }
+        RpcMessageError::Timeout | RpcMessageError::GsbTimeout => SgxMessageError::Timeout,
+    })
 }